@@ -0,0 +1,39 @@
+/* C interface to the payments engine. See src/ffi.rs. */
+#ifndef RS_BANK_H
+#define RS_BANK_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Bank Bank;
+
+/* Creates a bank with the default policies and exact decimal amounts.
+ * Release it with bank_free. */
+Bank *bank_new(void);
+
+/* Applies len bytes of CSV, starting with a header row. Returns how many
+ * rows were processed: all of them, or those before the first malformed
+ * one, which stay applied. A chunk that cannot be parsed applies no row.
+ * If error is not NULL it is set to the error that stopped the chunk,
+ * released with bank_string_free, or to NULL. Returns -1 if bank or data is
+ * NULL. */
+int64_t bank_apply_csv_chunk(Bank *bank, const uint8_t *data, size_t len, char **error);
+
+/* Returns the account report as JSON, or NULL on failure. Release it with
+ * bank_string_free. */
+char *bank_report_json(const Bank *bank);
+
+/* Releases a string returned by bank_report_json or bank_apply_csv_chunk. */
+void bank_string_free(char *string);
+
+void bank_free(Bank *bank);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,179 @@
+//! Read-only HTTP API for inspecting a run in flight; built with the
+//! `server` feature.
+
+use crate::bank::{AccountView, TxOutcome};
+use crate::money::Money;
+use crate::sinks::{AccountUpdate, AccountUpdateSink};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+/**
+ * What the admin API reports: progress, outcome counts and the balances
+ * of every account touched so far.
+ */
+#[derive(Debug, Default, Serialize)]
+pub struct AdminState<M = Decimal> {
+    /// Transactions processed so far.
+    pub rows: usize,
+    /// Outcomes other than `Applied`, by `ErrorCode`.
+    pub codes: BTreeMap<&'static str, usize>,
+    #[serde(skip)]
+    accounts: HashMap<u16, AccountView<M>>,
+}
+
+/**
+ * The state shared between the processing thread and the API.
+ */
+pub struct SharedAdminState<M = Decimal>(Arc<Mutex<AdminState<M>>>);
+
+impl<M> Clone for SharedAdminState<M> {
+    fn clone(&self) -> Self {
+        SharedAdminState(Arc::clone(&self.0))
+    }
+}
+
+impl<M: Money> Default for SharedAdminState<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: Money> SharedAdminState<M> {
+    pub fn new() -> Self {
+        SharedAdminState(Arc::new(Mutex::new(AdminState {
+            rows: 0,
+            codes: BTreeMap::new(),
+            accounts: HashMap::new(),
+        })))
+    }
+
+    /**
+     * Counts a processed transaction; call it with every outcome.
+     */
+    pub fn record(&self, outcome: TxOutcome) {
+        let mut state = self.lock();
+        state.rows += 1;
+        if let Some(code) = outcome.code() {
+            *state.codes.entry(code.as_str()).or_default() += 1;
+        }
+    }
+
+    /**
+     * A panic while holding the lock can't leave the counters inconsistent,
+     * so a poisoned lock is used as is.
+     */
+    fn lock(&self) -> MutexGuard<'_, AdminState<M>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/**
+ * Keeps the balances in the shared state current; register it with
+ * `BankBuilder::update_sink`.
+ */
+impl<M: Money> AccountUpdateSink<M> for SharedAdminState<M> {
+    fn update(&mut self, update: &AccountUpdate<M>) -> Result<(), Box<dyn Error>> {
+        self.lock()
+            .accounts
+            .insert(update.account.client_id, update.account);
+        Ok(())
+    }
+}
+
+/**
+ * Answers requests on `listener` from a background thread:
+ *
+ * * `GET /progress`: `{"rows": …, "codes": {"E2003": …}}`
+ * * `GET /clients/<id>`: the account as in the JSON report, or 404
+ */
+pub fn serve<M: Money>(listener: TcpListener, state: SharedAdminState<M>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A failed connection only affects that client.
+            let _ = handle(stream, &state);
+        }
+    })
+}
+
+fn handle<M: Money>(stream: TcpStream, state: &SharedAdminState<M>) -> io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/progress")) => ("200 OK", to_json(&*state.lock())),
+        (Some("GET"), Some(path)) => match path.strip_prefix("/clients/") {
+            Some(id) => match id
+                .parse()
+                .ok()
+                .and_then(|id| state.lock().accounts.get(&id).copied())
+            {
+                Some(account) => ("200 OK", to_json(&account)),
+                None => ("404 Not Found", String::from("{}")),
+            },
+            None => ("404 Not Found", String::from("{}")),
+        },
+        _ => ("405 Method Not Allowed", String::from("{}")),
+    };
+    write!(
+        &stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| String::from("{}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{BankBuilder, Transaction};
+    use std::io::Read;
+
+    fn get(address: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: admin\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_admin_api_reports_run_in_flight() {
+        // GIVEN
+        let state = SharedAdminState::new();
+        let bank = BankBuilder::<f32>::default()
+            .update_sink(state.clone())
+            .build();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        serve(listener, state.clone());
+
+        // WHEN
+        for tx in [
+            Transaction::deposit(1, 1, 2.0),
+            Transaction::withdrawal(1, 2, 5.0),
+        ] {
+            state.record(bank.process_transaction(tx).unwrap());
+        }
+
+        // THEN
+        let progress = get(&address, "/progress");
+        assert!(progress.starts_with("HTTP/1.1 200 OK"));
+        assert!(progress.ends_with(r#"{"rows":2,"codes":{"E2003":1}}"#));
+        let client = get(&address, "/clients/1");
+        assert!(client.contains(r#""client":1,"available":"2.0000""#));
+        assert!(get(&address, "/clients/2").starts_with("HTTP/1.1 404"));
+        assert!(get(&address, "/clients/x").starts_with("HTTP/1.1 404"));
+    }
+}
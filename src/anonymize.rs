@@ -0,0 +1,159 @@
+//! Exports that can be shared outside the bank, e.g. with analytics
+//! vendors: client ids become salted pseudonyms and amounts are bucketed.
+
+use crate::bank::{AccountView, TransactionType};
+use crate::compliance::{ComplianceEntry, ComplianceReason};
+use crate::money::Money;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::io;
+
+/**
+ * Replaces client ids with pseudonyms that stay the same for the same
+ * salt, so datasets from several runs can still be joined, and rounds
+ * amounts down to a multiple of `bucket`.
+ */
+pub struct Anonymizer {
+    salt: String,
+    bucket: f64,
+}
+
+impl Anonymizer {
+    pub fn new(salt: impl Into<String>, bucket: f64) -> Self {
+        Anonymizer {
+            salt: salt.into(),
+            bucket,
+        }
+    }
+
+    /**
+     * `c` and 16 hex digits of the SHA-256 of the salt and the client id.
+     */
+    pub fn pseudonym(&self, client_id: u16) -> String {
+        let digest = Sha256::digest(format!("{}:{}", self.salt, client_id));
+        let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        format!("c{}", hex)
+    }
+
+    /**
+     * The lower bound of the bucket holding `amount`, with four decimal
+     * places like the report.
+     */
+    pub fn bucket<M: Money>(&self, amount: M) -> String {
+        let value = amount.to_f64();
+        let lower = if self.bucket > 0.0 {
+            (value / self.bucket).floor() * self.bucket
+        } else {
+            value
+        };
+        // Adding zero turns -0.0 into 0.0.
+        format!("{:.4}", lower + 0.0)
+    }
+}
+
+#[derive(Serialize)]
+struct AccountRow {
+    client: String,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+    frozen: bool,
+}
+
+#[derive(Serialize)]
+struct ComplianceRow {
+    client: String,
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    amount: Option<String>,
+    timestamp: Option<u64>,
+    reason: ComplianceReason,
+}
+
+/**
+ * Writes the account report with pseudonyms and bucketed balances.
+ */
+pub fn write_anonymized_report<M: Money, W: io::Write>(
+    accounts: &[AccountView<M>],
+    anonymizer: &Anonymizer,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for account in accounts {
+        writer.serialize(AccountRow {
+            client: anonymizer.pseudonym(account.client_id),
+            available: anonymizer.bucket(account.available),
+            held: anonymizer.bucket(account.held),
+            total: anonymizer.bucket(account.total),
+            locked: account.locked,
+            frozen: account.frozen,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/**
+ * Writes the compliance report with pseudonyms and bucketed amounts,
+ * leaving out transaction ids, tags and descriptions.
+ */
+pub fn write_anonymized_compliance_report<M: Money, W: io::Write>(
+    entries: &[ComplianceEntry<M>],
+    anonymizer: &Anonymizer,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for entry in entries {
+        writer.serialize(ComplianceRow {
+            client: anonymizer.pseudonym(entry.client_id),
+            tx_type: entry.tx_type,
+            amount: entry.amount.map(|amount| anonymizer.bucket(amount)),
+            timestamp: entry.timestamp,
+            reason: entry.reason,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{Bank, Transaction};
+
+    #[test]
+    fn test_anonymized_report() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 1234.5),
+            Transaction::deposit(2, 2, 99.0),
+            Transaction::dispute(2, 2),
+        ])
+        .unwrap();
+        let anonymizer = Anonymizer::new("pepper", 100.0);
+        let mut output = Vec::new();
+
+        // WHEN
+        write_anonymized_report(&bank.accounts(), &anonymizer, &mut output).unwrap();
+
+        // THEN
+        let first = anonymizer.pseudonym(1);
+        let second = anonymizer.pseudonym(2);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "client,available,held,total,locked,frozen\n\
+                 {},1200.0000,0.0000,1200.0000,false,false\n\
+                 {},0.0000,0.0000,0.0000,false,false\n",
+                first, second
+            )
+        );
+        assert_eq!(first, Anonymizer::new("pepper", 1.0).pseudonym(1));
+        assert_ne!(first, Anonymizer::new("salt", 100.0).pseudonym(1));
+        assert_eq!(first.len(), 17);
+        assert_eq!(anonymizer.bucket(-0.5), "-100.0000");
+    }
+}
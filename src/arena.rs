@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+/**
+ * Records stored contiguously and looked up by id through a small index of
+ * slot numbers, so the index only ever moves `u32` pairs when it grows.
+ * Slots freed by `remove` are reused by later inserts.
+ */
+pub(crate) struct Arena<T> {
+    slots: Vec<Option<T>>,
+    index: HashMap<u32, u32>,
+    free: Vec<u32>,
+}
+
+impl<T> Arena<T> {
+    pub(crate) fn new() -> Self {
+        Arena {
+            slots: Vec::new(),
+            index: HashMap::new(),
+            free: Vec::new(),
+        }
+    }
+
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Arena {
+            slots: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity(capacity),
+            free: Vec::new(),
+        }
+    }
+
+    pub(crate) fn get(&self, id: u32) -> Option<&T> {
+        let slot = *self.index.get(&id)?;
+        self.slots[slot as usize].as_ref()
+    }
+
+    pub(crate) fn contains_key(&self, id: u32) -> bool {
+        self.index.contains_key(&id)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /**
+     * Stores `value` under `id`, returning the value it replaces.
+     */
+    pub(crate) fn insert(&mut self, id: u32, value: T) -> Option<T> {
+        if let Some(&slot) = self.index.get(&id) {
+            return self.slots[slot as usize].replace(value);
+        }
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot as usize] = Some(value);
+                slot
+            }
+            None => {
+                self.slots.push(Some(value));
+                (self.slots.len() - 1) as u32
+            }
+        };
+        self.index.insert(id, slot);
+        None
+    }
+
+    pub(crate) fn remove(&mut self, id: u32) -> Option<T> {
+        let slot = self.index.remove(&id)?;
+        self.free.push(slot);
+        self.slots[slot as usize].take()
+    }
+
+    /**
+     * Every stored value, in slot order.
+     */
+    pub(crate) fn values(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().flatten()
+    }
+}
+
+impl<T> FromIterator<(u32, T)> for Arena<T> {
+    fn from_iter<I: IntoIterator<Item = (u32, T)>>(iter: I) -> Self {
+        let mut arena = Arena::new();
+        for (id, value) in iter {
+            arena.insert(id, value);
+        }
+        arena
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arena_reuses_freed_slots() {
+        // GIVEN
+        let mut arena = Arena::new();
+        arena.insert(10, "a");
+        arena.insert(20, "b");
+
+        // WHEN
+        let removed = arena.remove(10);
+        arena.insert(30, "c");
+        let replaced = arena.insert(20, "d");
+
+        // THEN
+        assert_eq!(removed, Some("a"));
+        assert_eq!(replaced, Some("b"));
+        assert_eq!(arena.slots.len(), 2);
+        assert_eq!(arena.get(30), Some(&"c"));
+        assert!(!arena.contains_key(10));
+        assert_eq!(arena.values().copied().collect::<Vec<_>>(), vec!["c", "d"]);
+    }
+}
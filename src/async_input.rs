@@ -0,0 +1,97 @@
+//! Transactions read asynchronously, e.g. from a `tokio::fs::File` or a
+//! socket; built with the `tokio` feature.
+
+use crate::bank::Transaction;
+use crate::error::{BankError, ErrorCode};
+use crate::fast_csv::read_line;
+use crate::input::{parse_record, TypeNames};
+use crate::money::Money;
+use std::error::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio_stream::wrappers::LinesStream;
+use tokio_stream::{Stream, StreamExt};
+
+/**
+ * Reads CSV with headers from `reader` one line at a time, as a stream of
+ * the transactions `CsvSource` would read, or of the errors reading them.
+ * Blank lines are skipped. Quoted fields cannot span lines.
+ */
+pub async fn csv_stream<M: Money, R: AsyncBufRead + Unpin>(
+    reader: R,
+    type_names: TypeNames,
+) -> Result<impl Stream<Item = Result<Transaction<M>, Box<dyn Error>>>, Box<dyn Error>> {
+    let mut lines = reader.lines();
+    let header = lines.next_line().await?.unwrap_or_default();
+    let headers = read_line(header.as_bytes())?;
+    Ok(LinesStream::new(lines).filter_map(move |line| match line {
+        Ok(line) if line.trim().is_empty() => None,
+        Ok(line) => Some(
+            read_line(line.as_bytes())
+                .and_then(|record| parse_record(&record, &headers, &type_names)),
+        ),
+        Err(error) => Some(Err(BankError::new(
+            ErrorCode::ReadFailed,
+            error.to_string(),
+        )
+        .into())),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{Bank, TxOutcome};
+    use tokio::runtime;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_process_stream() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        let stream = tokio_stream::iter(vec![
+            Transaction::deposit(1, 1, 10.0),
+            Transaction::withdrawal(1, 2, 4.0),
+            Transaction::withdrawal(1, 3, 7.0),
+        ]);
+
+        // WHEN
+        let summary = block_on(bank.process_stream(stream)).unwrap();
+
+        // THEN
+        assert_eq!(summary.applied, 2);
+        assert_eq!(summary.rows(), 3);
+        assert_eq!(bank.account(1).unwrap().available, 6.0);
+    }
+
+    #[test]
+    fn test_csv_stream_through_process_source_stream() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        let input =
+            "type, client, tx, amount\ndeposit, 2, 1, 3.5\n\ndispute, 2, 1,\nrefund, 2, 2, 1\n";
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = block_on(async {
+            let stream = csv_stream(input.as_bytes(), TypeNames::default()).await?;
+            bank.process_source_stream(stream, |processed, outcome| {
+                outcomes.push((processed.tx_id, outcome))
+            })
+            .await
+        });
+
+        // THEN
+        assert!(result.unwrap_err().to_string().contains("refund"));
+        assert_eq!(
+            outcomes,
+            vec![(1, TxOutcome::Applied), (1, TxOutcome::Applied)]
+        );
+        assert_eq!(bank.account(2).unwrap().held, 3.5);
+    }
+}
@@ -1,541 +1,5669 @@
-use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
-use std::collections::HashMap;
+use crate::compliance::{AmlConfig, ComplianceEntry, ComplianceMonitor};
+use crate::error::{BankError, ErrorCode};
+use crate::input::{CsvSource, TransactionSource, TypeNames};
+use crate::liquidity::LiquidityReport;
+use crate::mandates::Mandate;
+use crate::money::{fixed_text, serialize_fixed, serialize_fixed_opt, Money};
+use crate::overrides::{ClientOverride, KycStatus};
+use crate::review::{ReviewEntry, ReviewQueue};
+use crate::risk::{RiskAction, RiskFlag, VelocityRule, VelocityTracker};
+use crate::rules::{Rule, RuleAction, RuleEngine};
+use crate::sinks::{AccountUpdate, AccountUpdateSink};
+use crate::snapshot::{BankSnapshot, DisputeStats};
+use crate::statements::{ClientStatement, StatementEntry};
+use crate::store::TransactionStore;
+use crate::validation::{Rejection, TxValidator};
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
-use std::io;
+use std::fmt;
+use std::io::{self, Write};
+use std::iter;
+use std::ops::ControlFlow;
+#[cfg(feature = "tokio")]
+use std::pin::pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(feature = "tokio")]
+use tokio_stream::{Stream, StreamExt};
 
 const INVALID_TRANSACTION_DATA_NO_AMOUNT: &str = "Invalid transaction data: missing amount";
+const INVALID_CHUNK_SIZE: &str = "Chunk size must be greater than zero";
+const AMOUNT_OUT_OF_RANGE: &str = "Amount out of range";
+const SECONDS_PER_DAY: u64 = 86_400;
+const REPORT_BUFFER_BYTES: usize = 64 * 1024;
 
-#[derive(Debug, Copy, Clone, Deserialize)]
-#[serde(rename_all = "lowercase")]
-enum TransactionType {
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TransactionType {
     Deposit,
     Withdrawal,
     Dispute,
     Resolve,
     Chargeback,
+    Freeze,
+    Unfreeze,
+    Approve,
+    Decline,
+    /// A creditor pulling funds under the mandate the transaction names.
+    DirectDebit,
+    /// The client sending a direct debit back; references its `tx`.
+    DebitReturn,
+    /// A program-specific type registered with `BankBuilder::custom_type`.
+    Custom(&'static str),
 }
 
-#[derive(Debug, PartialEq)]
-enum TransactionStatus {
+impl TransactionType {
+    /**
+     * The canonical name, as used in the `type` column.
+     */
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Freeze => "freeze",
+            TransactionType::Unfreeze => "unfreeze",
+            TransactionType::Approve => "approve",
+            TransactionType::Decline => "decline",
+            TransactionType::DirectDebit => "direct_debit",
+            TransactionType::DebitReturn => "debit_return",
+            TransactionType::Custom(name) => name,
+        }
+    }
+}
+
+impl FromStr for TransactionType {
+    type Err = String;
+
+    /**
+     * Parses a canonical lowercase name only; see `input::TypeNames` for
+     * tolerant parsing and custom types.
+     */
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "deposit" => Ok(TransactionType::Deposit),
+            "withdrawal" => Ok(TransactionType::Withdrawal),
+            "dispute" => Ok(TransactionType::Dispute),
+            "resolve" => Ok(TransactionType::Resolve),
+            "chargeback" => Ok(TransactionType::Chargeback),
+            "freeze" => Ok(TransactionType::Freeze),
+            "unfreeze" => Ok(TransactionType::Unfreeze),
+            "approve" => Ok(TransactionType::Approve),
+            "decline" => Ok(TransactionType::Decline),
+            "direct_debit" => Ok(TransactionType::DirectDebit),
+            "debit_return" => Ok(TransactionType::DebitReturn),
+            _ => Err(format!("Unknown transaction type {:?}", name)),
+        }
+    }
+}
+
+impl Serialize for TransactionType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for TransactionType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(de::Error::custom)
+    }
+}
+
+/**
+ * Where a stored deposit or withdrawal is in its lifecycle.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionStatus {
     Processed,
     Disputed,
+    Resolved,
+    ChargedBack,
+    Pending,
+    Declined,
+    /// A direct debit sent back by the client.
+    Returned,
+}
+
+/**
+ * The latest dispute of a transaction, for support lookups. Times are the
+ * timestamps of the dispute and of the resolve or chargeback that closed
+ * it, or the bank's watermark for rows without one.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub struct DisputeState {
+    #[serde(rename = "tx")]
+    pub tx_id: u32,
+    /// Client that raised the dispute.
+    pub disputed_by: u16,
+    pub disputed_at: Option<u64>,
+    /// `Disputed` while open, then `Resolved` or `ChargedBack`.
+    pub status: TransactionStatus,
+    pub closed_at: Option<u64>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct Transaction {
-    #[serde(rename(deserialize = "type"))]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Transaction<M = Decimal> {
+    #[serde(rename = "type")]
     tx_type: TransactionType,
-    #[serde(rename(deserialize = "client"))]
+    #[serde(rename = "client")]
     client_id: u16,
-    #[serde(rename(deserialize = "tx"))]
+    #[serde(rename = "tx")]
     id: u32,
-    amount: Option<f32>,
+    amount: Option<M>,
+    /// Event time in seconds since the Unix epoch, when the source provides one.
+    #[serde(default)]
+    timestamp: Option<u64>,
+    /// Free-form references from the upstream system, kept as given.
+    #[serde(default)]
+    tags: Option<String>,
+    /// Human-readable memo, e.g. for customer statements.
+    #[serde(default)]
+    description: Option<String>,
+    /// The upstream system's own reference, indexed for lookups.
+    #[serde(default)]
+    external_id: Option<String>,
+    /// Reference of the mandate a direct debit pulls under.
+    #[serde(default)]
+    mandate: Option<String>,
+}
+
+impl<M: Money> Transaction<M> {
+    pub fn new(tx_type: TransactionType, client_id: u16, id: u32, amount: Option<M>) -> Self {
+        Transaction {
+            tx_type,
+            client_id,
+            id,
+            amount,
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        }
+    }
+
+    /**
+     * Replaces the type, e.g. with a `TransactionType::Custom` that cannot
+     * be deserialized directly.
+     */
+    pub fn with_tx_type(mut self, tx_type: TransactionType) -> Self {
+        self.tx_type = tx_type;
+        self
+    }
+
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn with_tags(mut self, tags: impl Into<String>) -> Self {
+        self.tags = Some(tags.into());
+        self
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn with_external_id(mut self, external_id: impl Into<String>) -> Self {
+        self.external_id = Some(external_id.into());
+        self
+    }
+
+    pub fn with_mandate(mut self, mandate: impl Into<String>) -> Self {
+        self.mandate = Some(mandate.into());
+        self
+    }
+
+    pub fn direct_debit(client_id: u16, id: u32, amount: M, mandate: &str) -> Self {
+        Self::new(TransactionType::DirectDebit, client_id, id, Some(amount)).with_mandate(mandate)
+    }
+
+    /**
+     * Sends back the direct debit `id`.
+     */
+    pub fn debit_return(client_id: u16, id: u32) -> Self {
+        Self::new(TransactionType::DebitReturn, client_id, id, None)
+    }
+
+    pub fn deposit(client_id: u16, id: u32, amount: M) -> Self {
+        Self::new(TransactionType::Deposit, client_id, id, Some(amount))
+    }
+
+    pub fn withdrawal(client_id: u16, id: u32, amount: M) -> Self {
+        Self::new(TransactionType::Withdrawal, client_id, id, Some(amount))
+    }
+
+    /**
+     * Disputes, resolves and chargebacks carry no amount of their own; `id`
+     * refers to the transaction they apply to.
+     */
+    pub fn dispute(client_id: u16, id: u32) -> Self {
+        Self::new(TransactionType::Dispute, client_id, id, None)
+    }
+
+    pub fn resolve(client_id: u16, id: u32) -> Self {
+        Self::new(TransactionType::Resolve, client_id, id, None)
+    }
+
+    pub fn chargeback(client_id: u16, id: u32) -> Self {
+        Self::new(TransactionType::Chargeback, client_id, id, None)
+    }
+
+    /**
+     * Administrative freeze of the client's account. `id` only identifies the
+     * operation; freezes are not stored.
+     */
+    pub fn freeze(client_id: u16, id: u32) -> Self {
+        Self::new(TransactionType::Freeze, client_id, id, None)
+    }
+
+    pub fn unfreeze(client_id: u16, id: u32) -> Self {
+        Self::new(TransactionType::Unfreeze, client_id, id, None)
+    }
+
+    /**
+     * Settles the pending transaction `id` held for manual review.
+     */
+    pub fn approve(client_id: u16, id: u32) -> Self {
+        Self::new(TransactionType::Approve, client_id, id, None)
+    }
+
+    /**
+     * Reverts the pending transaction `id` held for manual review.
+     */
+    pub fn decline(client_id: u16, id: u32) -> Self {
+        Self::new(TransactionType::Decline, client_id, id, None)
+    }
+
+    pub fn tx_type(&self) -> TransactionType {
+        self.tx_type
+    }
+
+    pub fn client_id(&self) -> u16 {
+        self.client_id
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn amount(&self) -> Option<M> {
+        self.amount
+    }
+
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    pub fn tags(&self) -> Option<&str> {
+        self.tags.as_deref()
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn external_id(&self) -> Option<&str> {
+        self.external_id.as_deref()
+    }
+
+    pub fn mandate(&self) -> Option<&str> {
+        self.mandate.as_deref()
+    }
+
+    /**
+     * Drops the free-text fields that may identify a person, returning the
+     * external id so its index entry can go too.
+     */
+    fn purge_details(&mut self) -> Option<String> {
+        self.tags = None;
+        self.description = None;
+        self.external_id.take()
+    }
+}
+
+pub(crate) type TransactionRecord<M> = (Transaction<M>, TransactionStatus);
+
+/**
+ * Criteria for `Bank::find_transactions`; unset ones match everything.
+ * Ranges are inclusive. A transaction without an amount or timestamp never
+ * matches a range on it.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionFilter<M = Decimal> {
+    pub client_id: Option<u16>,
+    pub tx_type: Option<TransactionType>,
+    pub status: Option<TransactionStatus>,
+    pub min_amount: Option<M>,
+    pub max_amount: Option<M>,
+    /// Earliest timestamp.
+    pub from: Option<u64>,
+    /// Latest timestamp.
+    pub until: Option<u64>,
+}
+
+impl<M> Default for TransactionFilter<M> {
+    fn default() -> Self {
+        TransactionFilter {
+            client_id: None,
+            tx_type: None,
+            status: None,
+            min_amount: None,
+            max_amount: None,
+            from: None,
+            until: None,
+        }
+    }
+}
+
+impl<M: Money> TransactionFilter<M> {
+    pub fn matches(&self, tx: &Transaction<M>, status: TransactionStatus) -> bool {
+        let in_range = |value: Option<M>, min: Option<M>, max: Option<M>| match value {
+            Some(value) => min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max),
+            None => min.is_none() && max.is_none(),
+        };
+        let in_time = match tx.timestamp {
+            Some(at) => {
+                self.from.is_none_or(|from| at >= from)
+                    && self.until.is_none_or(|until| at <= until)
+            }
+            None => self.from.is_none() && self.until.is_none(),
+        };
+        self.client_id
+            .is_none_or(|client_id| tx.client_id == client_id)
+            && self.tx_type.is_none_or(|tx_type| tx.tx_type == tx_type)
+            && self.status.is_none_or(|wanted| status == wanted)
+            && in_range(tx.amount, self.min_amount, self.max_amount)
+            && in_time
+    }
+}
+
+/**
+ * Read-only access to the transactions stored by the bank, handed to
+ * validators.
+ */
+pub struct TxHistory<'a, M = Decimal> {
+    transactions: &'a TransactionStore<M>,
+    withdrawal_ids: &'a HashSet<u32>,
+}
+
+impl<M: Money> TxHistory<'_, M> {
+    pub fn get(&self, tx_id: u32) -> Option<&Transaction<M>> {
+        self.transactions.get(tx_id).map(|record| &record.0)
+    }
+
+    pub fn contains(&self, tx_id: u32) -> bool {
+        self.transactions.contains_key(tx_id) || self.withdrawal_ids.contains(&tx_id)
+    }
+
+    pub fn is_disputed(&self, tx_id: u32) -> bool {
+        matches!(
+            self.transactions.get(tx_id),
+            Some((_, TransactionStatus::Disputed))
+        )
+    }
+}
+
+/**
+ * What happened to a single transaction once it reached the bank.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TxOutcome {
+    Applied,
+    RejectedInsufficientFunds,
+    IgnoredUnknownTransaction,
+    IgnoredClientMismatch,
+    IgnoredInvalidState,
+    /// The referenced transaction is already in the state this request would
+    /// move it to; nothing was changed.
+    AlreadyInState,
+    IgnoredDisputeWindowExpired,
+    /// The referenced transaction is a withdrawal and withdrawals are not
+    /// disputable.
+    IgnoredWithdrawalNotDisputable,
+    /// The referenced transaction is on a credit account, whose draws and
+    /// repayments are not disputable.
+    IgnoredCreditNotDisputable,
+    RejectedMalformed,
+    RejectedDailyLimit,
+    Rejected(Rejection),
+    /// Applied, but broke a velocity rule set to flag.
+    Flagged(RiskFlag),
+    /// Refused because it broke a velocity rule set to block.
+    Blocked(RiskFlag),
+    /// Applied, but the dispute left the account's available balance below
+    /// zero.
+    NegativeAvailable,
+    /// Accepted but held for manual review; see `Transaction::approve`.
+    Pending,
+    /// Applied, but only the available balance was held.
+    DisputeCapped,
+    /// Refused because the dispute would push available below zero.
+    RejectedDisputeOverdraw,
+    /// The direct debit is older than the return window.
+    IgnoredReturnWindowExpired,
+}
+
+impl fmt::Display for TxOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TxOutcome::Applied => write!(f, "applied"),
+            TxOutcome::RejectedInsufficientFunds => write!(f, "rejected, insufficient funds"),
+            TxOutcome::IgnoredUnknownTransaction => {
+                write!(f, "ignored, referenced transaction not found")
+            }
+            TxOutcome::IgnoredClientMismatch => write!(
+                f,
+                "ignored, referenced transaction does not have matching client id"
+            ),
+            TxOutcome::IgnoredInvalidState => {
+                write!(f, "ignored, referenced transaction not in desired state")
+            }
+            TxOutcome::AlreadyInState => {
+                write!(f, "ignored, referenced transaction already in that state")
+            }
+            TxOutcome::IgnoredDisputeWindowExpired => {
+                write!(f, "ignored, dispute window has expired")
+            }
+            TxOutcome::IgnoredWithdrawalNotDisputable => {
+                write!(f, "ignored, withdrawals cannot be disputed")
+            }
+            TxOutcome::IgnoredCreditNotDisputable => {
+                write!(f, "ignored, credit account transactions cannot be disputed")
+            }
+            TxOutcome::RejectedMalformed => write!(f, "rejected, malformed transaction"),
+            TxOutcome::RejectedDailyLimit => write!(f, "rejected, daily withdrawal limit reached"),
+            TxOutcome::Rejected(rejection) => write!(f, "rejected, {}", rejection),
+            TxOutcome::Flagged(flag) => write!(f, "applied, flagged: {}", flag),
+            TxOutcome::Blocked(flag) => write!(f, "blocked, {}", flag),
+            TxOutcome::NegativeAvailable => {
+                write!(f, "applied, available balance is now negative")
+            }
+            TxOutcome::Pending => write!(f, "pending manual review"),
+            TxOutcome::DisputeCapped => {
+                write!(f, "applied, held amount capped at the available balance")
+            }
+            TxOutcome::RejectedDisputeOverdraw => {
+                write!(f, "rejected, dispute would overdraw available funds")
+            }
+            TxOutcome::IgnoredReturnWindowExpired => {
+                write!(f, "ignored, direct debit return window has expired")
+            }
+        }
+    }
+}
+
+impl TxOutcome {
+    /// Stable code for diagnostics; `None` for `Applied`.
+    pub fn code(&self) -> Option<ErrorCode> {
+        let code = match self {
+            TxOutcome::Applied => return None,
+            TxOutcome::RejectedInsufficientFunds => ErrorCode::InsufficientFunds,
+            TxOutcome::IgnoredUnknownTransaction => ErrorCode::UnknownTransaction,
+            TxOutcome::IgnoredClientMismatch => ErrorCode::ClientMismatch,
+            TxOutcome::IgnoredInvalidState => ErrorCode::InvalidState,
+            TxOutcome::AlreadyInState => ErrorCode::AlreadyInState,
+            TxOutcome::IgnoredDisputeWindowExpired => ErrorCode::DisputeWindowExpired,
+            TxOutcome::IgnoredWithdrawalNotDisputable => ErrorCode::WithdrawalNotDisputable,
+            TxOutcome::IgnoredCreditNotDisputable => ErrorCode::CreditNotDisputable,
+            TxOutcome::RejectedMalformed => ErrorCode::Malformed,
+            TxOutcome::RejectedDailyLimit => ErrorCode::DailyLimit,
+            TxOutcome::Rejected(rejection) => rejection.code(),
+            TxOutcome::Flagged(_) => ErrorCode::VelocityFlagged,
+            TxOutcome::Blocked(_) => ErrorCode::VelocityBlocked,
+            TxOutcome::NegativeAvailable => ErrorCode::NegativeAvailable,
+            TxOutcome::Pending => ErrorCode::Pending,
+            TxOutcome::DisputeCapped => ErrorCode::DisputeCapped,
+            TxOutcome::RejectedDisputeOverdraw => ErrorCode::DisputeOverdraw,
+            TxOutcome::IgnoredReturnWindowExpired => ErrorCode::ReturnWindowExpired,
+        };
+        Some(code)
+    }
 }
 
-type TransactionRecord = (Transaction, TransactionStatus);
+/**
+ * Which transaction an outcome passed to `Bank::process_source` belongs to.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ProcessedTx {
+    pub tx_id: u32,
+    pub client_id: u16,
+    /// See `TransactionSource::line`.
+    pub line: Option<u64>,
+}
+
+/**
+ * What a batch of transactions did, as returned by `Bank::batch_process`.
+ * Callers streaming transactions build one with `record`.
+ */
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BatchSummary {
+    /// Transactions applied, including those applied with a warning
+    /// (`W4xxx`) and those held pending review.
+    pub applied: usize,
+    /// Transactions refused or ignored, by code.
+    pub rejected: BTreeMap<ErrorCode, usize>,
+    pub duration: Duration,
+    clients: HashSet<u16>,
+}
+
+impl BatchSummary {
+    pub fn record(&mut self, client_id: u16, outcome: TxOutcome) {
+        self.clients.insert(client_id);
+        match outcome.code() {
+            Some(code) if !code.is_warning() => *self.rejected.entry(code).or_default() += 1,
+            _ => self.applied += 1,
+        }
+    }
+
+    /// Transactions recorded.
+    pub fn rows(&self) -> usize {
+        self.applied + self.rejected.values().sum::<usize>()
+    }
+
+    /// Clients that had transactions in the batch, whatever the outcome.
+    pub fn accounts_touched(&self) -> usize {
+        self.clients.len()
+    }
+}
+
+/**
+ * A batch waiting for approval, as returned by `Bank::stage_batch`.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct StagedBatch {
+    /// Passed to `Bank::approve_batch` or `Bank::reject_batch`.
+    pub id: u32,
+    pub summary: BatchSummary,
+}
 
-#[derive(Debug, Serialize)]
-struct Account {
-    #[serde(rename(serialize = "client"))]
+#[derive(Debug, Clone)]
+struct Account<M> {
     client_id: u16,
-    available: f32,
-    held: f32,
-    total: f32,
+    available: M,
+    held: M,
+    total: M,
     locked: bool,
+    frozen: bool,
+    /// What a credit account owes; `None` for deposit accounts.
+    outstanding: Option<M>,
 }
 
-impl Account {
+impl<M: Money> Account<M> {
     fn new(client_id: u16) -> Self {
         Account {
-            client_id: client_id,
-            available: 0.0,
-            held: 0.0,
-            total: 0.0,
+            client_id,
+            available: M::zero(),
+            held: M::zero(),
+            total: M::zero(),
             locked: false,
+            frozen: false,
+            outstanding: None,
+        }
+    }
+
+    /**
+     * Deposits `amount`. On a credit account it repays what is owed first
+     * and only the rest is credited.
+     */
+    fn deposit(&mut self, amount: M) -> Result<(), BankError> {
+        let Some(outstanding) = self.outstanding else {
+            return self.credit(amount);
+        };
+        let repaid = if amount < outstanding {
+            amount
+        } else {
+            outstanding
+        };
+        let outstanding = sub(outstanding, repaid)?;
+        self.credit(sub(amount, repaid)?)?;
+        self.outstanding = Some(outstanding);
+        Ok(())
+    }
+
+    /**
+     * Withdraws `amount`. A credit account pays out of its positive
+     * available balance first and borrows the rest.
+     */
+    fn draw(&mut self, amount: M) -> Result<(), BankError> {
+        let Some(outstanding) = self.outstanding else {
+            return self.debit(amount);
+        };
+        let funds = self.own_funds();
+        let paid = if amount < funds { amount } else { funds };
+        let outstanding = add(outstanding, sub(amount, paid)?)?;
+        self.debit(paid)?;
+        self.outstanding = Some(outstanding);
+        Ok(())
+    }
+
+    /// The available balance, or zero if it is negative.
+    fn own_funds(&self) -> M {
+        if self.available > M::zero() {
+            self.available
+        } else {
+            M::zero()
+        }
+    }
+
+    /**
+     * Adds `amount` to available and total.
+     */
+    fn credit(&mut self, amount: M) -> Result<(), BankError> {
+        let available = add(self.available, amount)?;
+        self.total = add(self.total, amount)?;
+        self.available = available;
+        Ok(())
+    }
+
+    /**
+     * Takes `amount` from available and total.
+     */
+    fn debit(&mut self, amount: M) -> Result<(), BankError> {
+        let available = sub(self.available, amount)?;
+        self.total = sub(self.total, amount)?;
+        self.available = available;
+        Ok(())
+    }
+
+    /**
+     * Moves `amount` from available to held.
+     */
+    fn hold(&mut self, amount: M) -> Result<(), BankError> {
+        let available = sub(self.available, amount)?;
+        self.held = add(self.held, amount)?;
+        self.available = available;
+        Ok(())
+    }
+
+    /**
+     * Moves `amount` from held back to available.
+     */
+    fn release(&mut self, amount: M) -> Result<(), BankError> {
+        let held = sub(self.held, amount)?;
+        self.available = add(self.available, amount)?;
+        self.held = held;
+        Ok(())
+    }
+
+    /**
+     * Adds `amount` to held and total, for incoming funds not yet available.
+     */
+    fn hold_incoming(&mut self, amount: M) -> Result<(), BankError> {
+        let held = add(self.held, amount)?;
+        self.total = add(self.total, amount)?;
+        self.held = held;
+        Ok(())
+    }
+
+    /**
+     * Takes `amount` out of held and total.
+     */
+    fn remove_held(&mut self, amount: M) -> Result<(), BankError> {
+        let held = sub(self.held, amount)?;
+        self.total = sub(self.total, amount)?;
+        self.held = held;
+        Ok(())
+    }
+
+    /**
+     * Panics, naming `tx_id`, if held funds went negative or total drifted
+     * from available + held by more than f32 rounding. Rounding is relative
+     * to `peak`, the largest balance the account has held: f32 loses small
+     * amounts added to large ones, and the loss stays once the large amount
+     * is gone.
+     */
+    #[cfg(feature = "invariant-checks")]
+    fn assert_invariants(&self, tx_id: u32, peak: f64) {
+        let total = self.total.to_f64();
+        let tolerance = (peak * 1e-6).max(1e-4);
+        assert!(
+            (total - (self.available.to_f64() + self.held.to_f64())).abs() <= tolerance,
+            "Invariant violated after transaction #{}: total {} != available {} + held {}",
+            tx_id,
+            self.total,
+            self.available,
+            self.held
+        );
+        assert!(
+            self.held.to_f64() >= -tolerance,
+            "Invariant violated after transaction #{}: held {} is negative",
+            tx_id,
+            self.held
+        );
+    }
+
+    fn view(&self) -> AccountView<M> {
+        AccountView {
+            client_id: self.client_id,
+            available: self.available,
+            held: self.held,
+            total: self.total,
+            locked: self.locked,
+            frozen: self.frozen,
         }
     }
 }
 
-pub struct Bank {
-    accounts: RefCell<Vec<Account>>,
-    transactions: RefCell<HashMap<u32, TransactionRecord>>,
+/**
+ * Handles a custom transaction type. Returning an error treats the
+ * transaction as malformed, as for built-in types; plain messages convert
+ * to errors with `ErrorCode::HandlerFailed`.
+ */
+pub type CustomHandler<M = Decimal> =
+    Box<dyn Fn(&Transaction<M>, &mut CustomContext<M>) -> Result<TxOutcome, BankError>>;
+
+/**
+ * What a custom transaction handler may touch: the client's account and the
+ * stored transactions. Balance changes fail, leaving the account as it was,
+ * if the result is out of range.
+ */
+pub struct CustomContext<'a, M = Decimal> {
+    account: &'a mut Account<M>,
+    transactions: &'a mut TransactionStore<M>,
+}
+
+impl<M: Money> CustomContext<'_, M> {
+    pub fn account(&self) -> AccountView<M> {
+        self.account.view()
+    }
+
+    /**
+     * Adds `amount` to the available and total balances.
+     */
+    pub fn credit(&mut self, amount: M) -> Result<(), BankError> {
+        self.account.credit(amount)
+    }
+
+    /**
+     * Takes `amount` from the available and total balances.
+     */
+    pub fn debit(&mut self, amount: M) -> Result<(), BankError> {
+        self.account.debit(amount)
+    }
+
+    /**
+     * Moves `amount` from available to held.
+     */
+    pub fn hold(&mut self, amount: M) -> Result<(), BankError> {
+        self.account.hold(amount)
+    }
+
+    /**
+     * Moves `amount` from held back to available.
+     */
+    pub fn release(&mut self, amount: M) -> Result<(), BankError> {
+        self.account.release(amount)
+    }
+
+    pub fn set_locked(&mut self, locked: bool) {
+        self.account.locked = locked;
+    }
+
+    pub fn transaction(&self, tx_id: u32) -> Option<(&Transaction<M>, TransactionStatus)> {
+        self.transactions
+            .get(tx_id)
+            .map(|(tx, status)| (tx, *status))
+    }
+
+    /**
+     * Stores `tx` as processed, replacing any transaction with its id.
+     */
+    pub fn store(&mut self, tx: Transaction<M>) {
+        self.transactions
+            .insert(tx.id, (tx, TransactionStatus::Processed));
+    }
+}
+
+/**
+ * Read-only copy of an account's balances, as returned by the query APIs
+ * and written to the report.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[serde(bound(serialize = "M: Money"))]
+pub struct AccountView<M = Decimal> {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    #[serde(serialize_with = "serialize_fixed")]
+    pub available: M,
+    #[serde(serialize_with = "serialize_fixed")]
+    pub held: M,
+    #[serde(serialize_with = "serialize_fixed")]
+    pub total: M,
+    /// Set by a chargeback.
+    pub locked: bool,
+    /// Set by an administrative freeze, independently of `locked`.
+    pub frozen: bool,
+}
+
+impl<M: Money> AccountView<M> {
+    /**
+     * Compares two views allowing each balance to differ by at most
+     * `tolerance`, since floating point balances accumulate rounding error.
+     */
+    pub fn approx_eq(&self, other: &AccountView<M>, tolerance: M) -> bool {
+        let close = |a: M, b: M| a.abs_diff(b).is_some_and(|diff| diff <= tolerance);
+        self.client_id == other.client_id
+            && self.locked == other.locked
+            && self.frozen == other.frozen
+            && close(self.available, other.available)
+            && close(self.held, other.held)
+            && close(self.total, other.total)
+    }
 }
 
 /**
- * In this model 1 account = 1 Client
+ * An account's balances plus figures derived from its stored transactions,
+ * for the extended report. The timestamps are `None` when no stored
+ * transaction carries one.
  */
-impl Bank {
-    pub fn new() -> Self {
-        Self {
-            accounts: RefCell::new(Vec::new()),
-            transactions: RefCell::new(HashMap::new()),
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[serde(bound(serialize = "M: Money"))]
+pub struct ExtendedAccountView<M = Decimal> {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    #[serde(serialize_with = "serialize_fixed")]
+    pub available: M,
+    #[serde(serialize_with = "serialize_fixed")]
+    pub held: M,
+    #[serde(serialize_with = "serialize_fixed")]
+    pub total: M,
+    pub locked: bool,
+    pub frozen: bool,
+    pub tx_count: usize,
+    /// Transactions that were ever disputed, however the dispute ended.
+    pub disputed_count: usize,
+    /// Latest timestamp among the stored transactions.
+    pub last_activity: Option<u64>,
+    /// Earliest timestamp among the stored transactions.
+    pub opened_at: Option<u64>,
+    /// What a credit account owes; `None` for deposit accounts.
+    #[serde(serialize_with = "serialize_fixed_opt")]
+    pub outstanding: Option<M>,
+}
+
+impl<M> ExtendedAccountView<M> {
+    fn new(account: AccountView<M>, outstanding: Option<M>) -> Self {
+        ExtendedAccountView {
+            client_id: account.client_id,
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+            frozen: account.frozen,
+            tx_count: 0,
+            disputed_count: 0,
+            last_activity: None,
+            opened_at: None,
+            outstanding,
         }
     }
-    pub fn batch_process(&self, batch_tx: Vec<Transaction>) -> Result<(), String> {
-        for tx in batch_tx {
-            if let Err(e) = self.process_transaction(tx) {
-                return Err(e);
-            }
+
+    fn record<T>(&mut self, tx: &Transaction<T>, status: TransactionStatus) {
+        self.tx_count += 1;
+        if matches!(
+            status,
+            TransactionStatus::Disputed
+                | TransactionStatus::Resolved
+                | TransactionStatus::ChargedBack
+        ) {
+            self.disputed_count += 1;
+        }
+        if let Some(timestamp) = tx.timestamp {
+            self.last_activity = self.last_activity.max(Some(timestamp));
+            self.opened_at = Some(self.opened_at.map_or(timestamp, |t| t.min(timestamp)));
         }
-        Ok(())
     }
-    fn process_transaction(&self, tx: Transaction) -> Result<(), String> {
-        let mut account = match self.get_account(tx.client_id) {
-            Some(a) => a,
-            None => Account::new(tx.client_id),
-        };
-        let tx_id = tx.id;
+}
 
-        let result = match tx.tx_type {
-            TransactionType::Deposit => {
-                let to_deposit = tx.amount.ok_or(INVALID_TRANSACTION_DATA_NO_AMOUNT)?;
-                account.available += to_deposit;
-                account.total += to_deposit;
-                self.transactions
-                    .borrow_mut()
-                    .insert(tx_id, (tx, TransactionStatus::Processed));
-            }
-            TransactionType::Withdrawal => {
-                let to_withdraw = tx.amount.ok_or(INVALID_TRANSACTION_DATA_NO_AMOUNT)?;
+/**
+ * What batch processing does with a transaction that cannot be applied
+ * because its data is invalid.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop the batch and return the error (default).
+    Abort,
+    /// Report the transaction as `TxOutcome::RejectedMalformed` and carry on.
+    Skip,
+}
 
-                if to_withdraw <= account.available {
-                    account.available -= to_withdraw;
-                    account.total -= to_withdraw;
-                    self.transactions
-                        .borrow_mut()
-                        .insert(tx_id, (tx, TransactionStatus::Processed));
-                }
-            }
-            TransactionType::Dispute => {
-                match self.get_transaction_with_status(
-                    &account,
-                    &tx_id,
-                    TransactionStatus::Processed,
-                ) {
-                    Ok(mut target_tx) => {
-                        let tx_amount = target_tx
-                            .0
-                            .amount
-                            .expect(INVALID_TRANSACTION_DATA_NO_AMOUNT);
-                        account.held += tx_amount;
-                        account.available -= tx_amount;
-                        target_tx.1 = TransactionStatus::Disputed;
-                        self.transactions.borrow_mut().insert(tx_id, target_tx);
-                    }
-                    Err(e) => {
-                        eprintln!("{}", e);
-                    }
-                };
-            }
-            TransactionType::Resolve => {
-                match self.get_transaction_with_status(
-                    &account,
-                    &tx_id,
-                    TransactionStatus::Disputed,
+/**
+ * What a dispute does when the disputed amount is larger than the account's
+ * available balance.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DisputeOverdrawPolicy {
+    /// Hold the full amount, letting available go negative (default).
+    Allow,
+    /// Hold only what is available.
+    Cap,
+    /// Refuse the dispute.
+    Reject,
+}
+
+/**
+ * Which transactions an account locked by a chargeback still accepts.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LockedAccountPolicy {
+    /// Refuse deposits and withdrawals.
+    BlockAll,
+    /// Accept repayment deposits but refuse withdrawals.
+    AllowDeposits,
+    /// Treat locked accounts like any other (default).
+    AllowAll,
+}
+
+#[derive(Debug, Clone)]
+struct BankConfig<M> {
+    overdraft: M,
+    dispute_window: Option<Duration>,
+    error_policy: ErrorPolicy,
+    velocity_rules: Vec<VelocityRule<M>>,
+    aml: Option<AmlConfig<M>>,
+    daily_withdrawal_limit: Option<M>,
+    client_overrides: HashMap<u16, ClientOverride<M>>,
+    unverified_balance_cap: Option<M>,
+    denylist: HashSet<u16>,
+    /// Account of each joint holder, by the holder's client id.
+    joint_holders: HashMap<u16, u16>,
+    dispute_overdraw_policy: DisputeOverdrawPolicy,
+    disputable_withdrawals: bool,
+    locked_account_policy: LockedAccountPolicy,
+    auto_unlock_after: Option<Duration>,
+    mandates: HashMap<String, Mandate<M>>,
+    debit_return_window: Option<Duration>,
+    dual_authorization_threshold: Option<M>,
+    dual_authorization_expiry: Option<Duration>,
+    expected_transactions: usize,
+    rules: Vec<Rule<M>>,
+}
+
+impl<M: Money> Default for BankConfig<M> {
+    fn default() -> Self {
+        BankConfig {
+            overdraft: M::zero(),
+            dispute_window: None,
+            error_policy: ErrorPolicy::Abort,
+            velocity_rules: Vec::new(),
+            aml: None,
+            daily_withdrawal_limit: None,
+            client_overrides: HashMap::new(),
+            unverified_balance_cap: None,
+            denylist: HashSet::new(),
+            joint_holders: HashMap::new(),
+            dispute_overdraw_policy: DisputeOverdrawPolicy::Allow,
+            disputable_withdrawals: true,
+            locked_account_policy: LockedAccountPolicy::AllowAll,
+            auto_unlock_after: None,
+            mandates: HashMap::new(),
+            debit_return_window: None,
+            dual_authorization_threshold: None,
+            dual_authorization_expiry: None,
+            expected_transactions: 0,
+            rules: Vec::new(),
+        }
+    }
+}
+
+/**
+ * Configures the policies of a `Bank`. Every setting defaults to the
+ * behaviour of `Bank::new()`. Amounts are `Decimal` unless the type is
+ * given, e.g. `BankBuilder::<MinorUnits>::default()`.
+ */
+pub struct BankBuilder<M: Money = Decimal> {
+    config: BankConfig<M>,
+    validators: Vec<Box<dyn TxValidator<M>>>,
+    custom_handlers: HashMap<&'static str, CustomHandler<M>>,
+    sinks: Vec<Box<dyn AccountUpdateSink<M>>>,
+}
+
+impl<M: Money> Default for BankBuilder<M> {
+    fn default() -> Self {
+        BankBuilder {
+            config: BankConfig::default(),
+            validators: Vec::new(),
+            custom_handlers: HashMap::new(),
+            sinks: Vec::new(),
+        }
+    }
+}
+
+impl<M: Money> BankBuilder<M> {
+    /**
+     * How far below zero `available` may go on a withdrawal. Defaults to 0.
+     */
+    pub fn overdraft(mut self, limit: M) -> Self {
+        self.config.overdraft = limit;
+        self
+    }
+
+    /**
+     * How long after a transaction it may still be disputed, measured between
+     * the two transactions' timestamps. Transactions without a timestamp are
+     * always disputable. Defaults to no limit.
+     */
+    pub fn dispute_window(mut self, window: Duration) -> Self {
+        self.config.dispute_window = Some(window);
+        self
+    }
+
+    /**
+     * What a dispute does when it would overdraw available funds. Defaults to
+     * `DisputeOverdrawPolicy::Allow`.
+     */
+    pub fn dispute_overdraw_policy(mut self, policy: DisputeOverdrawPolicy) -> Self {
+        self.config.dispute_overdraw_policy = policy;
+        self
+    }
+
+    /**
+     * Whether withdrawals can be disputed. When disabled only their ids are
+     * kept, and disputes referencing them are ignored. Enabled by default.
+     */
+    pub fn disputable_withdrawals(mut self, disputable: bool) -> Self {
+        self.config.disputable_withdrawals = disputable;
+        self
+    }
+
+    /**
+     * Which transactions accounts locked by a chargeback still accept.
+     * Defaults to `LockedAccountPolicy::AllowAll`.
+     */
+    pub fn locked_account_policy(mut self, policy: LockedAccountPolicy) -> Self {
+        self.config.locked_account_policy = policy;
+        self
+    }
+
+    /**
+     * Unlocks accounts whose latest chargeback is at least `after` old, as
+     * measured by the timestamps of incoming transactions. Without it,
+     * locked accounts stay in the review queue until handled manually.
+     */
+    pub fn auto_unlock_after(mut self, after: Duration) -> Self {
+        self.config.auto_unlock_after = Some(after);
+        self
+    }
+
+    /**
+     * Registers a mandate, replacing any with the same id. Direct debits
+     * are refused with `Rejection::NoMandate` unless they name a mandate of
+     * their client that covers the amount.
+     */
+    pub fn mandate(mut self, mandate: Mandate<M>) -> Self {
+        self.config.mandates.insert(mandate.id.clone(), mandate);
+        self
+    }
+
+    /**
+     * How long after a direct debit the client may still send it back,
+     * measured between the two transactions' timestamps like the dispute
+     * window. Defaults to no limit.
+     */
+    pub fn debit_return_window(mut self, window: Duration) -> Self {
+        self.config.debit_return_window = Some(window);
+        self
+    }
+
+    /**
+     * Holds deposits, withdrawals and direct debits above `threshold`
+     * pending until a second person authorizes them with `Bank::authorize` or an `approve`
+     * transaction.
+     */
+    pub fn dual_authorization(mut self, threshold: M) -> Self {
+        self.config.dual_authorization_threshold = Some(threshold);
+        self
+    }
+
+    /**
+     * Declines transactions still waiting for a second authorization once
+     * they are older than `expiry`, as measured by the timestamps of
+     * incoming transactions. Without it they wait indefinitely.
+     */
+    pub fn dual_authorization_expiry(mut self, expiry: Duration) -> Self {
+        self.config.dual_authorization_expiry = Some(expiry);
+        self
+    }
+
+    /**
+     * Pre-sizes the account and transaction stores for about `count`
+     * transactions, so large runs don't repeatedly regrow them. Only a hint;
+     * more transactions are still accepted.
+     */
+    pub fn expected_transactions(mut self, count: usize) -> Self {
+        self.config.expected_transactions = count;
+        self
+    }
+
+    /**
+     * What batch processing does with malformed transactions. Defaults to
+     * `ErrorPolicy::Abort`.
+     */
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.config.error_policy = policy;
+        self
+    }
+
+    /**
+     * Adds a velocity rule on withdrawals. See `risk::VelocityRule`.
+     */
+    pub fn velocity_rule(mut self, rule: VelocityRule<M>) -> Self {
+        self.config.velocity_rules.push(rule);
+        self
+    }
+
+    /**
+     * Caps the amount each client may withdraw per calendar day (UTC), going
+     * by transaction timestamps. Withdrawals without a timestamp are not
+     * counted. Defaults to no limit.
+     */
+    pub fn daily_withdrawal_limit(mut self, limit: M) -> Self {
+        self.config.daily_withdrawal_limit = Some(limit);
+        self
+    }
+
+    /**
+     * Overrides the daily withdrawal limit for one client.
+     */
+    pub fn client_daily_withdrawal_limit(mut self, client_id: u16, limit: M) -> Self {
+        self.client_override_mut(client_id).daily_withdrawal_limit = Some(limit);
+        self
+    }
+
+    /**
+     * Overrides policies for `client_override.client_id`, replacing any
+     * override set for that client before.
+     */
+    pub fn client_override(mut self, client_override: ClientOverride<M>) -> Self {
+        self.config
+            .client_overrides
+            .insert(client_override.client_id, client_override);
+        self
+    }
+
+    /**
+     * Sets the KYC status of one client. Unverified clients cannot withdraw,
+     * and their deposits are capped by `unverified_balance_cap`.
+     */
+    pub fn client_kyc(mut self, client_id: u16, status: KycStatus) -> Self {
+        self.client_override_mut(client_id).kyc = Some(status);
+        self
+    }
+
+    /**
+     * The highest total balance a deposit may bring an unverified client to.
+     * Defaults to no cap.
+     */
+    pub fn unverified_balance_cap(mut self, cap: M) -> Self {
+        self.config.unverified_balance_cap = Some(cap);
+        self
+    }
+
+    fn client_override_mut(&mut self, client_id: u16) -> &mut ClientOverride<M> {
+        self.config
+            .client_overrides
+            .entry(client_id)
+            .or_insert_with(|| ClientOverride::new(client_id))
+    }
+
+    /**
+     * Enables anti-money-laundering reporting, see `Bank::compliance_entries`.
+     */
+    pub fn aml(mut self, config: AmlConfig<M>) -> Self {
+        self.config.aml = Some(config);
+        self
+    }
+
+    /**
+     * Refuses every transaction from these clients with
+     * `Rejection::Denylisted` and reports it in the compliance entries.
+     */
+    pub fn denylist(mut self, client_ids: impl IntoIterator<Item = u16>) -> Self {
+        self.config.denylist.extend(client_ids);
+        self
+    }
+
+    /**
+     * Authorizes `client_ids` to transact on the account of `account_id`.
+     * Their transactions are applied to that account and they can dispute
+     * any of its transactions, while policies such as the daily limit and the
+     * denylist still apply to the client transacting. A client holds a
+     * single account: authorizing it again moves it to the new one.
+     */
+    pub fn joint_account(
+        mut self,
+        account_id: u16,
+        client_ids: impl IntoIterator<Item = u16>,
+    ) -> Self {
+        for client_id in client_ids {
+            self.config.joint_holders.insert(client_id, account_id);
+        }
+        self
+    }
+
+    /**
+     * Registers a validator run before every transaction, after the ones
+     * registered before it.
+     */
+    pub fn validator(mut self, validator: impl TxValidator<M> + 'static) -> Self {
+        self.validators.push(Box::new(validator));
+        self
+    }
+
+    /**
+     * Evaluates `rules` on every transaction that passes validation, after
+     * any rules added before. The first matching rule decides.
+     */
+    pub fn rules(mut self, rules: impl IntoIterator<Item = Rule<M>>) -> Self {
+        self.config.rules.extend(rules);
+        self
+    }
+
+    /**
+     * Handles transactions of type `TransactionType::Custom(name)` with
+     * `handler`, replacing any handler registered for `name` before. Custom
+     * types go through the same validation as built-in ones.
+     */
+    pub fn custom_type(
+        mut self,
+        name: &'static str,
+        handler: impl Fn(&Transaction<M>, &mut CustomContext<M>) -> Result<TxOutcome, BankError>
+            + 'static,
+    ) -> Self {
+        self.custom_handlers.insert(name, Box::new(handler));
+        self
+    }
+
+    /**
+     * Calls `sink` with every committed account change; may be called more
+     * than once to add several sinks.
+     */
+    pub fn update_sink(mut self, sink: impl AccountUpdateSink<M> + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    pub fn build(self) -> Bank<M> {
+        let expected = self.config.expected_transactions;
+        Bank {
+            accounts: RefCell::new(BTreeMap::new()),
+            transactions: RefCell::new(TransactionStore::with_capacity(expected)),
+            dispute_holds: RefCell::new(HashMap::new()),
+            withdrawal_ids: RefCell::new(HashSet::new()),
+            review: RefCell::new(ReviewQueue::new(self.config.auto_unlock_after)),
+            external_ids: RefCell::new(HashMap::new()),
+            rules: RefCell::new(RuleEngine::new(self.config.rules.clone())),
+            velocity: RefCell::new(VelocityTracker::new(self.config.velocity_rules.clone())),
+            compliance: RefCell::new(ComplianceMonitor::new(self.config.aml)),
+            daily_withdrawals: RefCell::new(HashMap::new()),
+            negative_available_alerts: Cell::new(0),
+            watermark: Cell::new(None),
+            config: self.config,
+            validators: self.validators,
+            custom_handlers: self.custom_handlers,
+            sinks: RefCell::new(self.sinks),
+            sink_failures: Cell::new(0),
+            dispute_states: RefCell::new(HashMap::new()),
+            snapshot: RefCell::new(None),
+            staging: Cell::new(false),
+            staged_batches: RefCell::new(BTreeMap::new()),
+            next_batch_id: Cell::new(0),
+            awaiting_authorization: RefCell::new(BTreeMap::new()),
+            expired_authorizations: Cell::new(0),
+            pending_fees: RefCell::new(HashMap::new()),
+            expiring: Cell::new(false),
+            #[cfg(feature = "invariant-checks")]
+            balance_peaks: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+/**
+ * The engine, with amounts of type `M`. Any arithmetic that would leave an
+ * amount out of range for `M` makes the transaction malformed.
+ */
+pub struct Bank<M: Money = Decimal> {
+    /// By client id, which orders the report.
+    accounts: RefCell<BTreeMap<u16, Account<M>>>,
+    transactions: RefCell<TransactionStore<M>>,
+    dispute_holds: RefCell<HashMap<u32, M>>,
+    withdrawal_ids: RefCell<HashSet<u32>>,
+    review: RefCell<ReviewQueue>,
+    external_ids: RefCell<HashMap<String, u32>>,
+    rules: RefCell<RuleEngine<M>>,
+    velocity: RefCell<VelocityTracker<M>>,
+    compliance: RefCell<ComplianceMonitor<M>>,
+    daily_withdrawals: RefCell<HashMap<u16, (u64, M)>>,
+    negative_available_alerts: Cell<usize>,
+    /// Newest transaction timestamp seen; the bank's notion of "now".
+    watermark: Cell<Option<u64>>,
+    config: BankConfig<M>,
+    validators: Vec<Box<dyn TxValidator<M>>>,
+    custom_handlers: HashMap<&'static str, CustomHandler<M>>,
+    sinks: RefCell<Vec<Box<dyn AccountUpdateSink<M>>>>,
+    sink_failures: Cell<usize>,
+    /// Latest dispute of each transaction ever disputed.
+    dispute_states: RefCell<HashMap<u32, DisputeState>>,
+    /// Taken by `snapshot` since the last transaction, if any.
+    snapshot: RefCell<Option<Arc<BankSnapshot<M>>>>,
+    /// Set while `stage_batch` runs, to hold every deposit and withdrawal.
+    staging: Cell<bool>,
+    /// Client and id of the pending transactions of each staged batch.
+    staged_batches: RefCell<BTreeMap<u32, Vec<(u16, u32)>>>,
+    next_batch_id: Cell<u32>,
+    /// Client and hold time of the transactions waiting for a second
+    /// authorization, by id.
+    awaiting_authorization: RefCell<BTreeMap<u32, (u16, Option<u64>)>>,
+    expired_authorizations: Cell<usize>,
+    /// Fee rules matched by pending transactions, by id; charged when the
+    /// transaction is approved.
+    pending_fees: RefCell<HashMap<u32, M>>,
+    /// Set while `expire_authorizations` runs.
+    expiring: Cell<bool>,
+    /// Largest balance each account has held, for `assert_invariants`.
+    #[cfg(feature = "invariant-checks")]
+    balance_peaks: RefCell<HashMap<u16, f64>>,
+}
+
+impl<M: Money> Default for Bank<M> {
+    fn default() -> Self {
+        BankBuilder::default().build()
+    }
+}
+
+impl Bank {
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> BankBuilder {
+        BankBuilder::default()
+    }
+}
+
+/**
+ * Each account belongs to a client and is keyed by its id; the joint
+ * holders of an account transact on it under their own ids.
+ */
+impl<M: Money> Bank<M> {
+    /**
+     * Applies `batch_tx` in order and summarizes what happened. Stops at the
+     * first malformed transaction.
+     */
+    pub fn batch_process(&self, batch_tx: Vec<Transaction<M>>) -> Result<BatchSummary, BankError> {
+        let started = Instant::now();
+        let mut summary = BatchSummary::default();
+        for tx in batch_tx {
+            let client_id = tx.client_id;
+            let outcome = self.process_with_policy(tx)?;
+            summary.record(client_id, outcome);
+        }
+        summary.duration = started.elapsed();
+        Ok(summary)
+    }
+
+    /**
+     * Applies `batch_tx` like `batch_process`, except that deposits and
+     * withdrawals are held pending instead of moving funds: deposits wait in
+     * `held`, and withdrawals reserve their amount there. The whole batch is
+     * then committed with `approve_batch` or discarded with `reject_batch`,
+     * e.g. once a second person has reviewed a partner file. Other
+     * transaction types could not be undone, and are refused as
+     * `Rejection::NotStageable`.
+     *
+     * Stops at the first malformed transaction, declining the ones it held
+     * so far.
+     */
+    pub fn stage_batch(&self, batch_tx: Vec<Transaction<M>>) -> Result<StagedBatch, BankError> {
+        let started = Instant::now();
+        let mut summary = BatchSummary::default();
+        let mut pending = Vec::new();
+        self.staging.set(true);
+        let result: Result<(), BankError> = batch_tx.into_iter().try_for_each(|tx| {
+            let (client_id, tx_id) = (tx.client_id, tx.id);
+            let stageable = matches!(
+                tx.tx_type,
+                TransactionType::Deposit | TransactionType::Withdrawal
+            );
+            let outcome = if stageable {
+                self.process_with_policy(tx)?
+            } else {
+                TxOutcome::Rejected(Rejection::NotStageable)
+            };
+            if outcome == TxOutcome::Pending {
+                pending.push((client_id, tx_id));
+            }
+            summary.record(client_id, outcome);
+            Ok(())
+        });
+        self.staging.set(false);
+        if let Err(error) = result {
+            // A transaction whose decline is refused stays pending, and can
+            // still be declined by id.
+            for (client_id, tx_id) in pending {
+                let _ = self.process_transaction(Transaction::decline(client_id, tx_id));
+            }
+            return Err(error);
+        }
+        summary.duration = started.elapsed();
+
+        let id = self.next_batch_id.get() + 1;
+        self.next_batch_id.set(id);
+        self.staged_batches.borrow_mut().insert(id, pending);
+        Ok(StagedBatch { id, summary })
+    }
+
+    /**
+     * Applies every transaction of the staged batch `id` still pending, as
+     * if each was approved. Fails with `ErrorCode::UnknownBatch` for a batch
+     * that was never staged or already settled. A transaction whose
+     * approval is refused, e.g. on an account frozen since, stays pending
+     * in the batch, which can be approved or rejected again.
+     */
+    pub fn approve_batch(&self, id: u32) -> Result<BatchSummary, BankError> {
+        self.settle_batch(id, Transaction::approve)
+    }
+
+    /**
+     * Reverts every transaction of the staged batch `id` still pending, as
+     * if each was declined.
+     */
+    pub fn reject_batch(&self, id: u32) -> Result<BatchSummary, BankError> {
+        self.settle_batch(id, Transaction::decline)
+    }
+
+    fn settle_batch(
+        &self,
+        id: u32,
+        settlement: fn(u16, u32) -> Transaction<M>,
+    ) -> Result<BatchSummary, BankError> {
+        let pending = self
+            .staged_batches
+            .borrow()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| {
+                BankError::new(ErrorCode::UnknownBatch, format!("No staged batch {}", id))
+            })?;
+        let result = self.batch_process(
+            pending
+                .iter()
+                .map(|&(client_id, tx_id)| settlement(client_id, tx_id))
+                .collect(),
+        );
+        let unsettled: Vec<_> = pending
+            .into_iter()
+            .filter(|&(_, tx_id)| {
+                matches!(
+                    self.transactions.borrow().get(tx_id),
+                    Some((_, TransactionStatus::Pending))
+                )
+            })
+            .collect();
+        let mut staged_batches = self.staged_batches.borrow_mut();
+        if unsettled.is_empty() {
+            staged_batches.remove(&id);
+        } else {
+            staged_batches.insert(id, unsettled);
+        }
+        result
+    }
+
+    /**
+     * Same as `batch_process`, but hands the outcome of every transaction to
+     * `on_outcome` as soon as it is applied, so callers can ack/nack them
+     * individually.
+     */
+    pub fn batch_process_with<F>(
+        &self,
+        batch_tx: Vec<Transaction<M>>,
+        mut on_outcome: F,
+    ) -> Result<(), BankError>
+    where
+        F: FnMut(u32, TxOutcome),
+    {
+        for tx in batch_tx {
+            let tx_id = tx.id;
+            let outcome = self.process_with_policy(tx)?;
+            on_outcome(tx_id, outcome);
+        }
+        Ok(())
+    }
+
+    /**
+     * Same as `batch_process_with`, but pulls transactions from `source` one
+     * at a time. Stops at the first entry the source cannot read.
+     */
+    pub fn process_source<S, F>(
+        &self,
+        source: &mut S,
+        mut on_outcome: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        S: TransactionSource<M> + ?Sized,
+        F: FnMut(&ProcessedTx, TxOutcome),
+    {
+        while let Some(tx) = source.next_tx() {
+            let tx = tx?;
+            let processed = ProcessedTx {
+                tx_id: tx.id,
+                client_id: tx.client_id,
+                line: source.line(),
+            };
+            let outcome = self.process_with_policy(tx)?;
+            on_outcome(&processed, outcome);
+        }
+        Ok(())
+    }
+
+    /**
+     * Applies the transactions of `stream` as they arrive, e.g. from a
+     * socket or a message queue, and summarizes what happened. Stops at the
+     * first malformed transaction.
+     */
+    #[cfg(feature = "tokio")]
+    pub async fn process_stream<S>(&self, stream: S) -> Result<BatchSummary, BankError>
+    where
+        S: Stream<Item = Transaction<M>>,
+    {
+        let started = Instant::now();
+        let mut stream = pin!(stream);
+        let mut summary = BatchSummary::default();
+        while let Some(tx) = stream.next().await {
+            let client_id = tx.client_id;
+            let outcome = self.process_with_policy(tx)?;
+            summary.record(client_id, outcome);
+        }
+        summary.duration = started.elapsed();
+        Ok(summary)
+    }
+
+    /**
+     * Same as `process_source`, for a stream of transactions and read
+     * errors such as `async_input::csv_stream`. Input lines are not known.
+     */
+    #[cfg(feature = "tokio")]
+    pub async fn process_source_stream<S, F>(
+        &self,
+        stream: S,
+        mut on_outcome: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        S: Stream<Item = Result<Transaction<M>, Box<dyn Error>>>,
+        F: FnMut(&ProcessedTx, TxOutcome),
+    {
+        let mut stream = pin!(stream);
+        while let Some(tx) = stream.next().await {
+            let tx = tx?;
+            let processed = ProcessedTx {
+                tx_id: tx.id,
+                client_id: tx.client_id,
+                line: None,
+            };
+            let outcome = self.process_with_policy(tx)?;
+            on_outcome(&processed, outcome);
+        }
+        Ok(())
+    }
+
+    /**
+     * Applies the CSV transactions read from `reader` as they are parsed,
+     * so memory use does not grow with the size of the input. Type names
+     * must be canonical. Stops at the first row that cannot be read.
+     */
+    pub fn process_from_reader<R: io::Read>(
+        &self,
+        reader: R,
+    ) -> Result<BatchSummary, Box<dyn Error>> {
+        let started = Instant::now();
+        let mut source = CsvSource::new(reader, TypeNames::default())?;
+        let mut summary = BatchSummary::default();
+        self.process_source(&mut source, |processed, outcome| {
+            summary.record(processed.client_id, outcome)
+        })?;
+        summary.duration = started.elapsed();
+        Ok(summary)
+    }
+
+    /**
+     * Same as `process_source`, for reprocessing history on top of the
+     * current state: deposits, withdrawals and direct debits whose id is
+     * already in the history were applied before and are skipped, without
+     * an outcome. Returns the number of transactions skipped.
+     *
+     * Disputes, resolves and chargebacks can't be told apart from new ones
+     * and are processed again. One that would move its transaction to the
+     * state it is already in is ignored, but a replayed dispute of a
+     * transaction resolved since holds its funds again, raising its alerts
+     * a second time, until the replayed resolve releases them.
+     */
+    pub fn backfill_source<S, F>(
+        &self,
+        source: &mut S,
+        mut on_outcome: F,
+    ) -> Result<usize, Box<dyn Error>>
+    where
+        S: TransactionSource<M> + ?Sized,
+        F: FnMut(&ProcessedTx, TxOutcome),
+    {
+        let mut skipped = 0;
+        while let Some(tx) = source.next_tx() {
+            let tx = tx?;
+            if self.is_applied(&tx) {
+                skipped += 1;
+                continue;
+            }
+            let processed = ProcessedTx {
+                tx_id: tx.id,
+                client_id: tx.client_id,
+                line: source.line(),
+            };
+            let outcome = self.process_with_policy(tx)?;
+            on_outcome(&processed, outcome);
+        }
+        Ok(skipped)
+    }
+
+    fn is_applied(&self, tx: &Transaction<M>) -> bool {
+        matches!(
+            tx.tx_type,
+            TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::DirectDebit
+        ) && (self.transactions.borrow().contains_key(tx.id)
+            || self.withdrawal_ids.borrow().contains(&tx.id))
+    }
+
+    /**
+     * Applies transactions in chunks of `chunk_size`, calling `on_chunk` with the
+     * number of transactions processed so far after each chunk. Returning
+     * `ControlFlow::Break` from the callback stops processing early.
+     */
+    pub fn process_chunks<I, F>(
+        &self,
+        transactions: I,
+        chunk_size: usize,
+        mut on_chunk: F,
+    ) -> Result<(), BankError>
+    where
+        I: IntoIterator<Item = Transaction<M>>,
+        F: FnMut(usize) -> ControlFlow<()>,
+    {
+        if chunk_size == 0 {
+            return Err(BankError::new(
+                ErrorCode::InvalidChunkSize,
+                INVALID_CHUNK_SIZE,
+            ));
+        }
+        let mut processed = 0;
+        let mut in_chunk = 0;
+        for tx in transactions {
+            self.process_with_policy(tx)?;
+            processed += 1;
+            in_chunk += 1;
+            if in_chunk == chunk_size {
+                in_chunk = 0;
+                if on_chunk(processed).is_break() {
+                    return Ok(());
+                }
+            }
+        }
+        if in_chunk > 0 {
+            let _ = on_chunk(processed);
+        }
+        Ok(())
+    }
+
+    fn process_with_policy(&self, tx: Transaction<M>) -> Result<TxOutcome, BankError> {
+        match self.process_transaction(tx) {
+            Err(_) if self.config.error_policy == ErrorPolicy::Skip => {
+                Ok(TxOutcome::RejectedMalformed)
+            }
+            result => result,
+        }
+    }
+
+    /**
+     * Applies a single transaction and reports what happened to it. Malformed
+     * transactions (e.g. a deposit without an amount) are returned as `Err`.
+     * Never panics, whatever the input: every failure is a `BankError`.
+     */
+    pub fn process_transaction(&self, tx: Transaction<M>) -> Result<TxOutcome, BankError> {
+        self.snapshot.replace(None);
+        if let Some(now) = self.advance_watermark(tx.timestamp) {
+            self.auto_unlock(now);
+            self.expire_authorizations(now)?;
+        }
+        let account_id = self.account_id(tx.client_id);
+        let existing = self.get_account(account_id);
+        let is_new_account = existing.is_none();
+        let mut account = existing.unwrap_or_else(|| self.open_account(account_id));
+        let before = account.view();
+        let tx_id = tx.id;
+        let external_id = tx.external_id.clone();
+
+        let result = match self.validate_transaction(&account, &tx) {
+            Ok(()) => self.apply_with_rules(&mut account, tx),
+            Err(rejection) => {
+                if rejection == Rejection::Denylisted {
+                    self.compliance.borrow_mut().record_denylisted(&tx);
+                }
+                Ok(TxOutcome::Rejected(rejection))
+            }
+        };
+        #[cfg(feature = "invariant-checks")]
+        account.assert_invariants(tx_id, self.balance_peak(&account));
+        let after = account.view();
+        if result.is_ok() || !is_new_account {
+            self.accounts
+                .borrow_mut()
+                .insert(account.client_id, account);
+        }
+        if after != before {
+            self.notify_sinks(tx_id, before, after);
+        }
+        if let Some(external_id) = external_id {
+            self.index_external_id(tx_id, external_id);
+        }
+        result
+    }
+
+    /**
+     * The largest balance `account` has held so far, including now.
+     */
+    #[cfg(feature = "invariant-checks")]
+    fn balance_peak(&self, account: &Account<M>) -> f64 {
+        let mut peaks = self.balance_peaks.borrow_mut();
+        let peak = peaks.entry(account.client_id).or_insert(0.0);
+        for balance in [account.available, account.held, account.total] {
+            *peak = peak.max(balance.to_f64().abs());
+        }
+        *peak
+    }
+
+    fn notify_sinks(&self, tx_id: u32, previous: AccountView<M>, account: AccountView<M>) {
+        let update = AccountUpdate {
+            tx_id,
+            account,
+            previous,
+        };
+        for sink in self.sinks.borrow_mut().iter_mut() {
+            if sink.update(&update).is_err() {
+                self.sink_failures.set(self.sink_failures.get() + 1);
+            }
+        }
+    }
+
+    /**
+     * How many times an update sink returned an error so far.
+     */
+    pub fn sink_failures(&self) -> usize {
+        self.sink_failures.get()
+    }
+
+    /**
+     * The newest transaction timestamp processed so far, or `None` before
+     * the first timestamped transaction. Time-based policies measure time by
+     * it rather than by the clock, so replaying an input gives the results
+     * of the original run, and a transaction arriving late can't move time
+     * backwards.
+     */
+    pub fn watermark(&self) -> Option<u64> {
+        self.watermark.get()
+    }
+
+    fn advance_watermark(&self, timestamp: Option<u64>) -> Option<u64> {
+        let watermark = self.watermark.get().max(timestamp);
+        self.watermark.set(watermark);
+        watermark
+    }
+
+    /**
+     * Indexes `external_id` if transaction `tx_id` was stored with it, so
+     * disputes and rejected duplicates don't overwrite the index.
+     */
+    fn index_external_id(&self, tx_id: u32, external_id: String) {
+        let stored = matches!(
+            self.transactions.borrow().get(tx_id),
+            Some((tx, _)) if tx.external_id.as_ref() == Some(&external_id)
+        );
+        if stored {
+            self.external_ids.borrow_mut().insert(external_id, tx_id);
+        }
+    }
+
+    fn auto_unlock(&self, now: u64) {
+        let due = self.review.borrow_mut().take_due(now);
+        if due.is_empty() {
+            return;
+        }
+        for account in self.accounts.borrow_mut().values_mut() {
+            if due.contains(&account.client_id) {
+                account.locked = false;
+            }
+        }
+    }
+
+    /**
+     * Declines the transactions that waited for a second authorization for
+     * longer than the configured expiry. A transaction whose decline is
+     * refused, e.g. on a frozen account, stays waiting and is tried again
+     * as time moves on.
+     */
+    fn expire_authorizations(&self, now: u64) -> Result<(), BankError> {
+        let Some(expiry) = self.config.dual_authorization_expiry else {
+            return Ok(());
+        };
+        // The declines below come through here again.
+        if self.expiring.replace(true) {
+            return Ok(());
+        }
+        let expired: Vec<(u32, u16)> = self
+            .awaiting_authorization
+            .borrow()
+            .iter()
+            .filter(|(_, (_, held_at))| {
+                held_at.is_some_and(|held_at| now.saturating_sub(held_at) > expiry.as_secs())
+            })
+            .map(|(&tx_id, &(client_id, _))| (tx_id, client_id))
+            .collect();
+        let result = expired.into_iter().try_for_each(|(tx_id, client_id)| {
+            // A decline that is applied stops the wait for authorization.
+            if self.process_transaction(Transaction::decline(client_id, tx_id))?
+                == TxOutcome::Applied
+            {
+                self.expired_authorizations
+                    .set(self.expired_authorizations.get() + 1);
+            }
+            Ok(())
+        });
+        self.expiring.set(false);
+        result
+    }
+
+    /**
+     * Gives the second authorization to the pending transaction `tx_id`,
+     * applying it like an `approve` transaction. Transactions that are not
+     * waiting for one are ignored as `IgnoredUnknownTransaction`.
+     */
+    pub fn authorize(&self, tx_id: u32) -> Result<TxOutcome, BankError> {
+        let awaiting = self.awaiting_authorization.borrow().get(&tx_id).copied();
+        match awaiting {
+            Some((client_id, _)) => {
+                self.process_transaction(Transaction::approve(client_id, tx_id))
+            }
+            None => Ok(TxOutcome::IgnoredUnknownTransaction),
+        }
+    }
+
+    /**
+     * How many transactions were declined so far because their second
+     * authorization did not come in time.
+     */
+    pub fn expired_authorizations(&self) -> usize {
+        self.expired_authorizations.get()
+    }
+
+    fn needs_authorization(&self, tx: &Transaction<M>) -> bool {
+        is_holdable(tx.tx_type)
+            && self
+                .config
+                .dual_authorization_threshold
+                .zip(tx.amount)
+                .is_some_and(|(threshold, amount)| amount > threshold)
+    }
+
+    fn validate_transaction(
+        &self,
+        account: &Account<M>,
+        tx: &Transaction<M>,
+    ) -> Result<(), Rejection> {
+        let positive = |amount: M| amount.partial_cmp(&M::zero()) == Some(Ordering::Greater);
+        if tx.amount.is_some_and(|amount| !positive(amount)) {
+            return Err(Rejection::NonPositiveAmount);
+        }
+        if self.config.denylist.contains(&tx.client_id) {
+            return Err(Rejection::Denylisted);
+        }
+        if account.frozen && tx.tx_type != TransactionType::Unfreeze {
+            return Err(Rejection::AccountFrozen);
+        }
+        self.check_locked(account, tx)?;
+        self.check_kyc(account, tx)?;
+        self.check_mandate(tx)?;
+        let view = account.view();
+        let transactions = self.transactions.borrow();
+        let withdrawal_ids = self.withdrawal_ids.borrow();
+        let history = TxHistory {
+            transactions: &transactions,
+            withdrawal_ids: &withdrawal_ids,
+        };
+        self.validators
+            .iter()
+            .try_for_each(|validator| validator.validate(tx, &view, &history))
+    }
+
+    fn apply_with_rules(
+        &self,
+        account: &mut Account<M>,
+        tx: Transaction<M>,
+    ) -> Result<TxOutcome, BankError> {
+        let kyc = self.client_override(tx.client_id).and_then(|o| o.kyc);
+        let matched = self.rules.borrow_mut().evaluate(&tx, kyc);
+        if let Some((position, RuleAction::Reject)) = matched {
+            return Ok(TxOutcome::Rejected(Rejection::Rule(position)));
+        }
+        let authorization = self
+            .needs_authorization(&tx)
+            .then(|| (tx.id, tx.client_id, tx.timestamp.or(self.watermark.get())));
+        let review = self.staging.get()
+            || authorization.is_some()
+            || matches!(matched, Some((_, RuleAction::Hold)))
+            || self.needs_review(account, &tx);
+        let held = review && is_holdable(tx.tx_type);
+        // A held transaction pays its fee when it is approved.
+        if let Some((_, RuleAction::Fee(fee))) = matched {
+            if !held && !self.can_pay_fee(account, &tx, fee) {
+                return Ok(TxOutcome::RejectedInsufficientFunds);
+            }
+        }
+
+        let tx_id = tx.id;
+        let outcome = self.apply_with_risk_checks(account, tx, review)?;
+        if let (Some((tx_id, client_id, held_at)), TxOutcome::Pending) = (authorization, outcome) {
+            self.awaiting_authorization
+                .borrow_mut()
+                .insert(tx_id, (client_id, held_at));
+        }
+        match (matched, outcome) {
+            (Some((position, RuleAction::Flag)), TxOutcome::Applied) => {
+                Ok(TxOutcome::Flagged(RiskFlag::Rule(position)))
+            }
+            (Some((_, RuleAction::Fee(fee))), TxOutcome::Pending) => {
+                self.pending_fees.borrow_mut().insert(tx_id, fee);
+                Ok(outcome)
+            }
+            (Some((_, RuleAction::Fee(fee))), TxOutcome::Applied | TxOutcome::Flagged(_)) => {
+                // `can_pay_fee` made this draw on a copy of the account
+                // before the transaction was applied.
+                account.draw(fee)?;
+                Ok(outcome)
+            }
+            _ => Ok(outcome),
+        }
+    }
+
+    /**
+     * Whether `fee` could be drawn once `tx` has moved its amount, so that
+     * a transaction is refused before it is applied rather than left
+     * without its fee.
+     */
+    fn can_pay_fee(&self, account: &Account<M>, tx: &Transaction<M>, fee: M) -> bool {
+        let mut after = account.clone();
+        let moved = match (tx.tx_type, tx.amount) {
+            (TransactionType::Deposit, Some(amount)) => after.deposit(amount),
+            (TransactionType::Withdrawal | TransactionType::DirectDebit, Some(amount)) => {
+                after.draw(amount)
+            }
+            _ => Ok(()),
+        };
+        moved.is_ok() && self.can_withdraw(&after, fee) && after.draw(fee).is_ok()
+    }
+
+    fn needs_review(&self, account: &Account<M>, tx: &Transaction<M>) -> bool {
+        let view = account.view();
+        let transactions = self.transactions.borrow();
+        let withdrawal_ids = self.withdrawal_ids.borrow();
+        let history = TxHistory {
+            transactions: &transactions,
+            withdrawal_ids: &withdrawal_ids,
+        };
+        self.validators
+            .iter()
+            .any(|validator| validator.needs_review(tx, &view, &history))
+    }
+
+    fn check_locked(&self, account: &Account<M>, tx: &Transaction<M>) -> Result<(), Rejection> {
+        if !account.locked {
+            return Ok(());
+        }
+        match (self.config.locked_account_policy, tx.tx_type) {
+            (LockedAccountPolicy::BlockAll, TransactionType::Deposit)
+            | (LockedAccountPolicy::BlockAll, TransactionType::Withdrawal)
+            | (LockedAccountPolicy::AllowDeposits, TransactionType::Withdrawal)
+            | (_, TransactionType::DirectDebit) => Err(Rejection::AccountLocked),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_mandate(&self, tx: &Transaction<M>) -> Result<(), Rejection> {
+        if tx.tx_type != TransactionType::DirectDebit {
+            return Ok(());
+        }
+        let covered = tx
+            .mandate
+            .as_ref()
+            .and_then(|id| self.config.mandates.get(id))
+            .zip(tx.amount)
+            .is_some_and(|(mandate, amount)| mandate.authorizes(tx.client_id, amount));
+        if covered {
+            Ok(())
+        } else {
+            Err(Rejection::NoMandate)
+        }
+    }
+
+    fn check_kyc(&self, account: &Account<M>, tx: &Transaction<M>) -> Result<(), Rejection> {
+        let kyc = self.client_override(tx.client_id).and_then(|o| o.kyc);
+        if kyc != Some(KycStatus::Unverified) {
+            return Ok(());
+        }
+        match (tx.tx_type, tx.amount, self.config.unverified_balance_cap) {
+            (TransactionType::Withdrawal, _, _) => Err(Rejection::KycWithdrawal),
+            (TransactionType::Deposit, Some(amount), Some(cap))
+                if account
+                    .total
+                    .checked_add(amount)
+                    .is_none_or(|total| total > cap) =>
+            {
+                Err(Rejection::KycDepositCap)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn apply_with_risk_checks(
+        &self,
+        account: &mut Account<M>,
+        tx: Transaction<M>,
+        review: bool,
+    ) -> Result<TxOutcome, BankError> {
+        let moves_funds = matches!(
+            tx.tx_type,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        );
+        let observed = moves_funds.then(|| tx.clone());
+        let withdrawal = self.velocity.borrow().track(&tx);
+        let breach = withdrawal.and_then(|w| self.velocity.borrow_mut().check(&w));
+        match breach {
+            Some((RiskAction::Block, flag)) => return Ok(TxOutcome::Blocked(flag)),
+            Some((RiskAction::Review, _)) if moves_funds => return self.hold_pending(account, tx),
+            _ if review && is_holdable(tx.tx_type) => return self.hold_pending(account, tx),
+            _ => {}
+        }
+
+        let outcome = self.apply_transaction(account, tx)?;
+        if outcome != TxOutcome::Applied {
+            return Ok(outcome);
+        }
+        if let Some(withdrawal) = withdrawal {
+            self.velocity.borrow_mut().record(withdrawal);
+        }
+        if let Some(observed) = observed {
+            self.compliance.borrow_mut().observe(&observed);
+        }
+        Ok(breach.map_or(outcome, |(_, flag)| TxOutcome::Flagged(flag)))
+    }
+
+    fn apply_transaction(
+        &self,
+        account: &mut Account<M>,
+        tx: Transaction<M>,
+    ) -> Result<TxOutcome, BankError> {
+        let tx_id = tx.id;
+
+        let outcome = match tx.tx_type {
+            TransactionType::Deposit => {
+                let to_deposit = tx.amount.ok_or_else(missing_amount)?;
+                account.deposit(to_deposit)?;
+                self.transactions
+                    .borrow_mut()
+                    .insert(tx_id, (tx, TransactionStatus::Processed));
+                TxOutcome::Applied
+            }
+            TransactionType::Withdrawal => {
+                let to_withdraw = tx.amount.ok_or_else(missing_amount)?;
+
+                if self.exceeds_daily_limit(&tx, to_withdraw) {
+                    TxOutcome::RejectedDailyLimit
+                } else if self.can_withdraw(account, to_withdraw) {
+                    account.draw(to_withdraw)?;
+                    self.record_daily_withdrawal(&tx, to_withdraw);
+                    if self.config.disputable_withdrawals {
+                        self.transactions
+                            .borrow_mut()
+                            .insert(tx_id, (tx, TransactionStatus::Processed));
+                    } else {
+                        self.withdrawal_ids.borrow_mut().insert(tx_id);
+                    }
+                    TxOutcome::Applied
+                } else {
+                    TxOutcome::RejectedInsufficientFunds
+                }
+            }
+            TransactionType::Dispute if account.outstanding.is_some() => {
+                TxOutcome::IgnoredCreditNotDisputable
+            }
+            TransactionType::Dispute => {
+                match self.get_transaction_with_status(
+                    account,
+                    &tx_id,
+                    &[TransactionStatus::Processed, TransactionStatus::Resolved],
+                    TransactionStatus::Disputed,
+                ) {
+                    Ok(target_tx) if self.is_outside_dispute_window(&target_tx.0, &tx) => {
+                        self.transactions.borrow_mut().insert(tx_id, target_tx);
+                        TxOutcome::IgnoredDisputeWindowExpired
+                    }
+                    Ok(target_tx) => {
+                        let outcome = self.hold_disputed(account, target_tx)?;
+                        if outcome != TxOutcome::RejectedDisputeOverdraw {
+                            self.open_dispute(&tx);
+                        }
+                        outcome
+                    }
+                    Err(outcome) => outcome,
+                }
+            }
+            TransactionType::Resolve => {
+                match self.get_transaction_with_status(
+                    account,
+                    &tx_id,
+                    &[TransactionStatus::Disputed],
+                    TransactionStatus::Resolved,
+                ) {
+                    Ok(mut target_tx) => {
+                        let tx_amount = self.dispute_hold(&target_tx)?;
+                        self.restore_on_error(account.release(tx_amount), &target_tx)?;
+                        self.dispute_holds.borrow_mut().remove(&tx_id);
+                        target_tx.1 = TransactionStatus::Resolved;
+                        self.transactions.borrow_mut().insert(tx_id, target_tx);
+                        self.close_dispute(&tx, TransactionStatus::Resolved);
+                        TxOutcome::Applied
+                    }
+                    Err(outcome) => outcome,
+                }
+            }
+            TransactionType::Chargeback => {
+                match self.get_transaction_with_status(
+                    account,
+                    &tx_id,
+                    &[TransactionStatus::Disputed],
+                    TransactionStatus::ChargedBack,
                 ) {
                     Ok(mut target_tx) => {
-                        let tx_amount = target_tx
-                            .0
-                            .amount
-                            .expect(INVALID_TRANSACTION_DATA_NO_AMOUNT);
-                        account.held -= tx_amount;
-                        account.available += tx_amount;
-                        target_tx.1 = TransactionStatus::Processed;
+                        let tx_amount = self.dispute_hold(&target_tx)?;
+                        self.restore_on_error(account.remove_held(tx_amount), &target_tx)?;
+                        self.dispute_holds.borrow_mut().remove(&tx_id);
+                        account.locked = true;
+                        self.review.borrow_mut().enqueue(&tx);
+                        target_tx.1 = TransactionStatus::ChargedBack;
+                        self.transactions.borrow_mut().insert(tx_id, target_tx);
+                        self.close_dispute(&tx, TransactionStatus::ChargedBack);
+                        TxOutcome::Applied
+                    }
+                    Err(outcome) => outcome,
+                }
+            }
+            TransactionType::Freeze => {
+                account.frozen = true;
+                TxOutcome::Applied
+            }
+            TransactionType::Unfreeze => {
+                account.frozen = false;
+                TxOutcome::Applied
+            }
+            TransactionType::Custom(name) => {
+                let handler = self.custom_handlers.get(name).ok_or_else(|| {
+                    BankError::new(
+                        ErrorCode::MissingHandler,
+                        format!("No handler for transaction type {:?}", name),
+                    )
+                })?;
+                let mut transactions = self.transactions.borrow_mut();
+                let mut context = CustomContext {
+                    account,
+                    transactions: &mut transactions,
+                };
+                handler(&tx, &mut context)?
+            }
+            TransactionType::DirectDebit => {
+                let to_pull = tx.amount.ok_or_else(missing_amount)?;
+                if self.can_withdraw(account, to_pull) {
+                    account.draw(to_pull)?;
+                    self.transactions
+                        .borrow_mut()
+                        .insert(tx_id, (tx, TransactionStatus::Processed));
+                    TxOutcome::Applied
+                } else {
+                    TxOutcome::RejectedInsufficientFunds
+                }
+            }
+            TransactionType::DebitReturn => {
+                match self.get_transaction_with_status(
+                    account,
+                    &tx_id,
+                    &[TransactionStatus::Processed],
+                    TransactionStatus::Returned,
+                ) {
+                    Ok(target_tx) if target_tx.0.tx_type != TransactionType::DirectDebit => {
                         self.transactions.borrow_mut().insert(tx_id, target_tx);
+                        TxOutcome::IgnoredInvalidState
                     }
-                    Err(e) => {
-                        eprintln!("{}", e);
+                    Ok(target_tx) if self.is_outside_return_window(&target_tx.0, &tx) => {
+                        self.transactions.borrow_mut().insert(tx_id, target_tx);
+                        TxOutcome::IgnoredReturnWindowExpired
+                    }
+                    Ok(mut target_tx) => {
+                        let amount = self.restore_on_error(
+                            target_tx.0.amount.ok_or_else(missing_amount),
+                            &target_tx,
+                        )?;
+                        self.restore_on_error(account.deposit(amount), &target_tx)?;
+                        target_tx.1 = TransactionStatus::Returned;
+                        self.transactions.borrow_mut().insert(tx_id, target_tx);
+                        TxOutcome::Applied
                     }
+                    Err(outcome) => outcome,
+                }
+            }
+            TransactionType::Approve | TransactionType::Decline => {
+                let target_status = if tx.tx_type == TransactionType::Approve {
+                    TransactionStatus::Processed
+                } else {
+                    TransactionStatus::Declined
                 };
+                match self.get_transaction_with_status(
+                    account,
+                    &tx_id,
+                    &[TransactionStatus::Pending],
+                    target_status,
+                ) {
+                    Ok(target_tx) => {
+                        let outcome = self.settle_pending(account, target_tx, target_status)?;
+                        if outcome == TxOutcome::Applied {
+                            self.awaiting_authorization.borrow_mut().remove(&tx_id);
+                        }
+                        outcome
+                    }
+                    Err(outcome) => outcome,
+                }
+            }
+        };
+        Ok(outcome)
+    }
+
+    /**
+     * Holds a deposit, withdrawal or direct debit for manual review: its
+     * amount sits in `held` until it is approved or declined.
+     */
+    fn hold_pending(
+        &self,
+        account: &mut Account<M>,
+        tx: Transaction<M>,
+    ) -> Result<TxOutcome, BankError> {
+        let amount = tx.amount.ok_or_else(missing_amount)?;
+        if tx.tx_type != TransactionType::Deposit {
+            if !self.can_withdraw(account, amount) {
+                return Ok(TxOutcome::RejectedInsufficientFunds);
+            }
+            account.hold(amount)?;
+        } else {
+            account.hold_incoming(amount)?;
+        }
+        self.transactions
+            .borrow_mut()
+            .insert(tx.id, (tx, TransactionStatus::Pending));
+        Ok(TxOutcome::Pending)
+    }
+
+    /**
+     * Releases a pending transaction's hold, applying it when `status` is
+     * `Processed` and reverting it when `Declined`. An approval whose fee
+     * the account cannot pay is refused, and the transaction stays pending.
+     */
+    fn settle_pending(
+        &self,
+        account: &mut Account<M>,
+        mut target_tx: TransactionRecord<M>,
+        status: TransactionStatus,
+    ) -> Result<TxOutcome, BankError> {
+        let tx_id = target_tx.0.id;
+        let amount =
+            self.restore_on_error(target_tx.0.amount.ok_or_else(missing_amount), &target_tx)?;
+        let tx_type = target_tx.0.tx_type;
+        let is_withdrawal = tx_type == TransactionType::Withdrawal;
+        let mut settled_account = account.clone();
+        let settled = match (status, tx_type != TransactionType::Deposit) {
+            (TransactionStatus::Processed, true) | (TransactionStatus::Declined, false) => {
+                settled_account.remove_held(amount)
+            }
+            _ => settled_account.release(amount),
+        };
+        self.restore_on_error(settled, &target_tx)?;
+        let fee = match status {
+            TransactionStatus::Processed => self.pending_fees.borrow().get(&tx_id).copied(),
+            _ => None,
+        };
+        if let Some(fee) = fee {
+            if !self.can_withdraw(&settled_account, fee) {
+                self.transactions.borrow_mut().insert(tx_id, target_tx);
+                return Ok(TxOutcome::RejectedInsufficientFunds);
+            }
+            self.restore_on_error(settled_account.draw(fee), &target_tx)?;
+        }
+        *account = settled_account;
+        self.pending_fees.borrow_mut().remove(&tx_id);
+        target_tx.1 = status;
+
+        // An approved direct debit is stored to be returnable, as when it
+        // is applied right away.
+        if status == TransactionStatus::Processed && tx_type != TransactionType::DirectDebit {
+            if is_withdrawal {
+                self.record_daily_withdrawal(&target_tx.0, amount);
+            }
+            self.compliance.borrow_mut().observe(&target_tx.0);
+            if is_withdrawal && !self.config.disputable_withdrawals {
+                self.withdrawal_ids.borrow_mut().insert(tx_id);
+                return Ok(TxOutcome::Applied);
+            }
+        }
+        self.transactions.borrow_mut().insert(tx_id, target_tx);
+        Ok(TxOutcome::Applied)
+    }
+
+    fn open_dispute(&self, dispute: &Transaction<M>) {
+        self.dispute_states.borrow_mut().insert(
+            dispute.id,
+            DisputeState {
+                tx_id: dispute.id,
+                disputed_by: dispute.client_id,
+                disputed_at: dispute.timestamp.or(self.watermark.get()),
+                status: TransactionStatus::Disputed,
+                closed_at: None,
+            },
+        );
+    }
+
+    fn close_dispute(&self, closing: &Transaction<M>, status: TransactionStatus) {
+        if let Some(state) = self.dispute_states.borrow_mut().get_mut(&closing.id) {
+            state.status = status;
+            state.closed_at = closing.timestamp.or(self.watermark.get());
+        }
+    }
+
+    /**
+     * Moves the disputed amount from available to held, subject to the
+     * dispute overdraw policy, and puts the record back as disputed.
+     */
+    fn hold_disputed(
+        &self,
+        account: &mut Account<M>,
+        mut target_tx: TransactionRecord<M>,
+    ) -> Result<TxOutcome, BankError> {
+        let tx_id = target_tx.0.id;
+        let tx_amount =
+            self.restore_on_error(target_tx.0.amount.ok_or_else(missing_amount), &target_tx)?;
+        let overdraws = tx_amount > account.available;
+        let policy = self.config.dispute_overdraw_policy;
+        if overdraws && policy == DisputeOverdrawPolicy::Reject {
+            self.transactions.borrow_mut().insert(tx_id, target_tx);
+            return Ok(TxOutcome::RejectedDisputeOverdraw);
+        }
+
+        let to_hold = if overdraws && policy == DisputeOverdrawPolicy::Cap {
+            if account.available > M::zero() {
+                account.available
+            } else {
+                M::zero()
+            }
+        } else {
+            tx_amount
+        };
+        self.restore_on_error(account.hold(to_hold), &target_tx)?;
+        target_tx.1 = TransactionStatus::Disputed;
+        self.transactions.borrow_mut().insert(tx_id, target_tx);
+        self.dispute_holds.borrow_mut().insert(tx_id, to_hold);
+
+        if account.available < M::zero() {
+            self.negative_available_alerts
+                .set(self.negative_available_alerts.get() + 1);
+            Ok(TxOutcome::NegativeAvailable)
+        } else if to_hold < tx_amount {
+            Ok(TxOutcome::DisputeCapped)
+        } else {
+            Ok(TxOutcome::Applied)
+        }
+    }
+
+    /**
+     * Stores `target_tx` back unchanged if applying it to the account failed.
+     */
+    fn restore_on_error<T>(
+        &self,
+        result: Result<T, BankError>,
+        target_tx: &TransactionRecord<M>,
+    ) -> Result<T, BankError> {
+        if result.is_err() {
+            self.transactions
+                .borrow_mut()
+                .insert(target_tx.0.id, target_tx.clone());
+        }
+        result
+    }
+
+    /**
+     * Amount held by the dispute on `target_tx`, which may be less than its
+     * amount when the dispute was capped. Stores `target_tx` back if it has
+     * neither.
+     */
+    fn dispute_hold(&self, target_tx: &TransactionRecord<M>) -> Result<M, BankError> {
+        let hold = self.dispute_holds.borrow().get(&target_tx.0.id).copied();
+        match hold {
+            Some(hold) => Ok(hold),
+            None => self.restore_on_error(target_tx.0.amount.ok_or_else(missing_amount), target_tx),
+        }
+    }
+
+    fn client_override(&self, client_id: u16) -> Option<&ClientOverride<M>> {
+        self.config.client_overrides.get(&client_id)
+    }
+
+    fn overdraft(&self, client_id: u16) -> M {
+        self.client_override(client_id)
+            .and_then(|o| o.overdraft)
+            .unwrap_or(self.config.overdraft)
+    }
+
+    /**
+     * Whether `amount` can be withdrawn without going past the overdraft,
+     * or for a credit account past its credit limit.
+     */
+    fn can_withdraw(&self, account: &Account<M>, amount: M) -> bool {
+        match (account.outstanding, self.credit_limit(account.client_id)) {
+            (Some(outstanding), Some(limit)) => limit
+                .checked_sub(outstanding)
+                .and_then(|unused| unused.checked_add(account.own_funds()))
+                .is_some_and(|limit| amount <= limit),
+            _ => account
+                .available
+                .checked_add(self.overdraft(account.client_id))
+                .is_some_and(|limit| amount <= limit),
+        }
+    }
+
+    fn credit_limit(&self, client_id: u16) -> Option<M> {
+        self.client_override(client_id).and_then(|o| o.credit_limit)
+    }
+
+    /**
+     * A new account for `client_id`, a credit account owing its principal
+     * if the client has a credit limit.
+     */
+    fn open_account(&self, client_id: u16) -> Account<M> {
+        let mut account = Account::new(client_id);
+        if let Some(client_override) = self.client_override(client_id) {
+            if client_override.credit_limit.is_some() {
+                account.outstanding = Some(client_override.principal.unwrap_or_else(M::zero));
+            }
+        }
+        account
+    }
+
+    fn daily_limit(&self, client_id: u16) -> Option<M> {
+        self.client_override(client_id)
+            .and_then(|o| o.daily_withdrawal_limit)
+            .or(self.config.daily_withdrawal_limit)
+    }
+
+    fn dispute_window(&self, client_id: u16) -> Option<Duration> {
+        self.client_override(client_id)
+            .and_then(|o| o.dispute_window)
+            .or(self.config.dispute_window)
+    }
+
+    fn withdrawn_on_day(&self, client_id: u16, day: u64) -> M {
+        match self.daily_withdrawals.borrow().get(&client_id) {
+            Some((recorded_day, sum)) if *recorded_day == day => *sum,
+            _ => M::zero(),
+        }
+    }
+
+    /**
+     * What `client_id` will have withdrawn on `day` after `amount`, or `None`
+     * if that is out of range.
+     */
+    fn withdrawn_with(&self, client_id: u16, day: u64, amount: M) -> Option<M> {
+        self.withdrawn_on_day(client_id, day).checked_add(amount)
+    }
+
+    fn exceeds_daily_limit(&self, tx: &Transaction<M>, amount: M) -> bool {
+        match (self.daily_limit(tx.client_id), tx.timestamp) {
+            (Some(limit), Some(timestamp)) => {
+                let day = timestamp / SECONDS_PER_DAY;
+                self.withdrawn_with(tx.client_id, day, amount)
+                    .is_none_or(|sum| sum > limit)
+            }
+            _ => false,
+        }
+    }
+
+    fn record_daily_withdrawal(&self, tx: &Transaction<M>, amount: M) {
+        if let (Some(_), Some(timestamp)) = (self.daily_limit(tx.client_id), tx.timestamp) {
+            let day = timestamp / SECONDS_PER_DAY;
+            if let Some(sum) = self.withdrawn_with(tx.client_id, day, amount) {
+                self.daily_withdrawals
+                    .borrow_mut()
+                    .insert(tx.client_id, (day, sum));
+            }
+        }
+    }
+
+    fn is_outside_dispute_window(
+        &self,
+        disputed: &Transaction<M>,
+        dispute: &Transaction<M>,
+    ) -> bool {
+        match (
+            self.dispute_window(dispute.client_id),
+            disputed.timestamp,
+            dispute.timestamp.or(self.watermark.get()),
+        ) {
+            (Some(window), Some(from), Some(to)) => to.saturating_sub(from) > window.as_secs(),
+            _ => false,
+        }
+    }
+
+    fn is_outside_return_window(
+        &self,
+        debit: &Transaction<M>,
+        debit_return: &Transaction<M>,
+    ) -> bool {
+        match (
+            self.config.debit_return_window,
+            debit.timestamp,
+            debit_return.timestamp.or(self.watermark.get()),
+        ) {
+            (Some(window), Some(from), Some(to)) => to.saturating_sub(from) > window.as_secs(),
+            _ => false,
+        }
+    }
+
+    /**
+     * Takes the referenced transaction out of the store if it belongs to the
+     * account and is in one of `desired_statuses`; otherwise leaves it
+     * untouched. A transaction already in `target_status` is reported as
+     * `AlreadyInState` so repeated requests are harmless.
+     */
+    fn get_transaction_with_status(
+        &self,
+        account: &Account<M>,
+        tx_id: &u32,
+        desired_statuses: &[TransactionStatus],
+        target_status: TransactionStatus,
+    ) -> Result<TransactionRecord<M>, TxOutcome> {
+        let mut transactions = self.transactions.borrow_mut();
+        match transactions.get(*tx_id) {
+            None if self.withdrawal_ids.borrow().contains(tx_id) => {
+                Err(TxOutcome::IgnoredWithdrawalNotDisputable)
+            }
+            None => Err(TxOutcome::IgnoredUnknownTransaction),
+            Some(target_tx) if self.account_id(target_tx.0.client_id) != account.client_id => {
+                Err(TxOutcome::IgnoredClientMismatch)
+            }
+            Some(target_tx) if target_tx.1 == target_status => Err(TxOutcome::AlreadyInState),
+            Some(target_tx) if !desired_statuses.contains(&target_tx.1) => {
+                Err(TxOutcome::IgnoredInvalidState)
+            }
+            Some(_) => transactions
+                .remove(*tx_id)
+                .ok_or(TxOutcome::IgnoredUnknownTransaction),
+        }
+    }
+
+    /**
+     * The account `client_id` transacts on: its own unless it is a joint
+     * holder of another one.
+     */
+    fn account_id(&self, client_id: u16) -> u16 {
+        self.config
+            .joint_holders
+            .get(&client_id)
+            .copied()
+            .unwrap_or(client_id)
+    }
+
+    /**
+     * Takes the account out of the bank while a transaction is applied to
+     * it; `process_transaction` puts it back.
+     */
+    fn get_account(&self, client_id: u16) -> Option<Account<M>> {
+        self.accounts.borrow_mut().remove(&client_id)
+    }
+
+    pub fn account(&self, client_id: u16) -> Option<AccountView<M>> {
+        self.accounts.borrow().get(&client_id).map(Account::view)
+    }
+
+    /**
+     * What the credit account of `client_id` owes, or `None` if it has no
+     * credit account.
+     */
+    pub fn outstanding(&self, client_id: u16) -> Option<M> {
+        self.accounts
+            .borrow()
+            .get(&client_id)
+            .and_then(|a| a.outstanding)
+    }
+
+    pub fn accounts(&self) -> Vec<AccountView<M>> {
+        self.accounts.borrow().values().map(Account::view).collect()
+    }
+
+    /**
+     * Transactions reported by the AML checks (see `BankBuilder::aml`) and
+     * denylist screening, in the order they were detected.
+     */
+    pub fn compliance_entries(&self) -> Vec<ComplianceEntry<M>> {
+        self.compliance.borrow().entries().to_vec()
+    }
+
+    /**
+     * The stored deposit or withdrawal with id `tx_id`, including its tags.
+     */
+    pub fn transaction(&self, tx_id: u32) -> Option<Transaction<M>> {
+        self.transactions
+            .borrow()
+            .get(tx_id)
+            .map(|(tx, _)| tx.clone())
+    }
+
+    /**
+     * The current status of stored transaction `tx_id`.
+     */
+    pub fn transaction_status(&self, tx_id: u32) -> Option<TransactionStatus> {
+        self.transactions
+            .borrow()
+            .get(tx_id)
+            .map(|(_, status)| *status)
+    }
+
+    /**
+     * The stored transaction submitted with `external_id`, with its status.
+     * Withdrawals are only found while they are disputable.
+     */
+    pub fn transaction_by_external_id(
+        &self,
+        external_id: &str,
+    ) -> Option<(Transaction<M>, TransactionStatus)> {
+        let tx_id = *self.external_ids.borrow().get(external_id)?;
+        self.transactions
+            .borrow()
+            .get(tx_id)
+            .filter(|(tx, _)| tx.external_id() == Some(external_id))
+            .cloned()
+    }
+
+    /**
+     * Deposits and withdrawals held for manual review, ordered by id.
+     */
+    pub fn pending_transactions(&self) -> Vec<Transaction<M>> {
+        let mut pending: Vec<Transaction<M>> = self
+            .transactions
+            .borrow()
+            .values()
+            .filter(|(_, status)| *status == TransactionStatus::Pending)
+            .map(|(tx, _)| tx.clone())
+            .collect();
+        pending.sort_by_key(|tx| tx.id);
+        pending
+    }
+
+    /**
+     * Every account with counts and dates from its stored transactions, in
+     * account order.
+     */
+    pub fn extended_accounts(&self) -> Vec<ExtendedAccountView<M>> {
+        let accounts = self.accounts.borrow();
+        let mut views: Vec<_> = accounts
+            .values()
+            .map(|account| ExtendedAccountView::new(account.view(), account.outstanding))
+            .collect();
+        let positions: HashMap<u16, usize> = accounts
+            .keys()
+            .enumerate()
+            .map(|(i, &client_id)| (client_id, i))
+            .collect();
+        for (tx, status) in self.transactions.borrow().values() {
+            if let Some(&i) = positions.get(&self.account_id(tx.client_id)) {
+                views[i].record(tx, *status);
+            }
+        }
+        views
+    }
+
+    /**
+     * The current state, detached from the bank. Taking one copies every
+     * account and counts every stored transaction, so it costs time and
+     * memory in proportion to the bank's state; the copy is then shared
+     * until the next transaction is processed, so taking one of an
+     * unchanged bank again costs nothing.
+     */
+    pub fn snapshot(&self) -> Arc<BankSnapshot<M>> {
+        let mut cached = self.snapshot.borrow_mut();
+        if let Some(snapshot) = cached.as_ref() {
+            return snapshot.clone();
+        }
+        let accounts = self.accounts();
+        let mut disputes = DisputeStats::default();
+        for (_, status) in self.transactions.borrow().values() {
+            disputes.transactions += 1;
+            match status {
+                TransactionStatus::Disputed => disputes.open += 1,
+                TransactionStatus::Resolved => disputes.resolved += 1,
+                TransactionStatus::ChargedBack => disputes.charged_back += 1,
+                _ => {}
+            }
+        }
+        cached
+            .insert(Arc::new(BankSnapshot { accounts, disputes }))
+            .clone()
+    }
+
+    /**
+     * The latest dispute of transaction `tx_id`, open or closed, or `None`
+     * if it was never disputed.
+     */
+    pub fn dispute_status(&self, tx_id: u32) -> Option<DisputeState> {
+        self.dispute_states.borrow().get(&tx_id).copied()
+    }
+
+    /**
+     * The stored transactions matching `filter`, with their status, ordered
+     * by transaction id. Disputes, resolves and chargebacks are not stored;
+     * they show in the status of the transaction they reference.
+     *
+     * The transactions are looked up as the iterator advances, so the bank
+     * may go on processing in between; each entry is as stored when it is
+     * reached.
+     */
+    pub fn find_transactions(
+        &self,
+        filter: &TransactionFilter<M>,
+    ) -> impl Iterator<Item = StatementEntry<M>> + '_ {
+        let filter = filter.clone();
+        let mut next_id = Some(0);
+        iter::from_fn(move || {
+            let stored = self.transactions.borrow();
+            let (id, (tx, status)) = stored
+                .starting_at(next_id?, filter.client_id)
+                .find(|(_, (tx, status))| filter.matches(tx, *status))?;
+            next_id = id.checked_add(1);
+            Some(StatementEntry {
+                transaction: tx.clone(),
+                status: *status,
+            })
+        })
+    }
+
+    /**
+     * Every account with the transactions stored for it, including those of
+     * its joint holders, in account order.
+     */
+    pub fn client_statements(&self) -> Vec<ClientStatement<M>> {
+        let stored = self.transactions.borrow();
+        let mut holders: HashMap<u16, Vec<u16>> = HashMap::new();
+        for (&client_id, &account_id) in &self.config.joint_holders {
+            holders.entry(account_id).or_default().push(client_id);
+        }
+        self.accounts
+            .borrow()
+            .values()
+            .map(|account| {
+                let joint = holders.remove(&account.client_id).unwrap_or_default();
+                let mut transactions: Vec<StatementEntry<M>> = iter::once(account.client_id)
+                    .chain(joint)
+                    .flat_map(|client_id| stored.for_client(client_id))
+                    .map(|(tx, status)| StatementEntry {
+                        transaction: tx.clone(),
+                        status: *status,
+                    })
+                    .collect();
+                transactions.sort_by_key(|entry| entry.transaction.id);
+                ClientStatement {
+                    account: account.view(),
+                    transactions,
+                }
+            })
+            .collect()
+    }
+
+    /**
+     * Accounts locked by a chargeback that are waiting for manual review,
+     * ordered by client id.
+     */
+    pub fn review_queue(&self) -> Vec<ReviewEntry> {
+        self.review.borrow().entries()
+    }
+
+    /**
+     * Accounts whose available balance is currently below zero.
+     */
+    pub fn negative_available_accounts(&self) -> Vec<AccountView<M>> {
+        self.accounts
+            .borrow()
+            .values()
+            .filter(|a| a.available < M::zero())
+            .map(Account::view)
+            .collect()
+    }
+
+    /**
+     * Erases the personal details of a closed client: the tags, description
+     * and external id of every transaction it made, and the tags and
+     * description of its compliance entries. Ids, types, amounts, times and
+     * statuses stay, so balances and every aggregate over the ledger are
+     * unchanged. A client with held funds or with transactions still
+     * disputed or pending is not closed and fails with
+     * `ErrorCode::ClientNotClosed`. Returns the number of transactions
+     * purged.
+     */
+    pub fn purge_client(&self, client_id: u16) -> Result<usize, BankError> {
+        let mut transactions = self.transactions.borrow_mut();
+        let open = transactions.for_client(client_id).any(|(_, status)| {
+            matches!(
+                status,
+                TransactionStatus::Disputed | TransactionStatus::Pending
+            )
+        });
+        let holds_funds = self
+            .account(self.account_id(client_id))
+            .is_some_and(|account| account.held != M::zero());
+        if open || holds_funds {
+            return Err(BankError::new(
+                ErrorCode::ClientNotClosed,
+                format!("Client {} has open disputes or held funds", client_id),
+            ));
+        }
+
+        let ids: Vec<u32> = transactions
+            .for_client(client_id)
+            .map(|(tx, _)| tx.id)
+            .collect();
+        let mut external_ids = self.external_ids.borrow_mut();
+        for &id in &ids {
+            if let Some(mut record) = transactions.remove(id) {
+                if let Some(external_id) = record.0.purge_details() {
+                    external_ids.remove(&external_id);
+                }
+                transactions.insert(id, record);
+            }
+        }
+        self.compliance.borrow_mut().purge_client(client_id);
+        Ok(ids.len())
+    }
+
+    /**
+     * The bank-wide cash position: liabilities to clients, held funds,
+     * daily flows and the largest movements. Fails only if a sum overflows
+     * the amount type.
+     */
+    pub fn liquidity_report(&self) -> Result<LiquidityReport<M>, BankError> {
+        LiquidityReport::build(&self.accounts(), self.transactions.borrow().values())
+    }
+
+    /**
+     * How many disputes have left an account with a negative available
+     * balance so far.
+     */
+    pub fn negative_available_alerts(&self) -> usize {
+        self.negative_available_alerts.get()
+    }
+
+    pub fn print_report(&self) -> Result<(), BankError> {
+        self.write_report(io::stdout().lock())
+    }
+
+    /**
+     * Writes the account report as CSV, handing `writer` chunks of
+     * `REPORT_BUFFER_BYTES` rather than one write per row.
+     */
+    pub fn write_report<W: io::Write>(&self, writer: W) -> Result<(), BankError> {
+        write_rows(writer, self.accounts.borrow().values().map(Account::view))
+    }
+
+    /**
+     * Writes the account report with `report_writer`, e.g. a
+     * `ReportFormat`.
+     */
+    pub fn write_report_as<W, R>(&self, report_writer: &R, mut writer: W) -> Result<(), BankError>
+    where
+        W: io::Write,
+        R: ReportWriter<M> + ?Sized,
+    {
+        report_writer.write_accounts(&self.accounts(), &mut writer)
+    }
+
+    /**
+     * Writes the account report with the `ExtendedAccountView` columns
+     * appended to the usual ones.
+     */
+    pub fn write_extended_report<W: io::Write>(&self, writer: W) -> Result<(), BankError> {
+        write_rows(writer, self.extended_accounts())
+    }
+
+    /**
+     * Same output as `write_report`, but serializes `shards` contiguous
+     * slices of the accounts on separate threads and writes them in order.
+     * The output is byte-identical to `write_report` for any number of
+     * shards, including zero and more shards than accounts, and however
+     * the threads are scheduled: slices are fixed before any thread starts
+     * and joined in order, and each is encoded independently of the others.
+     */
+    pub fn write_report_sharded<W: io::Write>(
+        &self,
+        writer: W,
+        shards: usize,
+    ) -> Result<(), BankError> {
+        let views = self.accounts();
+        let shard_len = views.len().div_ceil(shards.max(1)).max(1);
+        let encoded: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = views
+                .chunks(shard_len)
+                .enumerate()
+                .map(|(i, shard)| scope.spawn(move || encode_accounts(shard, i == 0)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("report shard panicked"))
+                .collect()
+        });
+        let mut writer = io::BufWriter::with_capacity(REPORT_BUFFER_BYTES, writer);
+        for shard in encoded {
+            writer.write_all(&shard?).map_err(write_error)?;
+        }
+        writer.flush().map_err(write_error)
+    }
+}
+
+/**
+ * A failure writing a report, from the writer or the serializer.
+ */
+fn write_error(error: impl fmt::Display) -> BankError {
+    BankError::new(ErrorCode::WriteFailed, error.to_string())
+}
+
+/**
+ * Writes accounts as a report, for `Bank::write_report_as`. Implement it
+ * for formats other than the `ReportFormat` ones.
+ */
+pub trait ReportWriter<M: Money = Decimal> {
+    fn write_accounts(
+        &self,
+        accounts: &[AccountView<M>],
+        writer: &mut dyn io::Write,
+    ) -> Result<(), BankError>;
+}
+
+/**
+ * The formats the account report can be written in. All of them write
+ * amounts as the CSV report does.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ReportFormat {
+    #[default]
+    Csv,
+    /// CSV separated by tabs.
+    Tsv,
+    /// One array of objects, amounts as strings.
+    Json,
+    /// One object per line.
+    JsonLines,
+    /// Columns aligned with spaces, for people.
+    Table,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "csv" => Ok(ReportFormat::Csv),
+            "tsv" => Ok(ReportFormat::Tsv),
+            "json" => Ok(ReportFormat::Json),
+            "jsonl" => Ok(ReportFormat::JsonLines),
+            "table" => Ok(ReportFormat::Table),
+            _ => Err(format!("Unknown report format {:?}", name)),
+        }
+    }
+}
+
+impl<M: Money> ReportWriter<M> for ReportFormat {
+    fn write_accounts(
+        &self,
+        accounts: &[AccountView<M>],
+        writer: &mut dyn io::Write,
+    ) -> Result<(), BankError> {
+        let mut writer = io::BufWriter::with_capacity(REPORT_BUFFER_BYTES, writer);
+        match self {
+            ReportFormat::Csv => return write_rows(writer, accounts),
+            ReportFormat::Tsv => {
+                let mut writer = csv::WriterBuilder::new()
+                    .delimiter(b'\t')
+                    .from_writer(writer);
+                for account in accounts {
+                    writer.serialize(account).map_err(write_error)?;
+                }
+                return writer.flush().map_err(write_error);
+            }
+            ReportFormat::Json => {
+                serde_json::to_writer(&mut writer, accounts).map_err(write_error)?;
+                writeln!(writer).map_err(write_error)?;
+            }
+            ReportFormat::JsonLines => {
+                for account in accounts {
+                    serde_json::to_writer(&mut writer, account).map_err(write_error)?;
+                    writeln!(writer).map_err(write_error)?;
+                }
+            }
+            ReportFormat::Table => write_table(accounts, &mut writer).map_err(write_error)?,
+        }
+        writer.flush().map_err(write_error)
+    }
+}
+
+/**
+ * Writes the report with every column as wide as its widest value, numbers
+ * aligned right and flags left.
+ */
+fn write_table<M: Money>(
+    accounts: &[AccountView<M>],
+    writer: &mut impl io::Write,
+) -> io::Result<()> {
+    const HEADER: [&str; 6] = ["client", "available", "held", "total", "locked", "frozen"];
+    let rows: Vec<[String; 6]> = accounts
+        .iter()
+        .map(|account| {
+            [
+                account.client_id.to_string(),
+                fixed_text(account.available),
+                fixed_text(account.held),
+                fixed_text(account.total),
+                account.locked.to_string(),
+                account.frozen.to_string(),
+            ]
+        })
+        .collect();
+    let mut widths = HEADER.map(str::len);
+    for row in &rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.len());
+        }
+    }
+    let header = HEADER.map(String::from);
+    for row in iter::once(&header).chain(&rows) {
+        let mut line = String::new();
+        for (i, value) in row.iter().enumerate() {
+            if i > 0 {
+                line.push_str("  ");
+            }
+            let width = widths[i];
+            if i < 4 {
+                line.push_str(&format!("{:>width$}", value));
+            } else {
+                line.push_str(&format!("{:<width$}", value));
+            }
+        }
+        writeln!(writer, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+fn write_rows<W, T, I>(writer: W, rows: I) -> Result<(), BankError>
+where
+    W: io::Write,
+    T: Serialize,
+    I: IntoIterator<Item = T>,
+{
+    let buffered = io::BufWriter::with_capacity(REPORT_BUFFER_BYTES, writer);
+    let mut writer = csv::Writer::from_writer(buffered);
+    for row in rows {
+        writer.serialize(row).map_err(write_error)?;
+    }
+    writer.flush().map_err(write_error)
+}
+
+/**
+ * Serializes one shard of the report, with the header row only if asked.
+ */
+fn encode_accounts<M: Money>(
+    accounts: &[AccountView<M>],
+    with_header: bool,
+) -> Result<Vec<u8>, BankError> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(with_header)
+        .from_writer(Vec::new());
+    for account in accounts {
+        writer.serialize(account).map_err(write_error)?;
+    }
+    writer.into_inner().map_err(write_error)
+}
+
+/**
+ * Whether a transaction of `tx_type` moves funds on its own, and so can be
+ * held pending until it is approved or declined.
+ */
+fn is_holdable(tx_type: TransactionType) -> bool {
+    matches!(
+        tx_type,
+        TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::DirectDebit
+    )
+}
+
+fn missing_amount() -> BankError {
+    BankError::new(ErrorCode::MissingAmount, INVALID_TRANSACTION_DATA_NO_AMOUNT)
+}
+
+pub(crate) fn add<M: Money>(a: M, b: M) -> Result<M, BankError> {
+    a.checked_add(b)
+        .ok_or_else(|| BankError::new(ErrorCode::AmountOutOfRange, AMOUNT_OUT_OF_RANGE))
+}
+
+pub(crate) fn sub<M: Money>(a: M, b: M) -> Result<M, BankError> {
+    a.checked_sub(b)
+        .ok_or_else(|| BankError::new(ErrorCode::AmountOutOfRange, AMOUNT_OUT_OF_RANGE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{read_transactions, CsvSource, JsonLinesSource, TypeNames};
+    use crate::money::MinorUnits;
+    use quickcheck::{Arbitrary, Gen, QuickCheck};
+    use rust_decimal::Decimal;
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn test_batch_process_deposit() {
+        // GIVEN
+        let deposit1 = Transaction {
+            tx_type: TransactionType::Deposit,
+            client_id: 1,
+            id: 1,
+            amount: Some(30.0),
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+        let bank = Bank::<f32>::default();
+
+        // WHEN
+        let result = bank.batch_process(vec![deposit1]);
+
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(bank.accounts.borrow()[&1].client_id, 1);
+        assert_eq!(bank.accounts.borrow()[&1].available, 30.0000);
+        assert_eq!(bank.accounts.borrow()[&1].total, 30.0000);
+        assert_eq!(bank.accounts.borrow()[&1].held, 0.0000);
+        assert!(!bank.accounts.borrow()[&1].locked);
+    }
+
+    #[test]
+    fn test_batch_process_deposit_no_amount_error() {
+        // GIVEN
+        let deposit1 = Transaction {
+            tx_type: TransactionType::Deposit,
+            client_id: 1,
+            id: 1,
+            amount: None,
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+        let bank = Bank::<f32>::default();
+
+        // WHEN
+        let result = bank.batch_process(vec![deposit1]);
+
+        // THEN
+        assert_eq!(result, Err(missing_amount()));
+        assert_eq!(bank.accounts.borrow().len(), 0);
+    }
+
+    #[test]
+    fn test_batch_process_withdrawal() {
+        // GIVEN
+        let withdrawal = Transaction {
+            tx_type: TransactionType::Withdrawal,
+            client_id: 5,
+            id: 2,
+            amount: Some(15.0),
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+
+        let mut bank: Bank<f32> = BankBuilder::default().build();
+
+        bank.accounts = RefCell::new(BTreeMap::from([(
+            5,
+            Account {
+                client_id: 5,
+                available: 30.0,
+                held: 0.0,
+                total: 30.0,
+                locked: false,
+                frozen: false,
+                outstanding: None,
+            },
+        )]));
+
+        // WHEN
+        let result = bank.batch_process(vec![withdrawal]);
+
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(bank.accounts.borrow()[&5].client_id, 5);
+        assert_eq!(bank.accounts.borrow()[&5].available, 15.0);
+        assert_eq!(bank.accounts.borrow()[&5].total, 15.0);
+        assert_eq!(bank.accounts.borrow()[&5].held, 0.0);
+        assert!(!bank.accounts.borrow()[&5].locked);
+    }
+
+    #[test]
+    fn test_batch_process_withdrawal_no_amount_error() {
+        // GIVEN
+        let withdrawal = Transaction {
+            tx_type: TransactionType::Withdrawal,
+            client_id: 1,
+            id: 1,
+            amount: None,
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+        let bank = Bank::<f32>::default();
+
+        // WHEN
+        let result = bank.batch_process(vec![withdrawal]);
+
+        // THEN
+        assert_eq!(result, Err(missing_amount()));
+        assert_eq!(bank.accounts.borrow().len(), 0);
+    }
+
+    #[test]
+    fn test_process_transaction_non_positive_amount_rejected() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        bank.process_transaction(Transaction::deposit(1, 1, 10.0))
+            .unwrap();
+
+        // WHEN
+        let negative = bank.process_transaction(Transaction::withdrawal(1, 2, -5.0));
+        let zero = bank.process_transaction(Transaction::deposit(2, 3, 0.0));
+        let nan = bank.process_transaction(Transaction::deposit(1, 4, f32::NAN));
+        let dispute = bank.process_transaction(Transaction::dispute(1, 2));
+
+        // THEN
+        let rejected = Ok(TxOutcome::Rejected(Rejection::NonPositiveAmount));
+        assert_eq!(negative, rejected);
+        assert_eq!(zero, rejected);
+        assert_eq!(nan, rejected);
+        assert_eq!(dispute, Ok(TxOutcome::IgnoredUnknownTransaction));
+        assert_eq!(bank.account(1).unwrap().available, 10.0);
+        assert_eq!(bank.account(1).unwrap().held, 0.0);
+        assert_eq!(bank.account(2).unwrap().total, 0.0);
+    }
+
+    #[test]
+    fn test_batch_process_withdrawal_not_sufficient_fund_no_change() {
+        // GIVEN
+        let withdrawal = Transaction {
+            tx_type: TransactionType::Withdrawal,
+            client_id: 5,
+            id: 2,
+            amount: Some(45.0),
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+
+        let mut bank: Bank<f32> = BankBuilder::default().build();
+
+        bank.accounts = RefCell::new(BTreeMap::from([(
+            5,
+            Account {
+                client_id: 5,
+                available: 30.0,
+                held: 0.0,
+                total: 30.0,
+                locked: false,
+                frozen: false,
+                outstanding: None,
+            },
+        )]));
+
+        // WHEN
+        let result = bank.batch_process(vec![withdrawal]);
+
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(bank.accounts.borrow()[&5].client_id, 5);
+        assert_eq!(bank.accounts.borrow()[&5].available, 30.0);
+        assert_eq!(bank.accounts.borrow()[&5].total, 30.0);
+        assert_eq!(bank.accounts.borrow()[&5].held, 0.0);
+        assert!(!bank.accounts.borrow()[&5].locked);
+    }
+
+    #[test]
+    fn test_batch_process_dispute() {
+        // GIVEN
+        let dispute = Transaction {
+            tx_type: TransactionType::Dispute,
+            client_id: 5,
+            id: 2,
+            amount: None,
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+
+        let mut bank: Bank<f32> = BankBuilder::default().build();
+
+        bank.accounts = RefCell::new(BTreeMap::from([(
+            5,
+            Account {
+                client_id: 5,
+                available: 15.0,
+                held: 0.0,
+                total: 15.0,
+                locked: false,
+                frozen: false,
+                outstanding: None,
+            },
+        )]));
+
+        bank.transactions = RefCell::new(TransactionStore::from_iter([(
+            2,
+            (
+                Transaction {
+                    tx_type: TransactionType::Withdrawal,
+                    client_id: 5,
+                    id: 2,
+                    amount: Some(10.0),
+                    timestamp: None,
+                    tags: None,
+                    description: None,
+                    external_id: None,
+                    mandate: None,
+                },
+                TransactionStatus::Processed,
+            ),
+        )]));
+
+        // WHEN
+        let result = bank.batch_process(vec![dispute]);
+
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(bank.accounts.borrow()[&5].client_id, 5);
+        assert_eq!(bank.accounts.borrow()[&5].held, 10.0);
+        assert_eq!(bank.accounts.borrow()[&5].total, 15.0);
+        assert_eq!(bank.accounts.borrow()[&5].available, 5.0);
+        assert!(!bank.accounts.borrow()[&5].locked);
+    }
+
+    #[test]
+    fn test_batch_process_dispute_valid_tx_but_not_matching_client_id() {
+        // GIVEN
+        let dispute = Transaction {
+            tx_type: TransactionType::Dispute,
+            client_id: 15,
+            id: 2,
+            amount: None,
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+
+        let mut bank: Bank<f32> = BankBuilder::default().build();
+
+        bank.accounts = RefCell::new(BTreeMap::from([(
+            5,
+            Account {
+                client_id: 5,
+                available: 15.0,
+                held: 0.0,
+                total: 15.0,
+                locked: false,
+                frozen: false,
+                outstanding: None,
+            },
+        )]));
+
+        bank.transactions = RefCell::new(TransactionStore::from_iter([(
+            2,
+            (
+                Transaction {
+                    tx_type: TransactionType::Withdrawal,
+                    client_id: 5,
+                    id: 2,
+                    amount: Some(10.0),
+                    timestamp: None,
+                    tags: None,
+                    description: None,
+                    external_id: None,
+                    mandate: None,
+                },
+                TransactionStatus::Processed,
+            ),
+        )]));
+
+        // WHEN
+        let result = bank.batch_process(vec![dispute]);
+
+        // THEN
+        assert!(result.is_ok());
+        // No fund amount was changed
+        assert_eq!(bank.accounts.borrow()[&5].client_id, 5);
+        assert_eq!(bank.accounts.borrow()[&5].held, 0.0);
+        assert_eq!(bank.accounts.borrow()[&5].total, 15.0);
+        assert_eq!(bank.accounts.borrow()[&5].available, 15.0);
+        assert!(!bank.accounts.borrow()[&5].locked);
+    }
+
+    #[test]
+    fn test_batch_process_resolve() {
+        // GIVEN
+        let resolve = Transaction {
+            tx_type: TransactionType::Resolve,
+            client_id: 5,
+            id: 2,
+            amount: None,
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+
+        let mut bank: Bank<f32> = BankBuilder::default().build();
+
+        bank.accounts = RefCell::new(BTreeMap::from([(
+            5,
+            Account {
+                client_id: 5,
+                available: 5.0,
+                held: 10.0,
+                total: 15.0,
+                locked: false,
+                frozen: false,
+                outstanding: None,
+            },
+        )]));
+
+        bank.transactions = RefCell::new(TransactionStore::from_iter([(
+            2,
+            (
+                Transaction {
+                    tx_type: TransactionType::Withdrawal,
+                    client_id: 5,
+                    id: 2,
+                    amount: Some(10.0),
+                    timestamp: None,
+                    tags: None,
+                    description: None,
+                    external_id: None,
+                    mandate: None,
+                },
+                TransactionStatus::Disputed,
+            ),
+        )]));
+
+        // WHEN
+        let result = bank.batch_process(vec![resolve]);
+
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(bank.accounts.borrow()[&5].client_id, 5);
+        assert_eq!(bank.accounts.borrow()[&5].held, 0.0);
+        assert_eq!(bank.accounts.borrow()[&5].total, 15.0);
+        assert_eq!(bank.accounts.borrow()[&5].available, 15.0);
+        assert!(!bank.accounts.borrow()[&5].locked);
+    }
+
+    #[test]
+    fn test_batch_process_chargeback() {
+        // GIVEN
+        let chargeback = Transaction {
+            tx_type: TransactionType::Chargeback,
+            client_id: 5,
+            id: 2,
+            amount: None,
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+
+        let mut bank: Bank<f32> = BankBuilder::default().build();
+
+        bank.accounts = RefCell::new(BTreeMap::from([(
+            5,
+            Account {
+                client_id: 5,
+                available: 5.0,
+                held: 10.0,
+                total: 15.0,
+                locked: false,
+                frozen: false,
+                outstanding: None,
+            },
+        )]));
+
+        bank.transactions = RefCell::new(TransactionStore::from_iter([(
+            2,
+            (
+                Transaction {
+                    tx_type: TransactionType::Withdrawal,
+                    client_id: 5,
+                    id: 2,
+                    amount: Some(10.0),
+                    timestamp: None,
+                    tags: None,
+                    description: None,
+                    external_id: None,
+                    mandate: None,
+                },
+                TransactionStatus::Disputed,
+            ),
+        )]));
+
+        // WHEN
+        let result = bank.batch_process(vec![chargeback]);
+
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(bank.accounts.borrow()[&5].client_id, 5);
+        assert_eq!(bank.accounts.borrow()[&5].held, 0.0);
+        assert_eq!(bank.accounts.borrow()[&5].total, 5.0);
+        assert_eq!(bank.accounts.borrow()[&5].available, 5.0);
+        assert!(bank.accounts.borrow()[&5].locked);
+    }
+
+    #[test]
+    fn test_dispute_status() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+
+        // WHEN
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 10.0).with_timestamp(100),
+            Transaction::deposit(1, 2, 10.0).with_timestamp(110),
+            Transaction::dispute(1, 1).with_timestamp(200),
+            Transaction::dispute(1, 2).with_timestamp(210),
+            Transaction::chargeback(1, 1).with_timestamp(300),
+        ])
+        .unwrap();
+
+        // THEN
+        assert_eq!(
+            bank.dispute_status(1),
+            Some(DisputeState {
+                tx_id: 1,
+                disputed_by: 1,
+                disputed_at: Some(200),
+                status: TransactionStatus::ChargedBack,
+                closed_at: Some(300),
+            })
+        );
+        assert_eq!(
+            bank.dispute_status(2)
+                .map(|state| (state.status, state.closed_at)),
+            Some((TransactionStatus::Disputed, None))
+        );
+        assert_eq!(bank.dispute_status(3), None);
+    }
+
+    #[test]
+    fn test_batch_summary() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+
+        // WHEN
+        let summary = bank
+            .batch_process(vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::withdrawal(1, 2, 20.0),
+                Transaction::dispute(2, 9),
+                Transaction::dispute(1, 1),
+                Transaction::withdrawal(1, 3, 20.0),
+            ])
+            .unwrap();
+
+        // THEN
+        assert_eq!(summary.applied, 2);
+        assert_eq!(
+            summary.rejected,
+            BTreeMap::from([
+                (ErrorCode::InsufficientFunds, 2),
+                (ErrorCode::UnknownTransaction, 1)
+            ])
+        );
+        assert_eq!(summary.rows(), 5);
+        assert_eq!(summary.accounts_touched(), 2);
+    }
+
+    #[test]
+    fn test_process_from_reader() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,10.0\n\
+                     withdrawal,1,2,4.5\n\
+                     withdrawal,2,3,1.0\n";
+
+        // WHEN
+        let summary = bank.process_from_reader(input.as_bytes()).unwrap();
+
+        // THEN
+        assert_eq!(summary.applied, 2);
+        assert_eq!(summary.rows(), 3);
+        assert_eq!(bank.account(1).unwrap().available, 5.5);
+        assert!(bank
+            .process_from_reader("type,client,tx,amount\nrefund,1,4,1.0\n".as_bytes())
+            .is_err());
+    }
+
+    #[test]
+    fn test_process_chunks_reports_progress_after_each_chunk() {
+        // GIVEN
+        let deposits = (1..=5).map(|id| Transaction {
+            tx_type: TransactionType::Deposit,
+            client_id: 1,
+            id,
+            amount: Some(1.0),
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        });
+        let bank = Bank::<f32>::default();
+        let mut progress = Vec::new();
+
+        // WHEN
+        let result = bank.process_chunks(deposits, 2, |processed| {
+            progress.push(processed);
+            ControlFlow::Continue(())
+        });
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(progress, vec![2, 4, 5]);
+        assert_eq!(bank.accounts.borrow()[&1].total, 5.0);
+    }
+
+    #[test]
+    fn test_process_chunks_stops_on_break() {
+        // GIVEN
+        let deposits = (1..=5).map(|id| Transaction {
+            tx_type: TransactionType::Deposit,
+            client_id: 1,
+            id,
+            amount: Some(1.0),
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        });
+        let bank = Bank::<f32>::default();
+
+        // WHEN
+        let result = bank.process_chunks(deposits, 2, |_| ControlFlow::Break(()));
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(bank.accounts.borrow()[&1].total, 2.0);
+        assert_eq!(bank.transactions.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_process_chunks_zero_chunk_size_error() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+
+        // WHEN
+        let result = bank.process_chunks(Vec::new(), 0, |_| ControlFlow::Continue(()));
+
+        // THEN
+        assert_eq!(
+            result,
+            Err(BankError::new(
+                ErrorCode::InvalidChunkSize,
+                "Chunk size must be greater than zero"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_process_transaction_withdrawal_insufficient_funds_outcome() {
+        // GIVEN
+        let withdrawal = Transaction {
+            tx_type: TransactionType::Withdrawal,
+            client_id: 5,
+            id: 2,
+            amount: Some(45.0),
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+        let bank = Bank::<f32>::default();
+
+        // WHEN
+        let result = bank.process_transaction(withdrawal);
+
+        // THEN
+        assert_eq!(result, Ok(TxOutcome::RejectedInsufficientFunds));
+        assert_eq!(bank.transactions.borrow().len(), 0);
+    }
+
+    #[test]
+    fn test_process_transaction_dispute_unknown_transaction_outcome() {
+        // GIVEN
+        let dispute = Transaction {
+            tx_type: TransactionType::Dispute,
+            client_id: 5,
+            id: 2,
+            amount: None,
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+        let bank = Bank::<f32>::default();
+
+        // WHEN
+        let result = bank.process_transaction(dispute);
+
+        // THEN
+        assert_eq!(result, Ok(TxOutcome::IgnoredUnknownTransaction));
+    }
+
+    #[test]
+    fn test_process_transaction_client_mismatch_keeps_transaction() {
+        // GIVEN
+        let dispute = Transaction {
+            tx_type: TransactionType::Dispute,
+            client_id: 15,
+            id: 2,
+            amount: None,
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+        let mut bank: Bank<f32> = BankBuilder::default().build();
+        bank.transactions = RefCell::new(TransactionStore::from_iter([(
+            2,
+            (
+                Transaction {
+                    tx_type: TransactionType::Deposit,
+                    client_id: 5,
+                    id: 2,
+                    amount: Some(10.0),
+                    timestamp: None,
+                    tags: None,
+                    description: None,
+                    external_id: None,
+                    mandate: None,
+                },
+                TransactionStatus::Processed,
+            ),
+        )]));
+
+        // WHEN
+        let result = bank.process_transaction(dispute);
+
+        // THEN
+        assert_eq!(result, Ok(TxOutcome::IgnoredClientMismatch));
+        assert_eq!(
+            bank.transactions.borrow().get(2).unwrap().1,
+            TransactionStatus::Processed
+        );
+    }
+
+    #[test]
+    fn test_process_transaction_no_amount_keeps_existing_account() {
+        // GIVEN
+        let deposit = Transaction {
+            tx_type: TransactionType::Deposit,
+            client_id: 5,
+            id: 3,
+            amount: None,
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+        let mut bank: Bank<f32> = BankBuilder::default().build();
+        bank.accounts = RefCell::new(BTreeMap::from([(
+            5,
+            Account {
+                client_id: 5,
+                available: 30.0,
+                held: 0.0,
+                total: 30.0,
+                locked: false,
+                frozen: false,
+                outstanding: None,
+            },
+        )]));
+
+        // WHEN
+        let result = bank.process_transaction(deposit);
+
+        // THEN
+        assert!(result.is_err());
+        assert_eq!(bank.accounts.borrow().len(), 1);
+        assert_eq!(bank.accounts.borrow()[&5].total, 30.0);
+    }
+
+    #[test]
+    fn test_batch_process_with_reports_every_outcome() {
+        // GIVEN
+        let deposit = Transaction {
+            tx_type: TransactionType::Deposit,
+            client_id: 1,
+            id: 1,
+            amount: Some(10.0),
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+        let withdrawal = Transaction {
+            tx_type: TransactionType::Withdrawal,
+            client_id: 1,
+            id: 2,
+            amount: Some(20.0),
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+        let dispute = Transaction {
+            tx_type: TransactionType::Dispute,
+            client_id: 1,
+            id: 1,
+            amount: None,
+            timestamp: None,
+            tags: None,
+            description: None,
+            external_id: None,
+            mandate: None,
+        };
+        let bank = Bank::<f32>::default();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank
+            .batch_process_with(vec![deposit, withdrawal, dispute], |tx_id, outcome| {
+                outcomes.push((tx_id, outcome))
+            });
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            outcomes,
+            vec![
+                (1, TxOutcome::Applied),
+                (2, TxOutcome::RejectedInsufficientFunds),
+                (1, TxOutcome::Applied),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transaction_csv_round_trip() {
+        // GIVEN
+        let transactions = vec![
+            Transaction::deposit(1, 1, 2.5)
+                .with_tags("order-7")
+                .with_description("Salary"),
+            Transaction::dispute(1, 1),
+        ];
+        let mut writer = csv::Writer::from_writer(Vec::new());
+
+        // WHEN
+        for tx in &transactions {
+            writer.serialize(tx).unwrap();
+        }
+        let output = writer.into_inner().unwrap();
+        let parsed: Vec<Transaction<f32>> = csv::Reader::from_reader(output.as_slice())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        // THEN
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "type,client,tx,amount,timestamp,tags,description,external_id,mandate\ndeposit,1,1,2.5,,order-7,Salary,,\ndispute,1,1,,,,,,\n"
+        );
+        assert_eq!(parsed, transactions);
+    }
+
+    #[test]
+    fn test_transaction_tags_are_optional_and_queryable() {
+        // GIVEN
+        let input = "type,client,tx,amount,tags\ndeposit,1,1,2.0,ref-1\ndeposit,1,2,3.0,\n";
+        let transactions: Vec<Transaction<f32>> = csv::Reader::from_reader(input.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let bank = Bank::<f32>::default();
+
+        // WHEN
+        let result = bank.batch_process(transactions);
+
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(bank.transaction(1).unwrap().tags(), Some("ref-1"));
+        assert_eq!(bank.transaction(2).unwrap().tags(), None);
+        assert!(bank.transaction(3).is_none());
+    }
+
+    #[test]
+    fn test_account_view_query() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 10.0),
+            Transaction::deposit(2, 2, 5.0),
+            Transaction::dispute(2, 2),
+        ])
+        .unwrap();
+
+        // WHEN
+        let account = bank.account(2);
+
+        // THEN
+        assert_eq!(
+            account,
+            Some(AccountView {
+                client_id: 2,
+                available: 0.0,
+                held: 5.0,
+                total: 5.0,
+                locked: false,
+                frozen: false,
+            })
+        );
+        assert_eq!(bank.account(3), None);
+        assert_eq!(bank.accounts().len(), 2);
+    }
+
+    #[test]
+    fn test_account_view_approx_eq() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 0.1),
+            Transaction::deposit(1, 2, 0.2),
+        ])
+        .unwrap();
+        let expected = AccountView {
+            client_id: 1,
+            available: 0.3,
+            held: 0.0,
+            total: 0.3,
+            locked: false,
+            frozen: false,
+        };
+
+        // WHEN
+        let account = bank.account(1).unwrap();
+
+        // THEN
+        assert!(account.approx_eq(&expected, 0.0001));
+        assert!(!account.approx_eq(
+            &AccountView {
+                locked: true,
+                ..expected
+            },
+            0.0001
+        ));
+    }
+
+    #[test]
+    fn test_find_transactions() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 10.0).with_timestamp(100),
+            Transaction::deposit(2, 2, 20.0).with_timestamp(200),
+            Transaction::deposit(1, 3, 30.0).with_timestamp(300),
+            Transaction::withdrawal(1, 4, 5.0),
+            Transaction::dispute(1, 3),
+        ])
+        .unwrap();
+        let ids = |filter: TransactionFilter<f32>| -> Vec<u32> {
+            bank.find_transactions(&filter)
+                .map(|entry| entry.transaction.id())
+                .collect()
+        };
+
+        // THEN
+        assert_eq!(ids(TransactionFilter::default()), vec![1, 2, 3, 4]);
+        assert_eq!(
+            ids(TransactionFilter {
+                client_id: Some(1),
+                tx_type: Some(TransactionType::Deposit),
+                ..Default::default()
+            }),
+            vec![1, 3]
+        );
+        assert_eq!(
+            ids(TransactionFilter {
+                status: Some(TransactionStatus::Disputed),
+                ..Default::default()
+            }),
+            vec![3]
+        );
+        assert_eq!(
+            ids(TransactionFilter {
+                min_amount: Some(10.0),
+                max_amount: Some(20.0),
+                ..Default::default()
+            }),
+            vec![1, 2]
+        );
+        assert_eq!(
+            ids(TransactionFilter {
+                from: Some(150),
+                until: Some(300),
+                ..Default::default()
+            }),
+            vec![2, 3]
+        );
+    }
+
+    #[test]
+    fn test_find_transactions_reads_lazily() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 10.0),
+            Transaction::deposit(1, 3, 30.0),
+        ])
+        .unwrap();
+        let mut found = bank.find_transactions(&TransactionFilter::default());
+
+        // WHEN
+        let first = found.next().unwrap();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 2, 20.0),
+            Transaction::dispute(1, 3),
+        ])
+        .unwrap();
+        let rest: Vec<_> = found.collect();
+
+        // THEN
+        assert_eq!(first.transaction.id(), 1);
+        assert_eq!(
+            rest.iter()
+                .map(|entry| (entry.transaction.id(), entry.status))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, TransactionStatus::Processed),
+                (3, TransactionStatus::Disputed)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_builder_overdraft_allows_withdrawal_below_zero() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default().overdraft(10.0).build();
+
+        // WHEN
+        let result = bank.batch_process(vec![
+            Transaction::deposit(1, 1, 5.0),
+            Transaction::withdrawal(1, 2, 12.0),
+            Transaction::withdrawal(1, 3, 4.0),
+        ]);
+
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(bank.account(1).unwrap().available, -7.0);
+        assert_eq!(bank.account(1).unwrap().total, -7.0);
+    }
+
+    #[test]
+    fn test_builder_error_policy_skip_continues_batch() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .error_policy(ErrorPolicy::Skip)
+            .build();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::new(TransactionType::Deposit, 1, 1, None),
+                Transaction::deposit(1, 2, 5.0),
+            ],
+            |tx_id, outcome| outcomes.push((tx_id, outcome)),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            outcomes,
+            vec![(1, TxOutcome::RejectedMalformed), (2, TxOutcome::Applied)]
+        );
+        assert_eq!(bank.account(1).unwrap().total, 5.0);
+    }
+
+    #[test]
+    fn test_backfill_skips_applied_transactions() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 5.0),
+            Transaction::withdrawal(1, 2, 2.0),
+        ])
+        .unwrap();
+        let history = "type,client,tx,amount\n\
+                       deposit,1,1,5.0\n\
+                       withdrawal,1,2,2.0\n\
+                       deposit,1,3,4.0\n\
+                       dispute,1,1,\n";
+        let mut source =
+            crate::input::CsvSource::new(history.as_bytes(), crate::input::TypeNames::strict())
+                .unwrap();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let skipped = bank
+            .backfill_source(&mut source, |processed, outcome| {
+                outcomes.push((processed.tx_id, outcome))
+            })
+            .unwrap();
+
+        // THEN
+        assert_eq!(skipped, 2);
+        assert_eq!(
+            outcomes,
+            vec![(3, TxOutcome::Applied), (1, TxOutcome::Applied)]
+        );
+        let account = bank.account(1).unwrap();
+        assert_eq!(account.available, 2.0);
+        assert_eq!(account.held, 5.0);
+    }
+
+    #[test]
+    fn test_watermark_times_disputes_without_timestamp() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .dispute_window(Duration::from_secs(60))
+            .build();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 5.0).with_timestamp(1_000),
+                Transaction::deposit(2, 2, 5.0).with_timestamp(1_100),
+                Transaction::deposit(2, 3, 5.0).with_timestamp(900),
+                Transaction::dispute(1, 1),
+            ],
+            |tx_id, outcome| outcomes.push((tx_id, outcome)),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(bank.watermark(), Some(1_100));
+        assert_eq!(outcomes[3], (1, TxOutcome::IgnoredDisputeWindowExpired));
+    }
+
+    #[test]
+    fn test_builder_dispute_window_ignores_late_dispute() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .dispute_window(Duration::from_secs(60))
+            .build();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 5.0).with_timestamp(1_000),
+            Transaction::deposit(1, 2, 5.0).with_timestamp(1_000),
+        ])
+        .unwrap();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::dispute(1, 1).with_timestamp(1_061),
+                Transaction::dispute(1, 2).with_timestamp(1_060),
+            ],
+            |tx_id, outcome| outcomes.push((tx_id, outcome)),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            outcomes,
+            vec![
+                (1, TxOutcome::IgnoredDisputeWindowExpired),
+                (2, TxOutcome::Applied)
+            ]
+        );
+        assert_eq!(bank.account(1).unwrap().held, 5.0);
+    }
+
+    #[test]
+    fn test_daily_withdrawal_limit_with_client_override() {
+        // GIVEN
+        let day = SECONDS_PER_DAY;
+        let bank = BankBuilder::<f32>::default()
+            .daily_withdrawal_limit(100.0)
+            .client_daily_withdrawal_limit(2, 500.0)
+            .build();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 1_000.0),
+                Transaction::deposit(2, 2, 1_000.0),
+                Transaction::withdrawal(1, 3, 60.0).with_timestamp(day),
+                Transaction::withdrawal(1, 4, 60.0).with_timestamp(day + 10),
+                Transaction::withdrawal(1, 5, 40.0).with_timestamp(day + 20),
+                Transaction::withdrawal(1, 6, 60.0).with_timestamp(2 * day),
+                Transaction::withdrawal(2, 7, 400.0).with_timestamp(day),
+                Transaction::withdrawal(1, 8, 500.0),
+            ],
+            |tx_id, outcome| outcomes.push((tx_id, outcome)),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            outcomes[2..],
+            [
+                (3, TxOutcome::Applied),
+                (4, TxOutcome::RejectedDailyLimit),
+                (5, TxOutcome::Applied),
+                (6, TxOutcome::Applied),
+                (7, TxOutcome::Applied),
+                (8, TxOutcome::Applied),
+            ]
+        );
+        assert_eq!(bank.account(1).unwrap().total, 340.0);
+        assert_eq!(bank.account(2).unwrap().total, 600.0);
+    }
+
+    #[test]
+    fn test_client_override_takes_precedence() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .overdraft(5.0)
+            .dispute_window(Duration::from_secs(10))
+            .client_override(ClientOverride {
+                overdraft: Some(50.0),
+                dispute_window: Some(Duration::from_secs(100)),
+                ..ClientOverride::new(2)
+            })
+            .build();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 10.0).with_timestamp(0),
+                Transaction::deposit(2, 2, 10.0).with_timestamp(0),
+                Transaction::withdrawal(1, 3, 20.0),
+                Transaction::withdrawal(2, 4, 20.0),
+                Transaction::dispute(1, 1).with_timestamp(50),
+                Transaction::dispute(2, 2).with_timestamp(50),
+            ],
+            |tx_id, outcome| outcomes.push((tx_id, outcome)),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            outcomes[2..],
+            [
+                (3, TxOutcome::RejectedInsufficientFunds),
+                (4, TxOutcome::Applied),
+                (1, TxOutcome::IgnoredDisputeWindowExpired),
+                (2, TxOutcome::NegativeAvailable),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unverified_client_capped_deposits_and_no_withdrawals() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .unverified_balance_cap(100.0)
+            .client_kyc(1, KycStatus::Unverified)
+            .client_kyc(2, KycStatus::Verified)
+            .build();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 80.0),
+                Transaction::deposit(1, 2, 30.0),
+                Transaction::withdrawal(1, 3, 10.0),
+                Transaction::deposit(2, 4, 500.0),
+                Transaction::withdrawal(2, 5, 10.0),
+            ],
+            |tx_id, outcome| outcomes.push((tx_id, outcome)),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            outcomes,
+            vec![
+                (1, TxOutcome::Applied),
+                (2, TxOutcome::Rejected(Rejection::KycDepositCap)),
+                (3, TxOutcome::Rejected(Rejection::KycWithdrawal)),
+                (4, TxOutcome::Applied),
+                (5, TxOutcome::Applied),
+            ]
+        );
+        assert_eq!(bank.account(1).unwrap().total, 80.0);
+    }
+
+    #[test]
+    fn test_freeze_blocks_activity_until_unfrozen() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::freeze(1, 2),
+                Transaction::withdrawal(1, 3, 5.0),
+                Transaction::dispute(1, 1),
+                Transaction::unfreeze(1, 4),
+                Transaction::withdrawal(1, 5, 5.0),
+            ],
+            |tx_id, outcome| outcomes.push((tx_id, outcome)),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            outcomes,
+            vec![
+                (1, TxOutcome::Applied),
+                (2, TxOutcome::Applied),
+                (3, TxOutcome::Rejected(Rejection::AccountFrozen)),
+                (1, TxOutcome::Rejected(Rejection::AccountFrozen)),
+                (4, TxOutcome::Applied),
+                (5, TxOutcome::Applied),
+            ]
+        );
+        let account = bank.account(1).unwrap();
+        assert_eq!(account.total, 5.0);
+        assert!(!account.frozen);
+        assert!(!account.locked);
+    }
+
+    #[test]
+    fn test_dispute_driving_available_negative_raises_alert() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::withdrawal(1, 2, 8.0),
+                Transaction::dispute(1, 1),
+                Transaction::deposit(2, 3, 10.0),
+                Transaction::dispute(2, 3),
+            ],
+            |tx_id, outcome| outcomes.push((tx_id, outcome)),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(outcomes[2], (1, TxOutcome::NegativeAvailable));
+        assert_eq!(outcomes[4], (3, TxOutcome::Applied));
+        assert_eq!(bank.negative_available_alerts(), 1);
+        let negative = bank.negative_available_accounts();
+        assert_eq!(negative.len(), 1);
+        assert_eq!(negative[0].client_id, 1);
+        assert_eq!(negative[0].available, -8.0);
+    }
+
+    #[test]
+    #[cfg(feature = "invariant-checks")]
+    #[should_panic(expected = "Invariant violated after transaction #7")]
+    fn test_invariant_checks_name_offending_transaction() {
+        // GIVEN
+        let mut bank: Bank<f32> = BankBuilder::default().build();
+        bank.accounts = RefCell::new(BTreeMap::from([(
+            1,
+            Account {
+                client_id: 1,
+                available: 10.0,
+                held: 0.0,
+                total: 12.0,
+                locked: false,
+                frozen: false,
+                outstanding: None,
+            },
+        )]));
+
+        // WHEN
+        let _ = bank.process_transaction(Transaction::deposit(1, 7, 1.0));
+    }
+
+    #[test]
+    #[cfg(feature = "invariant-checks")]
+    fn test_invariant_checks_allow_f32_rounding_of_large_amounts() {
+        // GIVEN
+        let hold_large = "[[rule]]\nmin_amount = 1000\naction = \"hold\"\n";
+        let bank: Bank<f32> = BankBuilder::default()
+            .rules(crate::rules::read_rules(hold_large).unwrap())
+            .build();
+
+        // WHEN
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 366.28833),
+            Transaction::deposit(1, 2, 1.5e20),
+            Transaction::decline(1, 2),
+            Transaction::deposit(1, 3, 1.0),
+        ])
+        .unwrap();
+
+        // THEN
+        assert_eq!(bank.account(1).unwrap().available, 367.28833);
+    }
+
+    #[test]
+    fn test_dispute_overdraw_policy_cap_holds_available_only() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .dispute_overdraw_policy(DisputeOverdrawPolicy::Cap)
+            .build();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::withdrawal(1, 2, 6.0),
+                Transaction::dispute(1, 1),
+            ],
+            |_, outcome| outcomes.push(outcome),
+        );
+        let after_dispute = bank.account(1).unwrap();
+        bank.process_transaction(Transaction::chargeback(1, 1))
+            .unwrap();
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(outcomes[2], TxOutcome::DisputeCapped);
+        assert_eq!(after_dispute.available, 0.0);
+        assert_eq!(after_dispute.held, 4.0);
+        let account = bank.account(1).unwrap();
+        assert_eq!(account.held, 0.0);
+        assert_eq!(account.total, 0.0);
+        assert!(account.locked);
+    }
+
+    #[test]
+    fn test_dispute_overdraw_policy_reject_keeps_transaction_disputable() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .dispute_overdraw_policy(DisputeOverdrawPolicy::Reject)
+            .build();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::withdrawal(1, 2, 6.0),
+                Transaction::dispute(1, 1),
+                Transaction::deposit(1, 3, 6.0),
+                Transaction::dispute(1, 1),
+            ],
+            |_, outcome| outcomes.push(outcome),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(outcomes[2], TxOutcome::RejectedDisputeOverdraw);
+        assert_eq!(outcomes[4], TxOutcome::Applied);
+        assert_eq!(bank.account(1).unwrap().held, 10.0);
+    }
+
+    #[test]
+    fn test_withdrawals_not_disputable_when_disabled() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .disputable_withdrawals(false)
+            .build();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::withdrawal(1, 2, 4.0),
+                Transaction::dispute(1, 2),
+                Transaction::dispute(1, 1),
+            ],
+            |_, outcome| outcomes.push(outcome),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Applied,
+                TxOutcome::Applied,
+                TxOutcome::IgnoredWithdrawalNotDisputable,
+                TxOutcome::NegativeAvailable,
+            ]
+        );
+        assert!(!bank.transactions.borrow().contains_key(2));
+    }
+
+    #[test]
+    fn test_repeated_dispute_and_resolve_are_no_ops() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::dispute(1, 1),
+                Transaction::dispute(1, 1),
+                Transaction::resolve(1, 1),
+                Transaction::resolve(1, 1),
+                Transaction::dispute(1, 1),
+                Transaction::chargeback(1, 1),
+                Transaction::chargeback(1, 1),
+            ],
+            |_, outcome| outcomes.push(outcome),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Applied,
+                TxOutcome::Applied,
+                TxOutcome::AlreadyInState,
+                TxOutcome::Applied,
+                TxOutcome::AlreadyInState,
+                TxOutcome::Applied,
+                TxOutcome::Applied,
+                TxOutcome::AlreadyInState,
+            ]
+        );
+        let account = bank.account(1).unwrap();
+        assert_eq!(account.held, 0.0);
+        assert_eq!(account.total, 0.0);
+    }
+
+    #[test]
+    fn test_locked_account_policy_allow_deposits() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .locked_account_policy(LockedAccountPolicy::AllowDeposits)
+            .build();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::deposit(1, 2, 10.0),
+                Transaction::dispute(1, 1),
+                Transaction::chargeback(1, 1),
+                Transaction::deposit(1, 3, 5.0),
+                Transaction::withdrawal(1, 4, 5.0),
+            ],
+            |_, outcome| outcomes.push(outcome),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(outcomes[4], TxOutcome::Applied);
+        assert_eq!(outcomes[5], TxOutcome::Rejected(Rejection::AccountLocked));
+        assert_eq!(bank.account(1).unwrap().total, 15.0);
+    }
+
+    #[test]
+    fn test_locked_account_policy_block_all() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .locked_account_policy(LockedAccountPolicy::BlockAll)
+            .build();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::dispute(1, 1),
+                Transaction::chargeback(1, 1),
+                Transaction::deposit(1, 2, 5.0),
+            ],
+            |_, outcome| outcomes.push(outcome),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(outcomes[3], TxOutcome::Rejected(Rejection::AccountLocked));
+        assert_eq!(bank.account(1).unwrap().total, 0.0);
+    }
+
+    struct ReviewLarge;
+
+    impl TxValidator<f32> for ReviewLarge {
+        fn validate(
+            &self,
+            _: &Transaction<f32>,
+            _: &AccountView<f32>,
+            _: &TxHistory<f32>,
+        ) -> Result<(), Rejection> {
+            Ok(())
+        }
+
+        fn needs_review(
+            &self,
+            tx: &Transaction<f32>,
+            _: &AccountView<f32>,
+            _: &TxHistory<f32>,
+        ) -> bool {
+            tx.amount().is_some_and(|amount| amount >= 100.0)
+        }
+    }
+
+    #[test]
+    fn test_pending_transactions_settle_on_approve_and_decline() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default().validator(ReviewLarge).build();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 500.0),
+                Transaction::deposit(1, 2, 50.0),
+                Transaction::withdrawal(1, 3, 20.0),
+            ],
+            |_, outcome| outcomes.push(outcome),
+        );
+        let while_pending = bank.account(1).unwrap();
+        let pending = bank.pending_transactions();
+        bank.process_transaction(Transaction::approve(1, 1))
+            .unwrap();
+        let after_approve = bank.account(1).unwrap();
+        bank.process_transaction(Transaction::withdrawal(1, 4, 200.0))
+            .unwrap();
+        let repeated = bank.process_transaction(Transaction::approve(1, 1));
+        bank.process_transaction(Transaction::decline(1, 4))
+            .unwrap();
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(outcomes[0], TxOutcome::Pending);
+        assert_eq!(pending, vec![Transaction::deposit(1, 1, 500.0)]);
+        assert_eq!(while_pending.available, 30.0);
+        assert_eq!(while_pending.held, 500.0);
+        assert_eq!(while_pending.total, 530.0);
+        assert_eq!(after_approve.available, 530.0);
+        assert_eq!(after_approve.held, 0.0);
+        assert_eq!(repeated, Ok(TxOutcome::AlreadyInState));
+        let account = bank.account(1).unwrap();
+        assert_eq!(account.available, 530.0);
+        assert_eq!(account.held, 0.0);
+        assert_eq!(account.total, 530.0);
+        assert!(bank.pending_transactions().is_empty());
+    }
+
+    #[test]
+    fn test_lookup_by_external_id() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .validator(crate::validation::DuplicateTransaction)
+            .build();
+
+        // WHEN
+        let result = bank.batch_process(vec![
+            Transaction::deposit(1, 1, 10.0).with_external_id("PSP-1"),
+            Transaction::deposit(1, 1, 99.0).with_external_id("PSP-dup"),
+            Transaction::dispute(1, 1).with_external_id("PSP-2"),
+        ]);
+
+        // THEN
+        assert!(result.is_ok());
+        let (tx, status) = bank.transaction_by_external_id("PSP-1").unwrap();
+        assert_eq!(tx.id(), 1);
+        assert_eq!(status, TransactionStatus::Disputed);
+        assert_eq!(
+            bank.transaction_status(1),
+            Some(TransactionStatus::Disputed)
+        );
+        assert!(bank.transaction_by_external_id("PSP-dup").is_none());
+        assert!(bank.transaction_by_external_id("PSP-2").is_none());
+    }
+
+    #[test]
+    fn test_custom_transaction_type_handler() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .custom_type("fee", |tx, context| {
+                let amount = tx.amount().ok_or("fee without amount")?;
+                context.debit(amount)?;
+                context.store(tx.clone());
+                Ok(TxOutcome::Applied)
+            })
+            .build();
+        let fee = Transaction::new(TransactionType::Custom("fee"), 1, 2, Some(1.5));
+
+        // WHEN
+        let result = bank.batch_process(vec![Transaction::deposit(1, 1, 10.0), fee.clone()]);
+        let unknown = bank.process_transaction(Transaction::new(
+            TransactionType::Custom("bonus"),
+            1,
+            3,
+            Some(1.0),
+        ));
+
+        // THEN
+        assert!(result.is_ok());
+        assert!(unknown.is_err());
+        assert_eq!(bank.account(1).unwrap().total, 8.5);
+        assert_eq!(bank.transaction(2), Some(fee));
+    }
+
+    #[test]
+    fn test_decimal_amounts_are_exact() {
+        // GIVEN
+        let bank = Bank::new();
+        let tenth = Decimal::new(1, 1);
+
+        // WHEN
+        let mut transactions: Vec<Transaction> = (1..=10)
+            .map(|id| Transaction::deposit(1, id, tenth))
+            .collect();
+        transactions.push(Transaction::withdrawal(1, 11, Decimal::ONE));
+        let result = bank.batch_process(transactions);
+
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(bank.account(1).unwrap().total, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_minor_units_overflow_is_malformed() {
+        // GIVEN
+        let bank = BankBuilder::<MinorUnits>::default().build();
+
+        // WHEN
+        let first = bank.process_transaction(Transaction::deposit(1, 1, MinorUnits(i64::MAX)));
+        let second = bank.process_transaction(Transaction::deposit(1, 2, MinorUnits(1)));
+
+        // THEN
+        assert_eq!(first, Ok(TxOutcome::Applied));
+        assert_eq!(second.unwrap_err().code, ErrorCode::AmountOutOfRange);
+        assert_eq!(bank.account(1).unwrap().total, MinorUnits(i64::MAX));
+        assert_eq!(bank.transaction(2), None);
+    }
+
+    #[test]
+    fn test_write_report_as_each_format() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![
+            Transaction::deposit(12, 1, 1234.5),
+            Transaction::deposit(3, 2, 2.0),
+            Transaction::dispute(3, 2),
+        ])
+        .unwrap();
+        let write = |format: ReportFormat| {
+            let mut output = Vec::new();
+            bank.write_report_as(&format, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        // WHEN
+        let tsv = write(ReportFormat::Tsv);
+        let json = write(ReportFormat::Json);
+        let json_lines = write(ReportFormat::JsonLines);
+        let table = write(ReportFormat::Table);
+
+        // THEN
+        assert_eq!(write(ReportFormat::Csv), crate::testkit::report(&bank));
+        assert_eq!(
+            tsv,
+            "client\tavailable\theld\ttotal\tlocked\tfrozen\n\
+             3\t0.0000\t2.0000\t2.0000\tfalse\tfalse\n\
+             12\t1234.5000\t0.0000\t1234.5000\tfalse\tfalse\n"
+        );
+        assert_eq!(
+            json,
+            r#"[{"client":3,"available":"0.0000","held":"2.0000","total":"2.0000","locked":false,"frozen":false},{"client":12,"available":"1234.5000","held":"0.0000","total":"1234.5000","locked":false,"frozen":false}]"#
+                .to_string()
+                + "\n"
+        );
+        assert_eq!(json_lines.lines().count(), 2);
+        assert!(json_lines.starts_with(r#"{"client":3,"#));
+        assert_eq!(
+            table,
+            "client  available    held      total  locked  frozen\n\
+             \x20    3     0.0000  2.0000     2.0000  false   false\n\
+             \x20   12  1234.5000  0.0000  1234.5000  false   false\n"
+        );
+    }
+
+    #[test]
+    fn test_write_report_failure_has_code() {
+        // GIVEN
+        struct FailingWriter;
+        impl io::Write for FailingWriter {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk full"))
             }
-            TransactionType::Chargeback => {
-                match self.get_transaction_with_status(
-                    &account,
-                    &tx_id,
-                    TransactionStatus::Disputed,
-                ) {
-                    Ok(mut target_tx) => {
-                        let tx_amount = target_tx
-                            .0
-                            .amount
-                            .expect(INVALID_TRANSACTION_DATA_NO_AMOUNT);
-                        account.held -= tx_amount;
-                        account.total -= tx_amount;
-                        account.locked = true;
-                        target_tx.1 = TransactionStatus::Processed;
-                        self.transactions.borrow_mut().insert(tx_id, target_tx);
-                    }
-                    Err(e) => {
-                        eprintln!("{}", e);
-                    }
-                };
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![Transaction::deposit(1, 1, 1.0)])
+            .unwrap();
+
+        // WHEN
+        let report = bank.write_report(FailingWriter);
+        let sharded = bank.write_report_sharded(FailingWriter, 2);
+
+        // THEN
+        assert_eq!(report.unwrap_err().code, ErrorCode::WriteFailed);
+        assert_eq!(sharded.unwrap_err().code, ErrorCode::WriteFailed);
+    }
+
+    #[test]
+    fn test_write_report_batches_writes() {
+        // GIVEN
+        struct CountingWriter {
+            writes: usize,
+            bytes: usize,
+        }
+        impl io::Write for CountingWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.writes += 1;
+                self.bytes += buf.len();
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let bank = Bank::<f32>::default();
+        let deposits = (0..10_000).map(|id| Transaction::deposit(id as u16, id, 1.5));
+        bank.batch_process(deposits.collect()).unwrap();
+        let mut writer = CountingWriter {
+            writes: 0,
+            bytes: 0,
+        };
+
+        // WHEN
+        let result = bank.write_report(&mut writer);
+
+        // THEN
+        assert!(result.is_ok());
+        assert!(writer.bytes > 4 * REPORT_BUFFER_BYTES);
+        assert!(writer.writes <= writer.bytes / REPORT_BUFFER_BYTES + 1);
+    }
+
+    #[test]
+    fn test_sharded_report_matches_sequential() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        let deposits = (0..10).map(|id| Transaction::deposit(id as u16, id, id as f32));
+        bank.batch_process(deposits.collect()).unwrap();
+        let mut sequential = Vec::new();
+        let mut sharded = Vec::new();
+        let mut empty = Vec::new();
+
+        // WHEN
+        bank.write_report(&mut sequential).unwrap();
+        bank.write_report_sharded(&mut sharded, 3).unwrap();
+        Bank::<f32>::default()
+            .write_report_sharded(&mut empty, 3)
+            .unwrap();
+
+        // THEN
+        assert_eq!(String::from_utf8(sharded), String::from_utf8(sequential));
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_sharded_report_is_deterministic_for_any_shard_count() {
+        // GIVEN
+        let bank = crate::testkit::bank_with_deposits(
+            &(0..500)
+                .map(|client| (client, f32::from(client) / 8.0))
+                .collect::<Vec<_>>(),
+        );
+        for client in (0..500).step_by(7) {
+            let first_id = 1_000 + u32::from(client) * 10;
+            bank.batch_process(crate::testkit::dispute_storm(client, first_id, 5, 0.25))
+                .unwrap();
+        }
+        let mut sequential = Vec::new();
+        bank.write_report(&mut sequential).unwrap();
+        let state_sha256 = crate::provenance::Provenance::new(&bank, io::empty(), 0)
+            .unwrap()
+            .state_sha256;
+
+        // WHEN
+        for _ in 0..3 {
+            for shards in [0, 1, 2, 3, 4, 7, 8, 16, 31, 64, 499, 500, 501, 4_096] {
+                let mut sharded = Vec::new();
+                bank.write_report_sharded(&mut sharded, shards).unwrap();
+
+                // THEN
+                assert!(sharded == sequential, "{} shards", shards);
+                assert_eq!(format!("{:x}", Sha256::digest(&sharded)), state_sha256);
             }
-        };
-        self.accounts.borrow_mut().push(account);
-        Ok(result)
+        }
     }
 
-    fn get_transaction_with_status(
-        &self,
-        account: &Account,
-        tx_id: &u32,
-        desired_status: TransactionStatus,
-    ) -> Result<TransactionRecord, String> {
-        if let Some(target_tx) = self.transactions.borrow_mut().remove(&tx_id) {
-            if target_tx.0.client_id != account.client_id {
-                return Err(format!(
-                    "Transaction #{} does not have matching client id",
-                    tx_id
-                ));
-            }
-            if desired_status != target_tx.1 {
-                return Err(format!("Transaction #{} not in desired state", tx_id));
-            }
-            return Ok(target_tx);
-        } else {
-            return Err(format!("Transaction #{} not found", tx_id));
+    #[test]
+    fn test_extended_report() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 10.0).with_timestamp(300),
+            Transaction::deposit(1, 2, 5.0).with_timestamp(100),
+            Transaction::withdrawal(1, 3, 1.0),
+            Transaction::dispute(1, 2).with_timestamp(400),
+            Transaction::deposit(2, 4, 1.0),
+        ])
+        .unwrap();
+        let mut report = Vec::new();
+
+        // WHEN
+        bank.write_extended_report(&mut report).unwrap();
+
+        // THEN
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "client,available,held,total,locked,frozen,tx_count,disputed_count,last_activity,\
+             opened_at,outstanding\n\
+             1,9.0000,5.0000,14.0000,false,false,3,1,300,100,\n\
+             2,1.0000,0.0000,1.0000,false,false,1,0,,,\n"
+        );
+    }
+
+    fn golden_report<M: Money>() -> String {
+        let input = include_str!("../testdata/golden_transactions.csv");
+        let bank = BankBuilder::<M>::default().build();
+        bank.batch_process(read_transactions(input.as_bytes(), &TypeNames::strict()).unwrap())
+            .unwrap();
+        let mut report = Vec::new();
+        bank.write_report(&mut report).unwrap();
+        String::from_utf8(report).unwrap()
+    }
+
+    #[test]
+    fn test_report_matches_golden_file() {
+        let expected = include_str!("../testdata/golden_report.csv");
+        assert_eq!(golden_report::<f32>(), expected);
+        assert_eq!(golden_report::<Decimal>(), expected);
+    }
+
+    /// Amounts for the fuzz tests, including extreme and invalid ones.
+    trait FuzzAmount: Money {
+        fn arbitrary_amount(g: &mut Gen) -> Self;
+    }
+
+    impl FuzzAmount for f32 {
+        fn arbitrary_amount(g: &mut Gen) -> Self {
+            f32::arbitrary(g)
         }
     }
 
-    fn get_account(&self, client_id: u16) -> Option<Account> {
-        let index;
-        {
-            index = self
-                .accounts
-                .borrow()
-                .iter()
-                .position(|x| x.client_id == client_id);
+    impl FuzzAmount for Decimal {
+        fn arbitrary_amount(g: &mut Gen) -> Self {
+            Decimal::new(i64::arbitrary(g), u32::arbitrary(g) % 29)
         }
-        match index {
-            Some(i) => Some(self.accounts.borrow_mut().remove(i)),
-            None => None,
+    }
+
+    impl FuzzAmount for MinorUnits {
+        fn arbitrary_amount(g: &mut Gen) -> Self {
+            MinorUnits(i64::arbitrary(g))
         }
     }
 
-    pub fn print_report(&self) -> Result<(), Box<dyn Error>> {
-        let mut writer = csv::Writer::from_writer(io::stdout());
-        for account in self.accounts.borrow().iter() {
-            writer.serialize(account)?;
+    /// A transaction on a few clients and ids, so they run into each other.
+    #[derive(Debug, Clone)]
+    struct FuzzTx<M>(Transaction<M>);
+
+    impl<M: FuzzAmount> Arbitrary for FuzzTx<M> {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let tx_type = *g
+                .choose(&[
+                    TransactionType::Deposit,
+                    TransactionType::Withdrawal,
+                    TransactionType::Dispute,
+                    TransactionType::Resolve,
+                    TransactionType::Chargeback,
+                    TransactionType::Freeze,
+                    TransactionType::Unfreeze,
+                    TransactionType::Approve,
+                    TransactionType::Decline,
+                    TransactionType::DirectDebit,
+                    TransactionType::DebitReturn,
+                ])
+                .unwrap();
+            let amount = bool::arbitrary(g).then(|| M::arbitrary_amount(g));
+            let tx = Transaction::new(
+                tx_type,
+                u16::arbitrary(g) % 4,
+                u32::arbitrary(g) % 16,
+                amount,
+            );
+            if bool::arbitrary(g) {
+                FuzzTx(tx.with_timestamp(u64::arbitrary(g)))
+            } else {
+                FuzzTx(tx)
+            }
         }
-        writer.flush()?;
-        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Banks covering the policies, each run through the same input.
+    fn fuzz_banks<M: Money>() -> Vec<Bank<M>> {
+        let hold_large = "[[rule]]\nmin_amount = 1000\naction = \"hold\"\n";
+        vec![
+            BankBuilder::default().build(),
+            BankBuilder::default()
+                .overdraft(M::from_whole(5))
+                .disputable_withdrawals(true)
+                .dispute_overdraw_policy(DisputeOverdrawPolicy::Cap)
+                .daily_withdrawal_limit(M::from_whole(100))
+                .build(),
+            BankBuilder::default()
+                .locked_account_policy(LockedAccountPolicy::BlockAll)
+                .dispute_overdraw_policy(DisputeOverdrawPolicy::Reject)
+                .dispute_window(Duration::from_secs(60))
+                .auto_unlock_after(Duration::from_secs(60))
+                .validator(crate::validation::DuplicateTransaction)
+                .rules(crate::rules::read_rules(hold_large).unwrap())
+                .aml(AmlConfig::default())
+                .build(),
+        ]
+    }
+
+    fn engine_never_panics<M: Money>(transactions: Vec<FuzzTx<M>>) -> bool {
+        for bank in fuzz_banks::<M>() {
+            for FuzzTx(tx) in transactions.clone() {
+                let _ = bank.process_transaction(tx);
+            }
+            let _ = bank.write_report(io::sink());
+            let _ = bank.write_extended_report(io::sink());
+            bank.client_statements();
+        }
+        true
+    }
+
+    /// CSV-ish input mixing transaction fields with arbitrary text.
+    #[derive(Debug, Clone)]
+    struct FuzzCsv(String);
+
+    impl Arbitrary for FuzzCsv {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let tokens = [
+                "deposit",
+                "withdrawal",
+                "dispute",
+                "chargeback",
+                "Resolve",
+                "1",
+                "-1",
+                "65536",
+                "4294967296",
+                "1.5",
+                "-0.0001",
+                "1e39",
+                "NaN",
+                "inf",
+                "",
+                " ",
+                "\"",
+            ];
+            let lines: Vec<String> = (0..usize::arbitrary(g) % g.size())
+                .map(|_| {
+                    let fields: Vec<String> = (0..usize::arbitrary(g) % 7)
+                        .map(|_| match g.choose(&tokens) {
+                            Some(token) if bool::arbitrary(g) => token.to_string(),
+                            _ => String::arbitrary(g),
+                        })
+                        .collect();
+                    fields.join(",")
+                })
+                .collect();
+            FuzzCsv(format!(
+                "type,client,tx,amount,timestamp\n{}",
+                lines.join("\n")
+            ))
+        }
+    }
+
+    fn parsers_never_panic(FuzzCsv(input): FuzzCsv) -> bool {
+        let bank = Bank::<f32>::default();
+        let sources: Vec<Box<dyn TransactionSource<f32>>> = vec![
+            Box::new(CsvSource::new(input.as_bytes(), TypeNames::tolerant()).unwrap()),
+            crate::fast_csv::fast_csv_source(input.as_bytes(), TypeNames::strict()).unwrap(),
+            Box::new(JsonLinesSource::new(
+                input.as_bytes(),
+                TypeNames::tolerant(),
+            )),
+        ];
+        for mut source in sources {
+            for _ in 0..=input.len() {
+                match source.next_tx() {
+                    Some(Ok(tx)) => {
+                        let _ = bank.process_transaction(tx);
+                    }
+                    Some(Err(_)) => {}
+                    None => break,
+                }
+            }
+        }
+        true
+    }
 
     #[test]
-    fn test_batch_process_deposit() {
+    fn test_fuzz_engine_never_panics() {
+        QuickCheck::new()
+            .tests(300)
+            .quickcheck(engine_never_panics::<f32> as fn(Vec<FuzzTx<f32>>) -> bool);
+        QuickCheck::new()
+            .tests(300)
+            .quickcheck(engine_never_panics::<Decimal> as fn(Vec<FuzzTx<Decimal>>) -> bool);
+        QuickCheck::new()
+            .tests(300)
+            .quickcheck(engine_never_panics::<MinorUnits> as fn(Vec<FuzzTx<MinorUnits>>) -> bool);
+    }
+
+    #[test]
+    fn test_fuzz_parsers_never_panic() {
+        QuickCheck::new()
+            .tests(500)
+            .quickcheck(parsers_never_panic as fn(FuzzCsv) -> bool);
+    }
+
+    #[test]
+    fn test_joint_account() {
         // GIVEN
-        let deposit1 = Transaction {
-            tx_type: TransactionType::Deposit,
-            client_id: 1,
-            id: 1,
-            amount: Some(30.0),
-        };
-        let bank = Bank::new();
+        let bank: Bank<f32> = BankBuilder::default().joint_account(1, [2, 3]).build();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 10.0),
+            Transaction::deposit(2, 2, 20.0),
+            Transaction::withdrawal(3, 3, 5.0),
+            Transaction::deposit(4, 4, 1.0),
+        ])
+        .unwrap();
 
         // WHEN
-        let result = bank.batch_process(vec![deposit1]);
+        let by_holder = bank.process_transaction(Transaction::dispute(3, 2));
+        let by_stranger = bank.process_transaction(Transaction::dispute(4, 1));
 
         // THEN
-        assert_eq!(result, Ok(()));
-        assert_eq!(bank.accounts.borrow()[0].client_id, 1);
-        assert_eq!(bank.accounts.borrow()[0].available, 30.0000);
-        assert_eq!(bank.accounts.borrow()[0].total, 30.0000);
-        assert_eq!(bank.accounts.borrow()[0].held, 0.0000);
-        assert_eq!(bank.accounts.borrow()[0].locked, false);
+        assert_eq!(by_holder, Ok(TxOutcome::Applied));
+        assert_eq!(by_stranger, Ok(TxOutcome::IgnoredClientMismatch));
+        let accounts = bank.accounts();
+        assert_eq!(
+            accounts
+                .iter()
+                .map(|a| (a.client_id, a.available, a.held))
+                .collect::<Vec<_>>(),
+            vec![(1, 5.0, 20.0), (4, 1.0, 0.0)]
+        );
+        let statements = bank.client_statements();
+        assert_eq!(
+            statements[0]
+                .transactions
+                .iter()
+                .map(|entry| entry.transaction.id())
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
     }
 
     #[test]
-    fn test_batch_process_deposit_no_amount_error() {
+    fn test_credit_account() {
         // GIVEN
-        let deposit1 = Transaction {
-            tx_type: TransactionType::Deposit,
-            client_id: 1,
-            id: 1,
-            amount: None,
-        };
-        let bank = Bank::new();
+        let bank: Bank<f32> = BankBuilder::default()
+            .client_override(ClientOverride {
+                credit_limit: Some(100.0),
+                principal: Some(20.0),
+                ..ClientOverride::new(1)
+            })
+            .build();
 
         // WHEN
-        let result = bank.batch_process(vec![deposit1]);
+        let outcomes: Vec<_> = [
+            Transaction::withdrawal(1, 1, 70.0),
+            Transaction::withdrawal(1, 2, 20.0),
+            Transaction::deposit(1, 3, 100.0),
+            Transaction::withdrawal(1, 4, 15.0),
+            Transaction::dispute(1, 3),
+        ]
+        .into_iter()
+        .map(|tx| bank.process_transaction(tx).unwrap())
+        .collect();
 
         // THEN
         assert_eq!(
-            result,
-            Err(String::from("Invalid transaction data: missing amount"))
+            outcomes,
+            vec![
+                TxOutcome::Applied,
+                TxOutcome::RejectedInsufficientFunds,
+                TxOutcome::Applied,
+                TxOutcome::Applied,
+                TxOutcome::IgnoredCreditNotDisputable,
+            ]
         );
-        assert_eq!(bank.accounts.borrow().len(), 0);
+        assert_eq!(bank.outstanding(1), Some(5.0));
+        let account = bank.account(1).unwrap();
+        assert_eq!((account.available, account.total), (0.0, 0.0));
     }
 
     #[test]
-    fn test_batch_process_withdrawal() {
+    fn test_direct_debits_and_returns() {
         // GIVEN
-        let withdrawal = Transaction {
-            tx_type: TransactionType::Withdrawal,
-            client_id: 5,
-            id: 2,
-            amount: Some(15.0),
-        };
-
-        let mut bank = Bank::new();
-
-        bank.accounts = RefCell::new(vec![Account {
-            client_id: 5,
-            available: 30.0,
-            held: 0.0,
-            total: 30.0,
-            locked: false,
-        }]);
+        let bank: Bank<f32> = BankBuilder::default()
+            .mandate(Mandate {
+                id: String::from("MD-1"),
+                client_id: 1,
+                creditor: String::from("utility"),
+                max_amount: Some(40.0),
+            })
+            .debit_return_window(Duration::from_secs(8 * SECONDS_PER_DAY))
+            .build();
+        bank.process_transaction(Transaction::deposit(1, 1, 100.0))
+            .unwrap();
 
         // WHEN
-        let result = bank.batch_process(vec![withdrawal]);
+        let outcomes: Vec<_> = [
+            Transaction::direct_debit(1, 2, 30.0, "MD-1").with_timestamp(0),
+            Transaction::direct_debit(1, 3, 50.0, "MD-1"),
+            Transaction::direct_debit(2, 4, 10.0, "MD-1"),
+            Transaction::new(TransactionType::DirectDebit, 1, 5, Some(10.0)),
+            Transaction::direct_debit(1, 6, 20.0, "MD-1").with_timestamp(SECONDS_PER_DAY),
+            Transaction::debit_return(1, 2).with_timestamp(2 * SECONDS_PER_DAY),
+            Transaction::debit_return(1, 1),
+            Transaction::debit_return(1, 6).with_timestamp(10 * SECONDS_PER_DAY),
+        ]
+        .into_iter()
+        .map(|tx| bank.process_transaction(tx).unwrap())
+        .collect();
 
         // THEN
-        assert_eq!(result, Ok(()));
-        assert_eq!(bank.accounts.borrow()[0].client_id, 5);
-        assert_eq!(bank.accounts.borrow()[0].available, 15.0);
-        assert_eq!(bank.accounts.borrow()[0].total, 15.0);
-        assert_eq!(bank.accounts.borrow()[0].held, 0.0);
-        assert_eq!(bank.accounts.borrow()[0].locked, false);
+        let no_mandate = TxOutcome::Rejected(Rejection::NoMandate);
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Applied,
+                no_mandate,
+                no_mandate,
+                no_mandate,
+                TxOutcome::Applied,
+                TxOutcome::Applied,
+                TxOutcome::IgnoredInvalidState,
+                TxOutcome::IgnoredReturnWindowExpired,
+            ]
+        );
+        assert_eq!(bank.account(1).unwrap().available, 80.0);
+        assert_eq!(
+            bank.find_transactions(&TransactionFilter {
+                status: Some(TransactionStatus::Returned),
+                ..Default::default()
+            })
+            .map(|entry| entry.transaction.id())
+            .collect::<Vec<_>>(),
+            vec![2]
+        );
     }
 
     #[test]
-    fn test_batch_process_withdrawal_no_amount_error() {
+    fn test_staged_batches_are_approved_or_rejected_as_a_whole() {
         // GIVEN
-        let withdrawal = Transaction {
-            tx_type: TransactionType::Withdrawal,
-            client_id: 1,
-            id: 1,
-            amount: None,
-        };
-        let bank = Bank::new();
+        let bank = Bank::<f32>::default();
+        bank.process_transaction(Transaction::deposit(1, 1, 50.0))
+            .unwrap();
+        let approved = bank
+            .stage_batch(vec![
+                Transaction::deposit(1, 2, 10.0),
+                Transaction::withdrawal(1, 3, 20.0),
+            ])
+            .unwrap();
+        let rejected = bank
+            .stage_batch(vec![Transaction::deposit(2, 4, 5.0)])
+            .unwrap();
+        let staged = bank.account(1).unwrap();
 
         // WHEN
-        let result = bank.batch_process(vec![withdrawal]);
+        let approval = bank.approve_batch(approved.id).unwrap();
+        let rejection = bank.reject_batch(rejected.id).unwrap();
 
         // THEN
+        assert_eq!((staged.available, staged.held), (30.0, 30.0));
+        assert_eq!((approval.applied, rejection.applied), (2, 1));
+        let account = bank.account(1).unwrap();
+        assert_eq!((account.available, account.held), (40.0, 0.0));
+        assert_eq!(bank.account(2).unwrap().total, 0.0);
         assert_eq!(
-            result,
-            Err(String::from("Invalid transaction data: missing amount"))
+            bank.approve_batch(approved.id).unwrap_err().code,
+            ErrorCode::UnknownBatch
         );
-        assert_eq!(bank.accounts.borrow().len(), 0);
     }
 
     #[test]
-    fn test_batch_process_withdrawal_not_sufficient_fund_no_change() {
+    fn test_staged_batch_refuses_types_it_cannot_undo() {
         // GIVEN
-        let withdrawal = Transaction {
-            tx_type: TransactionType::Withdrawal,
-            client_id: 5,
-            id: 2,
-            amount: Some(45.0),
-        };
-
-        let mut bank = Bank::new();
-
-        bank.accounts = RefCell::new(vec![Account {
-            client_id: 5,
-            available: 30.0,
-            held: 0.0,
-            total: 30.0,
-            locked: false,
-        }]);
+        let bank = Bank::<f32>::default();
+        bank.process_transaction(Transaction::deposit(1, 1, 50.0))
+            .unwrap();
 
         // WHEN
-        let result = bank.batch_process(vec![withdrawal]);
+        let staged = bank
+            .stage_batch(vec![
+                Transaction::deposit(1, 2, 10.0),
+                Transaction::dispute(1, 1),
+                Transaction::freeze(1, 3),
+            ])
+            .unwrap();
+        bank.reject_batch(staged.id).unwrap();
 
         // THEN
-        assert_eq!(result, Ok(()));
-        assert_eq!(bank.accounts.borrow()[0].client_id, 5);
-        assert_eq!(bank.accounts.borrow()[0].available, 30.0);
-        assert_eq!(bank.accounts.borrow()[0].total, 30.0);
-        assert_eq!(bank.accounts.borrow()[0].held, 0.0);
-        assert_eq!(bank.accounts.borrow()[0].locked, false);
+        assert_eq!(staged.summary.applied, 1);
+        assert_eq!(
+            staged.summary.rejected,
+            BTreeMap::from([(ErrorCode::NotStageable, 2)])
+        );
+        let account = bank.account(1).unwrap();
+        assert_eq!((account.available, account.held), (50.0, 0.0));
+        assert!(!account.frozen);
     }
 
     #[test]
-    fn test_batch_process_dispute() {
+    fn test_staged_batch_error_declines_transactions_held_so_far() {
         // GIVEN
-        let dispute = Transaction {
-            tx_type: TransactionType::Dispute,
-            client_id: 5,
-            id: 2,
-            amount: None,
-        };
-
-        let mut bank = Bank::new();
-
-        bank.accounts = RefCell::new(vec![Account {
-            client_id: 5,
-            available: 15.0,
-            held: 0.0,
-            total: 15.0,
-            locked: false,
-        }]);
-
-        bank.transactions = RefCell::new(HashMap::from([(
-            2,
-            (
-                Transaction {
-                    tx_type: TransactionType::Withdrawal,
-                    client_id: 5,
-                    id: 2,
-                    amount: Some(10.0),
-                },
-                TransactionStatus::Processed,
-            ),
-        )]));
+        let bank = Bank::<f32>::default();
+        bank.process_transaction(Transaction::deposit(1, 1, 50.0))
+            .unwrap();
 
         // WHEN
-        let result = bank.batch_process(vec![dispute]);
+        let staged = bank.stage_batch(vec![
+            Transaction::deposit(1, 2, 10.0),
+            Transaction::withdrawal(1, 3, 20.0),
+            Transaction::new(TransactionType::Deposit, 1, 4, None),
+        ]);
 
         // THEN
-        assert_eq!(result, Ok(()));
-        assert_eq!(bank.accounts.borrow()[0].client_id, 5);
-        assert_eq!(bank.accounts.borrow()[0].held, 10.0);
-        assert_eq!(bank.accounts.borrow()[0].total, 15.0);
-        assert_eq!(bank.accounts.borrow()[0].available, 5.0);
-        assert_eq!(bank.accounts.borrow()[0].locked, false);
+        assert_eq!(staged.unwrap_err().code, ErrorCode::MissingAmount);
+        let account = bank.account(1).unwrap();
+        assert_eq!((account.available, account.held), (50.0, 0.0));
+        assert_eq!(
+            bank.transactions.borrow().get(2).map(|(_, status)| *status),
+            Some(TransactionStatus::Declined)
+        );
     }
 
     #[test]
-    fn test_batch_process_dispute_valid_tx_but_not_matching_client_id() {
+    fn test_refused_batch_approval_keeps_transaction_staged() {
         // GIVEN
-        let dispute = Transaction {
-            tx_type: TransactionType::Dispute,
-            client_id: 15,
-            id: 2,
-            amount: None,
-        };
-
-        let mut bank = Bank::new();
-
-        bank.accounts = RefCell::new(vec![Account {
-            client_id: 5,
-            available: 15.0,
-            held: 0.0,
-            total: 15.0,
-            locked: false,
-        }]);
-
-        bank.transactions = RefCell::new(HashMap::from([(
-            2,
-            (
-                Transaction {
-                    tx_type: TransactionType::Withdrawal,
-                    client_id: 5,
-                    id: 2,
-                    amount: Some(10.0),
-                },
-                TransactionStatus::Processed,
-            ),
-        )]));
+        let bank = Bank::<f32>::default();
+        let staged = bank
+            .stage_batch(vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::deposit(2, 2, 20.0),
+            ])
+            .unwrap();
+        bank.process_transaction(Transaction::freeze(1, 3)).unwrap();
 
         // WHEN
-        let result = bank.batch_process(vec![dispute]);
+        let refused = bank.approve_batch(staged.id).unwrap();
+        bank.process_transaction(Transaction::unfreeze(1, 4))
+            .unwrap();
+        let retried = bank.approve_batch(staged.id).unwrap();
 
         // THEN
-        assert_eq!(result, Ok(()));
-        // No fund amount was changed
-        assert_eq!(bank.accounts.borrow()[0].client_id, 5);
-        assert_eq!(bank.accounts.borrow()[0].held, 0.0);
-        assert_eq!(bank.accounts.borrow()[0].total, 15.0);
-        assert_eq!(bank.accounts.borrow()[0].available, 15.0);
-        assert_eq!(bank.accounts.borrow()[0].locked, false);
+        assert_eq!(refused.applied, 1);
+        assert_eq!(
+            refused.rejected,
+            BTreeMap::from([(ErrorCode::AccountFrozen, 1)])
+        );
+        assert_eq!(retried.applied, 1);
+        assert!(retried.rejected.is_empty());
+        assert_eq!(bank.account(1).unwrap().available, 10.0);
+        assert_eq!(bank.account(2).unwrap().available, 20.0);
+        assert_eq!(
+            bank.reject_batch(staged.id).unwrap_err().code,
+            ErrorCode::UnknownBatch
+        );
     }
 
     #[test]
-    fn test_batch_process_resolve() {
+    fn test_dual_authorization() {
         // GIVEN
-        let resolve = Transaction {
-            tx_type: TransactionType::Resolve,
-            client_id: 5,
-            id: 2,
-            amount: None,
-        };
+        let bank: Bank<f32> = BankBuilder::default()
+            .dual_authorization(1000.0)
+            .dual_authorization_expiry(Duration::from_secs(3600))
+            .build();
+        let mut held = Vec::new();
 
-        let mut bank = Bank::new();
+        // WHEN
+        held.push(bank.process_transaction(Transaction::deposit(1, 1, 1000.0).with_timestamp(0)));
+        held.push(bank.process_transaction(Transaction::deposit(1, 2, 5000.0).with_timestamp(0)));
+        let authorized = bank.authorize(2);
+        let unknown = bank.authorize(1);
+        held.push(
+            bank.process_transaction(Transaction::withdrawal(1, 3, 2000.0).with_timestamp(100)),
+        );
+        let reserved = bank.account(1).unwrap();
+        bank.process_transaction(Transaction::deposit(2, 4, 1.0).with_timestamp(3701))
+            .unwrap();
 
-        bank.accounts = RefCell::new(vec![Account {
-            client_id: 5,
-            available: 5.0,
-            held: 10.0,
-            total: 15.0,
-            locked: false,
-        }]);
+        // THEN
+        assert_eq!(
+            held,
+            vec![
+                Ok(TxOutcome::Applied),
+                Ok(TxOutcome::Pending),
+                Ok(TxOutcome::Pending)
+            ]
+        );
+        assert_eq!(authorized, Ok(TxOutcome::Applied));
+        assert_eq!(unknown, Ok(TxOutcome::IgnoredUnknownTransaction));
+        assert_eq!((reserved.available, reserved.held), (4000.0, 2000.0));
+        assert_eq!(bank.expired_authorizations(), 1);
+        let account = bank.account(1).unwrap();
+        assert_eq!((account.available, account.held), (6000.0, 0.0));
+    }
 
-        bank.transactions = RefCell::new(HashMap::from([(
-            2,
-            (
-                Transaction {
-                    tx_type: TransactionType::Withdrawal,
-                    client_id: 5,
-                    id: 2,
-                    amount: Some(10.0),
-                },
-                TransactionStatus::Disputed,
-            ),
-        )]));
+    #[test]
+    fn test_refused_expiry_decline_keeps_waiting_for_authorization() {
+        // GIVEN
+        let bank: Bank<f32> = BankBuilder::default()
+            .dual_authorization(1000.0)
+            .dual_authorization_expiry(Duration::from_secs(3600))
+            .build();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 5000.0).with_timestamp(0),
+            Transaction::freeze(1, 2).with_timestamp(10),
+        ])
+        .unwrap();
 
         // WHEN
-        let result = bank.batch_process(vec![resolve]);
+        bank.process_transaction(Transaction::deposit(2, 3, 1.0).with_timestamp(3601))
+            .unwrap();
+        let while_frozen = bank.transaction_status(1);
+        bank.batch_process(vec![
+            Transaction::unfreeze(1, 4).with_timestamp(3700),
+            Transaction::deposit(2, 5, 1.0).with_timestamp(3701),
+        ])
+        .unwrap();
 
         // THEN
-        assert_eq!(result, Ok(()));
-        assert_eq!(bank.accounts.borrow()[0].client_id, 5);
-        assert_eq!(bank.accounts.borrow()[0].held, 0.0);
-        assert_eq!(bank.accounts.borrow()[0].total, 15.0);
-        assert_eq!(bank.accounts.borrow()[0].available, 15.0);
-        assert_eq!(bank.accounts.borrow()[0].locked, false);
+        assert_eq!(while_frozen, Some(TransactionStatus::Pending));
+        assert_eq!(
+            bank.transaction_status(1),
+            Some(TransactionStatus::Declined)
+        );
+        assert_eq!(bank.expired_authorizations(), 1);
+        assert_eq!(bank.authorize(1), Ok(TxOutcome::IgnoredUnknownTransaction));
+        assert_eq!(bank.account(1).unwrap().held, 0.0);
     }
 
     #[test]
-    fn test_batch_process_chargeback() {
+    fn test_direct_debit_above_threshold_needs_authorization() {
         // GIVEN
-        let chargeback = Transaction {
-            tx_type: TransactionType::Chargeback,
-            client_id: 5,
-            id: 2,
-            amount: None,
-        };
+        let bank: Bank<f32> = BankBuilder::default()
+            .dual_authorization(1000.0)
+            .mandate(Mandate {
+                id: String::from("MD-1"),
+                client_id: 1,
+                creditor: String::from("utility"),
+                max_amount: None,
+            })
+            .build();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 1000.0),
+            Transaction::deposit(1, 2, 1000.0),
+        ])
+        .unwrap();
 
-        let mut bank = Bank::new();
+        // WHEN
+        let held = bank.process_transaction(Transaction::direct_debit(1, 3, 1500.0, "MD-1"));
+        let reserved = bank.account(1).unwrap();
+        let authorized = bank.authorize(3);
+        let debited = bank.account(1).unwrap();
+        let returned = bank.process_transaction(Transaction::debit_return(1, 3));
 
-        bank.accounts = RefCell::new(vec![Account {
-            client_id: 5,
-            available: 5.0,
-            held: 10.0,
-            total: 15.0,
-            locked: false,
-        }]);
+        // THEN
+        assert_eq!(held, Ok(TxOutcome::Pending));
+        assert_eq!((reserved.available, reserved.held), (500.0, 1500.0));
+        assert_eq!(authorized, Ok(TxOutcome::Applied));
+        assert_eq!((debited.available, debited.held), (500.0, 0.0));
+        assert_eq!(debited.total, 500.0);
+        assert_eq!(returned, Ok(TxOutcome::Applied));
+        assert_eq!(bank.account(1).unwrap().total, 2000.0);
+    }
 
-        bank.transactions = RefCell::new(HashMap::from([(
-            2,
-            (
-                Transaction {
-                    tx_type: TransactionType::Withdrawal,
-                    client_id: 5,
-                    id: 2,
-                    amount: Some(10.0),
-                },
-                TransactionStatus::Disputed,
-            ),
-        )]));
+    #[test]
+    fn test_purge_client() {
+        // GIVEN
+        let bank: Bank<f32> = BankBuilder::default()
+            .aml(AmlConfig {
+                threshold: 100.0,
+                ..Default::default()
+            })
+            .build();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 500.0)
+                .with_description("Salary J. Doe")
+                .with_tags("employer 42")
+                .with_external_id("PSP-1"),
+            Transaction::withdrawal(1, 2, 20.0).with_description("Rent"),
+            Transaction::deposit(2, 3, 10.0).with_description("Gift"),
+            Transaction::dispute(2, 3),
+        ])
+        .unwrap();
+        let before = bank.accounts();
 
         // WHEN
-        let result = bank.batch_process(vec![chargeback]);
+        let purged = bank.purge_client(1);
+        let open = bank.purge_client(2);
 
         // THEN
-        assert_eq!(result, Ok(()));
-        assert_eq!(bank.accounts.borrow()[0].client_id, 5);
-        assert_eq!(bank.accounts.borrow()[0].held, 0.0);
-        assert_eq!(bank.accounts.borrow()[0].total, 5.0);
-        assert_eq!(bank.accounts.borrow()[0].available, 5.0);
-        assert_eq!(bank.accounts.borrow()[0].locked, true);
+        assert_eq!(purged, Ok(2));
+        assert_eq!(open.unwrap_err().code, ErrorCode::ClientNotClosed);
+        assert_eq!(bank.accounts(), before);
+        assert_eq!(bank.transaction_by_external_id("PSP-1"), None);
+        let details: Vec<_> = bank
+            .find_transactions(&TransactionFilter::default())
+            .map(|entry| {
+                (
+                    entry.transaction.description().map(String::from),
+                    entry.transaction.tags().is_some(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            details,
+            vec![
+                (None, false),
+                (None, false),
+                (Some(String::from("Gift")), false)
+            ]
+        );
+        let entries = bank.compliance_entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            (entries[0].tags.as_ref(), entries[0].description.as_ref()),
+            (None, None)
+        );
     }
 }
@@ -0,0 +1,601 @@
+use rs_bank_assignment::bank::{ReportFormat, TransactionType};
+use rs_bank_assignment::diagnostics::ErrorFormat;
+use rs_bank_assignment::locale::NumberFormat;
+use rs_bank_assignment::reorder::LatePolicy;
+use rs_bank_assignment::shadow::ShadowEngine;
+use rust_decimal::Decimal;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const USAGE_NO_INPUT: &str = "Expected 1 argument, but got none";
+
+/**
+ * Format of the input file.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum InputFormat {
+    #[default]
+    Csv,
+    /// One JSON array of transaction objects.
+    Json,
+    JsonLines,
+}
+
+impl InputFormat {
+    /**
+     * The format a file name's extension suggests: `.json` for JSON,
+     * `.jsonl` or `.ndjson` for JSON Lines, and CSV otherwise.
+     */
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => InputFormat::Json,
+            Some("jsonl" | "ndjson") => InputFormat::JsonLines,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "csv" => Ok(InputFormat::Csv),
+            "json" => Ok(InputFormat::Json),
+            "jsonl" => Ok(InputFormat::JsonLines),
+            _ => Err(format!("Unknown input format {:?}", name)),
+        }
+    }
+}
+
+/**
+ * Command line options. The first positional argument is the input file,
+ * everything else is an optional `--flag value` pair.
+ */
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub input: OsString,
+    pub input_format: InputFormat,
+    pub compliance_report: Option<PathBuf>,
+    pub aml_threshold: Option<Decimal>,
+    pub overrides: Option<PathBuf>,
+    /// CSV of clients authorized on other clients' accounts.
+    pub joint_accounts: Option<PathBuf>,
+    pub unverified_balance_cap: Option<Decimal>,
+    pub denylist: Option<PathBuf>,
+    pub review_queue: Option<PathBuf>,
+    /// Fixed-width end-of-day balance file for the regulator.
+    pub regulatory_export: Option<PathBuf>,
+    /// TOML field layout of that file, instead of the default one.
+    pub regulatory_layout: Option<PathBuf>,
+    /// Closed clients whose personal details are erased before the exports.
+    pub purge_clients: Vec<u16>,
+    /// JSON file receiving the bank-wide cash position.
+    pub liquidity_report: Option<PathBuf>,
+    pub auto_unlock_days: Option<u64>,
+    /// CSV of direct debit mandates.
+    pub mandates: Option<PathBuf>,
+    /// Days within which a direct debit can be sent back.
+    pub debit_return_days: Option<u64>,
+    /// Amount above which deposits, withdrawals and direct debits need a
+    /// second authorization.
+    pub dual_auth_threshold: Option<Decimal>,
+    /// Seconds of event time after which unauthorized ones are declined.
+    pub dual_auth_expiry: Option<u64>,
+    pub pending: Option<PathBuf>,
+    pub rules: Option<PathBuf>,
+    pub tolerant_types: bool,
+    pub type_aliases: Vec<(String, TransactionType)>,
+    /// More inputs, merged with the first by timestamp.
+    pub merge: Vec<PathBuf>,
+    /// History files reprocessed after the input, skipping applied ids.
+    pub backfill: Vec<PathBuf>,
+    /// Seconds of event time to hold transactions back for reordering.
+    pub reorder_window: Option<u64>,
+    /// What to do with transactions arriving beyond the reorder window.
+    pub late_policy: LatePolicy,
+    /// Read the input through a memory map instead of buffered reads.
+    pub mmap: bool,
+    /// Parse canonical CSV rows with the fast path; implies `mmap`.
+    pub fast_csv: bool,
+    /// Overrides the row count estimated from the input size.
+    pub expected_rows: Option<usize>,
+    /// Serialize the report on this many threads.
+    pub report_threads: Option<usize>,
+    /// Format of the rejections and warnings written while processing.
+    pub errors: ErrorFormat,
+    /// Where to write them instead of stderr.
+    pub errors_output: Option<PathBuf>,
+    /// Retries of a transiently failed input read; see `RetryPolicy`.
+    pub retries: Option<u32>,
+    /// Wait before the first retry, doubled for each one after it.
+    pub retry_backoff_ms: Option<u64>,
+    /// File receiving every account change as a JSON line.
+    pub updates: Option<PathBuf>,
+    /// File receiving a CSV row per changed account field.
+    pub cdc: Option<PathBuf>,
+    /// `http://` URL receiving every account change as a POST.
+    pub updates_url: Option<String>,
+    /// Address for the admin API, e.g. `127.0.0.1:8080`.
+    #[cfg(feature = "server")]
+    pub admin_addr: Option<String>,
+    /// Amount type of a second engine run to compare against.
+    pub shadow: Option<ShadowEngine>,
+    /// Print throughput, stage timings and peak memory at the end.
+    pub stats: bool,
+    /// Where to write them instead of stderr.
+    pub stats_output: Option<PathBuf>,
+    /// Flamegraph SVG of the processing loop.
+    #[cfg(feature = "profiling")]
+    pub profile: Option<PathBuf>,
+    /// Read the input with tokio instead of blocking reads.
+    #[cfg(feature = "tokio")]
+    pub async_input: bool,
+    /// Append history-derived columns to the report.
+    pub extended_report: bool,
+    /// Format of the report written to stdout.
+    pub output_format: ReportFormat,
+    /// Write report amounts for people in this locale's number format.
+    pub number_format: Option<NumberFormat>,
+    /// Salt of the pseudonyms replacing client ids in the report and the
+    /// compliance report, which also get bucketed amounts.
+    pub anonymize: Option<String>,
+    /// Width of the amount buckets of `anonymize`.
+    pub amount_bucket: Option<f64>,
+    /// Start reports and exports with a provenance comment line.
+    pub metadata_header: bool,
+    /// Directory for one statement file per client.
+    pub output_dir: Option<PathBuf>,
+}
+
+/**
+ * Options of `migrate <legacy file> --mapping <path> [--output <path>]`.
+ */
+#[derive(Debug, PartialEq)]
+pub struct MigrateOptions {
+    pub input: OsString,
+    /// TOML mapping of the legacy layout.
+    pub mapping: PathBuf,
+    /// Where the converted file goes, instead of stdout.
+    pub output: Option<PathBuf>,
+}
+
+pub fn parse_migrate_args<I>(args: I) -> Result<MigrateOptions, String>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut input = None;
+    let mut mapping = None;
+    let mut output = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--mapping") => mapping = Some(PathBuf::from(value_of(&arg, &mut args)?)),
+            Some("--output") => output = Some(PathBuf::from(value_of(&arg, &mut args)?)),
+            Some(flag) if flag.starts_with("--") => {
+                return Err(format!("Unknown option {}", flag));
+            }
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("Unexpected argument {:?}", arg)),
+        }
+    }
+    Ok(MigrateOptions {
+        input: input.ok_or(USAGE_NO_INPUT)?,
+        mapping: mapping.ok_or("migrate needs --mapping")?,
+        output,
+    })
+}
+
+pub fn parse_args<I>(args: I) -> Result<Options, String>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut options = Options::default();
+    let mut input = None;
+    let mut input_format = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--compliance-report") => {
+                options.compliance_report = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--aml-threshold") => {
+                options.aml_threshold = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--overrides") => {
+                options.overrides = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--joint-accounts") => {
+                options.joint_accounts = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--denylist") => {
+                options.denylist = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--review-queue") => {
+                options.review_queue = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--regulatory-export") => {
+                options.regulatory_export = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--regulatory-layout") => {
+                options.regulatory_layout = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--purge-client") => {
+                options.purge_clients.push(parse_value(&arg, &mut args)?);
+            }
+            Some("--liquidity-report") => {
+                options.liquidity_report = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--tolerant-types") => options.tolerant_types = true,
+            Some("--mmap") => options.mmap = true,
+            Some("--extended-report") => options.extended_report = true,
+            Some("--output-format") => {
+                options.output_format = parse_value(&arg, &mut args)?;
+            }
+            Some("--metadata-header") => options.metadata_header = true,
+            Some("--anonymize") => {
+                options.anonymize = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--amount-bucket") => {
+                options.amount_bucket = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--fast-csv") => options.fast_csv = true,
+            Some("--number-format") => {
+                options.number_format = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--errors") => {
+                options.errors = parse_value(&arg, &mut args)?;
+            }
+            Some("--errors-output") => {
+                options.errors_output = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--retries") => {
+                options.retries = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--retry-backoff-ms") => {
+                options.retry_backoff_ms = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--updates") => {
+                options.updates = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--cdc") => {
+                options.cdc = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--updates-url") => {
+                options.updates_url = Some(parse_value(&arg, &mut args)?);
+            }
+            #[cfg(feature = "server")]
+            Some("--admin-addr") => {
+                options.admin_addr = Some(parse_value(&arg, &mut args)?);
+            }
+            #[cfg(feature = "tokio")]
+            Some("--async") => options.async_input = true,
+            #[cfg(feature = "profiling")]
+            Some("--profile") => {
+                options.profile = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--merge") => {
+                options
+                    .merge
+                    .push(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--reorder-window") => {
+                options.reorder_window = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--late-policy") => {
+                options.late_policy = parse_value(&arg, &mut args)?;
+            }
+            Some("--backfill") => {
+                options
+                    .backfill
+                    .push(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--shadow") => {
+                options.shadow = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--stats") => options.stats = true,
+            Some("--stats-output") => {
+                options.stats_output = Some(PathBuf::from(value_of(&arg, &mut args)?));
+                options.stats = true;
+            }
+            Some("--output-dir") => {
+                options.output_dir = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--report-threads") => {
+                options.report_threads = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--expected-rows") => {
+                options.expected_rows = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--type-alias") => {
+                options.type_aliases.push(parse_alias(&arg, &mut args)?);
+                options.tolerant_types = true;
+            }
+            Some("--input-format") => {
+                input_format = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--rules") => {
+                options.rules = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--pending") => {
+                options.pending = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--mandates") => {
+                options.mandates = Some(PathBuf::from(value_of(&arg, &mut args)?));
+            }
+            Some("--debit-return-days") => {
+                options.debit_return_days = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--dual-auth-threshold") => {
+                options.dual_auth_threshold = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--dual-auth-expiry") => {
+                options.dual_auth_expiry = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--auto-unlock-days") => {
+                options.auto_unlock_days = Some(parse_value(&arg, &mut args)?);
+            }
+            Some("--unverified-balance-cap") => {
+                options.unverified_balance_cap = Some(parse_value(&arg, &mut args)?);
+            }
+            Some(flag) if flag.starts_with("--") => {
+                return Err(format!("Unknown option {}", flag));
+            }
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("Unexpected argument {:?}", arg)),
+        }
+    }
+    let input: OsString = input.ok_or(USAGE_NO_INPUT)?;
+    options.input_format =
+        input_format.unwrap_or_else(|| InputFormat::from_path(Path::new(&input)));
+    options.input = input;
+    if options.extended_report && options.number_format.is_some() {
+        return Err(String::from(
+            "--number-format cannot be combined with --extended-report",
+        ));
+    }
+    if options.output_format != ReportFormat::Csv {
+        let report_options = [
+            ("--extended-report", options.extended_report),
+            ("--number-format", options.number_format.is_some()),
+            ("--metadata-header", options.metadata_header),
+        ];
+        if let Some((flag, _)) = report_options.iter().find(|(_, asked)| *asked) {
+            return Err(format!("{} cannot be combined with --output-format", flag));
+        }
+    }
+    #[cfg(feature = "tokio")]
+    if options.async_input {
+        if let Some(flag) = blocking_input(&options, input_format) {
+            return Err(format!("{} cannot be combined with --async", flag));
+        }
+        if options.input_format != InputFormat::Csv {
+            return Err(format!(
+                "--async reads CSV only, but the input's extension selects {:?}; \
+                 pass --input-format csv to read it as CSV",
+                options.input_format
+            ));
+        }
+    }
+    if options.anonymize.is_some() {
+        if let Some(flag) = identifying_export(&options) {
+            return Err(format!("{} cannot be combined with --anonymize", flag));
+        }
+    }
+    Ok(options)
+}
+
+/**
+ * The first output asked for that `--anonymize` does not cover, which
+ * would give real client ids or amounts away.
+ */
+fn identifying_export(options: &Options) -> Option<&'static str> {
+    let exports = [
+        ("--extended-report", options.extended_report),
+        ("--number-format", options.number_format.is_some()),
+        (
+            "--output-format",
+            options.output_format != ReportFormat::Csv,
+        ),
+        ("--review-queue", options.review_queue.is_some()),
+        ("--pending", options.pending.is_some()),
+        ("--output-dir", options.output_dir.is_some()),
+        ("--regulatory-export", options.regulatory_export.is_some()),
+        ("--liquidity-report", options.liquidity_report.is_some()),
+        ("--updates", options.updates.is_some()),
+        ("--cdc", options.cdc.is_some()),
+        ("--updates-url", options.updates_url.is_some()),
+        ("--errors-output", options.errors_output.is_some()),
+        ("--shadow", options.shadow.is_some()),
+    ];
+    exports
+        .into_iter()
+        .find_map(|(flag, asked)| asked.then_some(flag))
+}
+
+/**
+ * The first input option asked for that `--async` does not support, given
+ * the `--input-format` passed, if any.
+ */
+#[cfg(feature = "tokio")]
+fn blocking_input(options: &Options, input_format: Option<InputFormat>) -> Option<&'static str> {
+    let inputs = [
+        (
+            "--input-format",
+            input_format.is_some_and(|format| format != InputFormat::Csv),
+        ),
+        ("--mmap", options.mmap),
+        ("--fast-csv", options.fast_csv),
+        ("--merge", !options.merge.is_empty()),
+        ("--backfill", !options.backfill.is_empty()),
+        ("--reorder-window", options.reorder_window.is_some()),
+        ("--retries", options.retries.is_some()),
+    ];
+    inputs
+        .into_iter()
+        .find_map(|(flag, asked)| asked.then_some(flag))
+}
+
+fn value_of(
+    flag: &OsString,
+    args: &mut impl Iterator<Item = OsString>,
+) -> Result<OsString, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for {:?}", flag))
+}
+
+fn parse_value<T: FromStr>(
+    flag: &OsString,
+    args: &mut impl Iterator<Item = OsString>,
+) -> Result<T, String> {
+    let value = value_of(flag, args)?;
+    value
+        .to_str()
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| format!("Invalid value {:?} for {:?}", value, flag))
+}
+
+/**
+ * Parses a `NAME=TYPE` pair, where TYPE is a canonical transaction type.
+ */
+fn parse_alias(
+    flag: &OsString,
+    args: &mut impl Iterator<Item = OsString>,
+) -> Result<(String, TransactionType), String> {
+    let value = value_of(flag, args)?;
+    value
+        .to_str()
+        .and_then(|v| v.split_once('='))
+        .and_then(|(name, tx_type)| Some((name.to_string(), tx_type.parse().ok()?)))
+        .ok_or_else(|| format!("Invalid value {:?} for {:?}", value, flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<OsString> {
+        values.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args_input_only() {
+        // WHEN
+        let options = parse_args(args(&["transactions.csv"]));
+
+        // THEN
+        assert_eq!(
+            options,
+            Ok(Options {
+                input: OsString::from("transactions.csv"),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_args_flags_around_input() {
+        // WHEN
+        let options = parse_args(args(&[
+            "--aml-threshold",
+            "500",
+            "transactions.csv",
+            "--compliance-report",
+            "aml.csv",
+            "--mmap",
+            "--expected-rows",
+            "1000",
+        ]))
+        .unwrap();
+
+        // THEN
+        assert_eq!(options.input, OsString::from("transactions.csv"));
+        assert_eq!(options.aml_threshold, Some(Decimal::from(500)));
+        assert_eq!(options.compliance_report, Some(PathBuf::from("aml.csv")));
+        assert!(options.mmap);
+        assert_eq!(options.expected_rows, Some(1000));
+    }
+
+    #[test]
+    fn test_input_format_from_extension() {
+        let format = |values: &[&str]| parse_args(args(values)).unwrap().input_format;
+
+        assert_eq!(format(&["in.json"]), InputFormat::Json);
+        assert_eq!(format(&["in.ndjson"]), InputFormat::JsonLines);
+        assert_eq!(format(&["in.txt"]), InputFormat::Csv);
+        assert_eq!(
+            format(&["in.json", "--input-format", "csv"]),
+            InputFormat::Csv
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "tokio")]
+    fn test_async_rejects_non_csv_input() {
+        let error = |values: &[&str]| parse_args(args(values)).unwrap_err();
+
+        assert_eq!(
+            error(&["in.csv", "--async", "--input-format", "json"]),
+            "--input-format cannot be combined with --async"
+        );
+        assert!(error(&["in.jsonl", "--async"]).starts_with("--async reads CSV only"));
+        assert!(parse_args(args(&["in.jsonl", "--async", "--input-format", "csv"])).is_ok());
+    }
+
+    #[test]
+    fn test_parse_migrate_args() {
+        assert_eq!(
+            parse_migrate_args(args(&["old.csv", "--mapping", "old.toml"])),
+            Ok(MigrateOptions {
+                input: OsString::from("old.csv"),
+                mapping: PathBuf::from("old.toml"),
+                output: None,
+            })
+        );
+        assert_eq!(
+            parse_migrate_args(args(&["old.csv"])),
+            Err(String::from("migrate needs --mapping"))
+        );
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert_eq!(
+            parse_args(args(&[])),
+            Err(String::from("Expected 1 argument, but got none"))
+        );
+        assert!(parse_args(args(&["a.csv", "--aml-threshold"])).is_err());
+        assert!(parse_args(args(&["a.csv", "--aml-threshold", "ten"])).is_err());
+        assert!(parse_args(args(&["a.csv", "--unknown"])).is_err());
+        assert!(parse_args(args(&["a.csv", "b.csv"])).is_err());
+        assert!(parse_args(args(&["a.csv", "--type-alias", "refund"])).is_err());
+        assert!(parse_args(args(&["a.csv", "--type-alias", "refund=gift"])).is_err());
+        assert!(parse_args(args(&["a.csv", "--number-format", "xx"])).is_err());
+        assert!(parse_args(args(&["a.csv", "--late-policy", "drop"])).is_err());
+        assert_eq!(
+            parse_args(args(&[
+                "a.csv",
+                "--output-format",
+                "json",
+                "--extended-report"
+            ])),
+            Err(String::from(
+                "--extended-report cannot be combined with --output-format"
+            ))
+        );
+        assert_eq!(
+            parse_args(args(&["a.csv", "--anonymize", "s", "--output-dir", "out"])),
+            Err(String::from(
+                "--output-dir cannot be combined with --anonymize"
+            ))
+        );
+        assert!(parse_args(args(&[
+            "a.csv",
+            "--number-format",
+            "de",
+            "--extended-report"
+        ]))
+        .is_err());
+    }
+}
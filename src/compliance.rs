@@ -0,0 +1,345 @@
+use crate::bank::{Transaction, TransactionType};
+use crate::money::Money;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io::{self, BufRead};
+
+/**
+ * Thresholds for anti-money-laundering reporting. Deposits and withdrawals
+ * above `threshold` are reported as large transactions. A client making
+ * `structuring_count` or more deposits within `structuring_margin` (a
+ * fraction of the threshold) below it is reported for structuring.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AmlConfig<M = Decimal> {
+    pub threshold: M,
+    pub structuring_margin: f32,
+    pub structuring_count: usize,
+}
+
+impl<M: Money> Default for AmlConfig<M> {
+    fn default() -> Self {
+        AmlConfig {
+            threshold: M::from_whole(10_000),
+            structuring_margin: 0.1,
+            structuring_count: 3,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComplianceReason {
+    LargeTransaction,
+    Structuring,
+    Denylisted,
+}
+
+/**
+ * One row of the compliance report.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComplianceEntry<M = Decimal> {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    #[serde(rename = "tx")]
+    pub tx_id: u32,
+    #[serde(rename = "type")]
+    pub tx_type: TransactionType,
+    pub amount: Option<M>,
+    pub timestamp: Option<u64>,
+    pub tags: Option<String>,
+    pub description: Option<String>,
+    pub reason: ComplianceReason,
+}
+
+impl<M: Money> ComplianceEntry<M> {
+    fn new(tx: &Transaction<M>, reason: ComplianceReason) -> Self {
+        ComplianceEntry {
+            client_id: tx.client_id(),
+            tx_id: tx.id(),
+            tx_type: tx.tx_type(),
+            amount: tx.amount(),
+            timestamp: tx.timestamp(),
+            tags: tx.tags().map(String::from),
+            description: tx.description().map(String::from),
+            reason,
+        }
+    }
+}
+
+/**
+ * Collects compliance entries: AML hits from applied deposits and
+ * withdrawals, and every transaction refused by denylist screening.
+ */
+#[derive(Default)]
+pub(crate) struct ComplianceMonitor<M> {
+    config: Option<AmlConfig<M>>,
+    entries: Vec<ComplianceEntry<M>>,
+    near_threshold: HashMap<u16, Vec<ComplianceEntry<M>>>,
+    structuring: HashSet<u16>,
+}
+
+impl<M: Money> ComplianceMonitor<M> {
+    pub(crate) fn new(config: Option<AmlConfig<M>>) -> Self {
+        ComplianceMonitor {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn observe(&mut self, tx: &Transaction<M>) {
+        let (config, amount) = match (self.config, tx.amount()) {
+            (Some(config), Some(amount)) => (config, amount),
+            _ => return,
+        };
+        if amount > config.threshold {
+            self.entries
+                .push(ComplianceEntry::new(tx, ComplianceReason::LargeTransaction));
+            return;
+        }
+
+        let floor = config.threshold.to_f64() * (1.0 - f64::from(config.structuring_margin));
+        let near_threshold = amount.to_f64() >= floor && amount < config.threshold;
+        if tx.tx_type() != TransactionType::Deposit || !near_threshold {
+            return;
+        }
+        let entry = ComplianceEntry::new(tx, ComplianceReason::Structuring);
+        if self.structuring.contains(&tx.client_id()) {
+            self.entries.push(entry);
+            return;
+        }
+        let pending = self.near_threshold.entry(tx.client_id()).or_default();
+        pending.push(entry);
+        if pending.len() >= config.structuring_count {
+            self.entries.append(pending);
+            self.near_threshold.remove(&tx.client_id());
+            self.structuring.insert(tx.client_id());
+        }
+    }
+
+    pub(crate) fn record_denylisted(&mut self, tx: &Transaction<M>) {
+        self.entries
+            .push(ComplianceEntry::new(tx, ComplianceReason::Denylisted));
+    }
+
+    /**
+     * Drops the free-text fields of the client's entries, keeping what the
+     * AML record needs.
+     */
+    pub(crate) fn purge_client(&mut self, client_id: u16) {
+        let pending = self
+            .near_threshold
+            .get_mut(&client_id)
+            .into_iter()
+            .flatten();
+        for entry in self.entries.iter_mut().chain(pending) {
+            if entry.client_id == client_id {
+                entry.tags = None;
+                entry.description = None;
+            }
+        }
+    }
+
+    pub(crate) fn entries(&self) -> &[ComplianceEntry<M>] {
+        &self.entries
+    }
+}
+
+/**
+ * Reads denylisted client ids, one per line. Blank lines and lines starting
+ * with `#` are ignored.
+ */
+pub fn read_denylist<R: BufRead>(reader: R) -> Result<Vec<u16>, Box<dyn Error>> {
+    let mut client_ids = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let client_id = line
+            .parse()
+            .map_err(|_| format!("Invalid client id in denylist: {}", line))?;
+        client_ids.push(client_id);
+    }
+    Ok(client_ids)
+}
+
+pub fn write_compliance_report<M: Money, W: io::Write>(
+    entries: &[ComplianceEntry<M>],
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for entry in entries {
+        writer.serialize(entry)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{Bank, BankBuilder, TxOutcome};
+    use crate::validation::Rejection;
+
+    fn aml_bank() -> Bank<f32> {
+        BankBuilder::<f32>::default()
+            .aml(AmlConfig {
+                threshold: 1_000.0,
+                structuring_margin: 0.1,
+                structuring_count: 2,
+            })
+            .build()
+    }
+
+    #[test]
+    fn test_large_transactions_are_reported() {
+        // GIVEN
+        let bank = aml_bank();
+
+        // WHEN
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 5_000.0).with_timestamp(10),
+            Transaction::withdrawal(1, 2, 1_500.0),
+            Transaction::withdrawal(1, 3, 100_000.0),
+            Transaction::deposit(2, 4, 1_000.0),
+        ])
+        .unwrap();
+
+        // THEN
+        let entries = bank.compliance_entries();
+        assert_eq!(
+            entries,
+            vec![
+                ComplianceEntry {
+                    client_id: 1,
+                    tx_id: 1,
+                    tx_type: TransactionType::Deposit,
+                    amount: Some(5_000.0),
+                    timestamp: Some(10),
+                    tags: None,
+                    description: None,
+                    reason: ComplianceReason::LargeTransaction,
+                },
+                ComplianceEntry {
+                    client_id: 1,
+                    tx_id: 2,
+                    tx_type: TransactionType::Withdrawal,
+                    amount: Some(1_500.0),
+                    timestamp: None,
+                    tags: None,
+                    description: None,
+                    reason: ComplianceReason::LargeTransaction,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_structuring_reported_once_pattern_emerges() {
+        // GIVEN
+        let bank = aml_bank();
+
+        // WHEN
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 950.0),
+            Transaction::deposit(2, 2, 990.0),
+            Transaction::deposit(1, 3, 800.0),
+            Transaction::deposit(1, 4, 999.0),
+            Transaction::deposit(1, 5, 920.0),
+        ])
+        .unwrap();
+
+        // THEN
+        let reported: Vec<(u32, ComplianceReason)> = bank
+            .compliance_entries()
+            .iter()
+            .map(|entry| (entry.tx_id, entry.reason))
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                (1, ComplianceReason::Structuring),
+                (4, ComplianceReason::Structuring),
+                (5, ComplianceReason::Structuring),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_compliance_report_csv() {
+        // GIVEN
+        let bank = aml_bank();
+        bank.batch_process(vec![
+            Transaction::deposit(3, 7, 2_000.0).with_tags("batch-9;ref 42")
+        ])
+        .unwrap();
+        let mut output = Vec::new();
+
+        // WHEN
+        write_compliance_report(&bank.compliance_entries(), &mut output).unwrap();
+
+        // THEN
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,tx,type,amount,timestamp,tags,description,reason\n3,7,deposit,2000.0,,batch-9;ref 42,,large_transaction\n"
+        );
+    }
+
+    #[test]
+    fn test_denylisted_client_rejected_and_reported() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default().denylist([2]).build();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::deposit(2, 2, 10.0),
+                Transaction::withdrawal(2, 3, 5.0),
+            ],
+            |_, outcome| outcomes.push(outcome),
+        )
+        .unwrap();
+
+        // THEN
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Applied,
+                TxOutcome::Rejected(Rejection::Denylisted),
+                TxOutcome::Rejected(Rejection::Denylisted),
+            ]
+        );
+        let reported: Vec<(u32, ComplianceReason)> = bank
+            .compliance_entries()
+            .iter()
+            .map(|entry| (entry.tx_id, entry.reason))
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                (2, ComplianceReason::Denylisted),
+                (3, ComplianceReason::Denylisted)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_denylist_skips_comments() {
+        // GIVEN
+        let input = "# blocked by ops\n12\n\n 7 \n";
+
+        // WHEN
+        let client_ids = read_denylist(input.as_bytes());
+
+        // THEN
+        assert_eq!(client_ids.unwrap(), vec![12, 7]);
+        assert!(read_denylist("abc\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::bank::{ProcessedTx, TxOutcome};
+use crate::error::{BankError, ErrorCode};
+use serde::Serialize;
+use std::error::Error;
+use std::io;
+use std::str::FromStr;
+
+/**
+ * How rejections and warnings are written while processing.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// `Transaction #<tx>: <message> [<code>]` lines for people (default).
+    #[default]
+    Text,
+    /// One JSON object per line, for monitoring.
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!("Unknown error format {:?}", name)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Diagnostic {
+    code: &'static str,
+    /// `None` for input that could not be read as a transaction.
+    tx: Option<u32>,
+    client: Option<u16>,
+    line: Option<u64>,
+    message: String,
+}
+
+/**
+ * Writes every outcome other than `TxOutcome::Applied` to `writer`. Write
+ * failures don't interrupt processing; the first one is returned by
+ * `finish`.
+ */
+pub struct Diagnostics<W: io::Write> {
+    format: ErrorFormat,
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> Diagnostics<W> {
+    pub fn new(format: ErrorFormat, writer: W) -> Self {
+        Diagnostics {
+            format,
+            writer,
+            error: None,
+        }
+    }
+
+    pub fn report(&mut self, processed: &ProcessedTx, outcome: TxOutcome) {
+        let Some(code) = outcome.code() else {
+            return;
+        };
+        if self.error.is_some() {
+            return;
+        }
+        let result = match self.format {
+            ErrorFormat::Text => writeln!(
+                self.writer,
+                "Transaction #{}: {} [{}]",
+                processed.tx_id, outcome, code
+            ),
+            ErrorFormat::Json => {
+                let diagnostic = Diagnostic {
+                    code: code.as_str(),
+                    tx: Some(processed.tx_id),
+                    client: Some(processed.client_id),
+                    line: processed.line,
+                    message: outcome.to_string(),
+                };
+                self.write_json(&diagnostic)
+            }
+        };
+        self.error = result.err();
+    }
+
+    /**
+     * Reports the error that stopped processing, at input `line` if known.
+     * Only written as JSON: in text mode the error is left to the caller,
+     * which prints it on exit.
+     */
+    pub fn report_fatal(&mut self, error: &(dyn Error + 'static), line: Option<u64>) {
+        if self.format != ErrorFormat::Json || self.error.is_some() {
+            return;
+        }
+        let (code, message) = match error.downcast_ref::<BankError>() {
+            Some(error) => (error.code, error.message.clone()),
+            None => (ErrorCode::InvalidRow, error.to_string()),
+        };
+        let diagnostic = Diagnostic {
+            code: code.as_str(),
+            tx: None,
+            client: None,
+            line,
+            message,
+        };
+        self.error = self.write_json(&diagnostic).err();
+    }
+
+    fn write_json(&mut self, diagnostic: &Diagnostic) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, diagnostic)?;
+        writeln!(self.writer)
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error {
+            Some(error) => Err(error),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::Bank;
+    use crate::input::{CsvSource, TransactionSource, TypeNames};
+
+    #[test]
+    fn test_json_diagnostics() {
+        // GIVEN
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,1.0\n\
+                     withdrawal,1,2,5.0\n\
+                     dispute,2,9,\n";
+        let mut source = CsvSource::new(input.as_bytes(), TypeNames::strict()).unwrap();
+        let mut diagnostics = Diagnostics::new(ErrorFormat::Json, Vec::new());
+
+        // WHEN
+        Bank::<f32>::default()
+            .process_source(&mut source, |processed, outcome| {
+                diagnostics.report(processed, outcome)
+            })
+            .unwrap();
+
+        // THEN
+        assert_eq!(
+            String::from_utf8(diagnostics.writer).unwrap(),
+            "{\"code\":\"E2003\",\"tx\":2,\"client\":1,\"line\":3,\
+             \"message\":\"rejected, insufficient funds\"}\n\
+             {\"code\":\"E3001\",\"tx\":9,\"client\":2,\"line\":4,\
+             \"message\":\"ignored, referenced transaction not found\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_text_diagnostics() {
+        // GIVEN
+        let mut diagnostics = Diagnostics::new(ErrorFormat::Text, Vec::new());
+        let processed = ProcessedTx {
+            tx_id: 4,
+            client_id: 1,
+            line: None,
+        };
+
+        // WHEN
+        diagnostics.report(&processed, TxOutcome::Applied);
+        diagnostics.report(&processed, TxOutcome::RejectedMalformed);
+
+        // THEN
+        assert_eq!(
+            String::from_utf8(diagnostics.writer).unwrap(),
+            "Transaction #4: rejected, malformed transaction [E1010]\n"
+        );
+    }
+
+    #[test]
+    fn test_json_fatal_error() {
+        // GIVEN
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\nrefund,1,2,1.0\n";
+        let mut source = CsvSource::new(input.as_bytes(), TypeNames::strict()).unwrap();
+        let mut diagnostics = Diagnostics::new(ErrorFormat::Json, Vec::new());
+
+        // WHEN
+        let error = Bank::<f32>::default()
+            .process_source(&mut source, |_, _| {})
+            .unwrap_err();
+        diagnostics.report_fatal(error.as_ref(), TransactionSource::<f32>::line(&source));
+
+        // THEN
+        assert_eq!(
+            String::from_utf8(diagnostics.writer).unwrap(),
+            "{\"code\":\"E1003\",\"tx\":null,\"client\":null,\"line\":3,\
+             \"message\":\"Unknown transaction type \\\"refund\\\"\"}\n"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::error::Error;
+use std::fmt;
+
+/**
+ * Stable codes for everything the engine and the input parsers report, for
+ * alerts and runbooks to refer to. Codes are never reused or renumbered:
+ * `E1xxx` for input that cannot be processed, `E2xxx` for refused
+ * transactions, `E3xxx` for ignored ones and `W4xxx` for transactions that
+ * were applied with a warning.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorCode {
+    MissingAmount,
+    AmountOutOfRange,
+    UnknownTransactionType,
+    InvalidRow,
+    FieldCountMismatch,
+    ReadFailed,
+    MissingHandler,
+    HandlerFailed,
+    InvalidChunkSize,
+    UnknownBatch,
+    ClientNotClosed,
+    /// A report or export could not be written.
+    WriteFailed,
+    /// Malformed, skipped under `ErrorPolicy::Skip`.
+    Malformed,
+    AmountLimit,
+    AccountLocked,
+    InsufficientFunds,
+    AccountFrozen,
+    DuplicateTransaction,
+    KycWithdrawal,
+    KycDepositCap,
+    Denylisted,
+    RuleRejected,
+    CustomRejected,
+    DailyLimit,
+    VelocityBlocked,
+    DisputeOverdraw,
+    NoMandate,
+    /// A zero, negative or NaN amount.
+    NonPositiveAmount,
+    /// A transaction type other than deposit or withdrawal in a staged
+    /// batch.
+    NotStageable,
+    UnknownTransaction,
+    ClientMismatch,
+    InvalidState,
+    AlreadyInState,
+    DisputeWindowExpired,
+    WithdrawalNotDisputable,
+    CreditNotDisputable,
+    ReturnWindowExpired,
+    VelocityFlagged,
+    NegativeAvailable,
+    Pending,
+    DisputeCapped,
+}
+
+impl ErrorCode {
+    /// Whether the transaction was applied despite what the code reports.
+    pub fn is_warning(&self) -> bool {
+        self.as_str().starts_with('W')
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::MissingAmount => "E1001",
+            ErrorCode::AmountOutOfRange => "E1002",
+            ErrorCode::UnknownTransactionType => "E1003",
+            ErrorCode::InvalidRow => "E1004",
+            ErrorCode::FieldCountMismatch => "E1005",
+            ErrorCode::ReadFailed => "E1006",
+            ErrorCode::MissingHandler => "E1007",
+            ErrorCode::HandlerFailed => "E1008",
+            ErrorCode::InvalidChunkSize => "E1009",
+            ErrorCode::Malformed => "E1010",
+            ErrorCode::UnknownBatch => "E1011",
+            ErrorCode::ClientNotClosed => "E1012",
+            ErrorCode::WriteFailed => "E1013",
+            ErrorCode::AmountLimit => "E2001",
+            ErrorCode::AccountLocked => "E2002",
+            ErrorCode::InsufficientFunds => "E2003",
+            ErrorCode::AccountFrozen => "E2004",
+            ErrorCode::DuplicateTransaction => "E2005",
+            ErrorCode::KycWithdrawal => "E2006",
+            ErrorCode::KycDepositCap => "E2007",
+            ErrorCode::Denylisted => "E2008",
+            ErrorCode::RuleRejected => "E2009",
+            ErrorCode::CustomRejected => "E2010",
+            ErrorCode::DailyLimit => "E2011",
+            ErrorCode::VelocityBlocked => "E2012",
+            ErrorCode::DisputeOverdraw => "E2013",
+            ErrorCode::NoMandate => "E2014",
+            ErrorCode::NonPositiveAmount => "E2015",
+            ErrorCode::NotStageable => "E2016",
+            ErrorCode::UnknownTransaction => "E3001",
+            ErrorCode::ClientMismatch => "E3002",
+            ErrorCode::InvalidState => "E3003",
+            ErrorCode::AlreadyInState => "E3004",
+            ErrorCode::DisputeWindowExpired => "E3005",
+            ErrorCode::WithdrawalNotDisputable => "E3006",
+            ErrorCode::CreditNotDisputable => "E3007",
+            ErrorCode::ReturnWindowExpired => "E3008",
+            ErrorCode::VelocityFlagged => "W4001",
+            ErrorCode::NegativeAvailable => "W4002",
+            ErrorCode::Pending => "W4003",
+            ErrorCode::DisputeCapped => "W4004",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/**
+ * An error from the engine or an input parser, with its stable code.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BankError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl BankError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        BankError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for BankError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl Error for BankError {}
+
+/// Plain messages, as returned by custom handlers, are `HandlerFailed`.
+impl From<&str> for BankError {
+    fn from(message: &str) -> Self {
+        BankError::new(ErrorCode::HandlerFailed, message)
+    }
+}
+
+impl From<String> for BankError {
+    fn from(message: String) -> Self {
+        BankError::new(ErrorCode::HandlerFailed, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bank_error_display() {
+        let error = BankError::new(ErrorCode::MissingAmount, "missing amount");
+
+        assert_eq!(error.to_string(), "E1001: missing amount");
+        assert_eq!(BankError::from("no fee").code, ErrorCode::HandlerFailed);
+        assert_eq!(ErrorCode::InsufficientFunds.to_string(), "E2003");
+    }
+}
@@ -0,0 +1,234 @@
+use crate::bank::Transaction;
+use crate::error::{BankError, ErrorCode};
+use crate::input::{parse_record, read_error, CsvSource, TransactionSource, TypeNames};
+use crate::money::Money;
+use csv::ByteRecord;
+use memchr::{memchr, memchr_iter};
+use std::error::Error;
+use std::str;
+
+/// The header a fast-path input must have, byte for byte.
+const CANONICAL_HEADER: &[u8] = b"type,client,tx,amount";
+
+/**
+ * Reads CSV held in memory, e.g. a memory-mapped file. Rows of the canonical
+ * `type,client,tx,amount` shape without whitespace are parsed directly, with
+ * vectorized scanning for line and field separators; any other row goes
+ * through the same deserialization as `CsvSource`. Input containing quotes
+ * is read with `CsvSource` altogether, since a quoted field may span lines.
+ */
+pub fn fast_csv_source<'a, M: Money>(
+    input: &'a [u8],
+    type_names: TypeNames,
+) -> Result<Box<dyn TransactionSource<M> + 'a>, Box<dyn Error>> {
+    if memchr(b'"', input).is_some() {
+        return Ok(Box::new(CsvSource::new(input, type_names)?));
+    }
+    let (header, rest) = split_line(input);
+    let headers = read_line(header)?;
+    Ok(Box::new(FastCsvSource {
+        canonical: header == CANONICAL_HEADER,
+        rest,
+        line: 1,
+        headers,
+        type_names,
+    }))
+}
+
+struct FastCsvSource<'a> {
+    canonical: bool,
+    rest: &'a [u8],
+    /// Line of the row last split off, the header being line 1.
+    line: u64,
+    headers: ByteRecord,
+    type_names: TypeNames,
+}
+
+impl FastCsvSource<'_> {
+    fn parse_canonical<M: Money>(&self, line: &[u8]) -> Option<Transaction<M>> {
+        let mut commas = memchr_iter(b',', line);
+        let (first, second, third) = (commas.next()?, commas.next()?, commas.next()?);
+        if commas.next().is_some() {
+            return None;
+        }
+        let tx_type = self
+            .type_names
+            .parse(str::from_utf8(&line[..first]).ok()?)?;
+        let client_id = parse_uint(&line[first + 1..second])?;
+        let tx_id = parse_uint(&line[second + 1..third])?;
+        let amount = match &line[third + 1..] {
+            [] => None,
+            field => Some(str::from_utf8(field).ok()?.parse().ok()?),
+        };
+        Some(Transaction::new(tx_type, client_id, tx_id, amount))
+    }
+
+    fn parse_fallback<M: Money>(&self, line: &[u8]) -> Result<Transaction<M>, Box<dyn Error>> {
+        let record = read_line(line)?;
+        if record.len() != self.headers.len() {
+            return Err(BankError::new(
+                ErrorCode::FieldCountMismatch,
+                format!(
+                    "Found a row with {} fields, but the header has {}",
+                    record.len(),
+                    self.headers.len()
+                ),
+            )
+            .into());
+        }
+        parse_record(&record, &self.headers, &self.type_names)
+    }
+}
+
+impl<M: Money> TransactionSource<M> for FastCsvSource<'_> {
+    fn next_tx(&mut self) -> Option<Result<Transaction<M>, Box<dyn Error>>> {
+        loop {
+            if self.rest.is_empty() {
+                return None;
+            }
+            let (line, rest) = split_line(self.rest);
+            self.rest = rest;
+            self.line += 1;
+            if line.is_empty() {
+                continue;
+            }
+            let fast = self.canonical.then(|| self.parse_canonical(line));
+            return Some(fast.flatten().map_or_else(|| self.parse_fallback(line), Ok));
+        }
+    }
+
+    fn line(&self) -> Option<u64> {
+        Some(self.line)
+    }
+}
+
+/**
+ * Splits off the first line, without its `\n` or `\r\n` ending.
+ */
+fn split_line(input: &[u8]) -> (&[u8], &[u8]) {
+    let (line, rest) = match memchr(b'\n', input) {
+        Some(end) => (&input[..end], &input[end + 1..]),
+        None => (input, &input[input.len()..]),
+    };
+    (line.strip_suffix(b"\r").unwrap_or(line), rest)
+}
+
+pub(crate) fn read_line(line: &[u8]) -> Result<ByteRecord, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(line);
+    let mut record = ByteRecord::new();
+    reader.read_byte_record(&mut record).map_err(read_error)?;
+    record.trim();
+    Ok(record)
+}
+
+/**
+ * Parses plain ASCII digits, refusing signs, whitespace and overflow.
+ */
+fn parse_uint<T: TryFrom<u64>>(field: &[u8]) -> Option<T> {
+    if field.is_empty() {
+        return None;
+    }
+    let value = field.iter().try_fold(0_u64, |value, &byte| {
+        let digit = byte.checked_sub(b'0').filter(|digit| *digit <= 9)?;
+        value.checked_mul(10)?.checked_add(u64::from(digit))
+    })?;
+    T::try_from(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::TransactionType;
+
+    fn read_both(input: &str, type_names: TypeNames) -> Vec<(bool, Option<Transaction<f32>>)> {
+        let mut standard = CsvSource::new(input.as_bytes(), type_names.clone()).unwrap();
+        let mut fast = fast_csv_source(input.as_bytes(), type_names).unwrap();
+        let mut results = Vec::new();
+        loop {
+            let (expected, actual): (Option<Result<Transaction<f32>, _>>, _) =
+                (standard.next_tx(), fast.next_tx());
+            match (expected, actual) {
+                (None, None) => return results,
+                (Some(Ok(expected)), Some(Ok(actual))) => {
+                    assert_eq!(expected, actual);
+                    results.push((true, Some(actual)));
+                }
+                (Some(Err(_)), Some(Err(_))) => results.push((false, None)),
+                (expected, actual) => panic!("{:?} != {:?}", expected, actual),
+            }
+        }
+    }
+
+    #[test]
+    fn test_fast_path_matches_standard_parser() {
+        // GIVEN
+        let input = "type,client,tx,amount\r\n\
+                     deposit,1,1,2.5\r\n\
+                     withdrawal,1,2,1\n\
+                     \n\
+                     dispute,1,1,\n\
+                     deposit, 2, 3, 4.0\n\
+                     Deposit,2,4,1.0\n\
+                     deposit,70000,5,1.0\n\
+                     deposit,-1,6,1.0\n\
+                     deposit,1,7\n\
+                     deposit,1,8,1.0,extra\n\
+                     withdrawal,3,9,abc\n\
+                     resolve,1,1,";
+
+        // WHEN
+        let results = read_both(input, TypeNames::strict());
+
+        // THEN
+        assert_eq!(
+            results.iter().map(|(ok, _)| *ok).collect::<Vec<_>>(),
+            vec![true, true, true, true, false, false, false, false, false, false, true]
+        );
+        assert_eq!(results[3].1, Some(Transaction::deposit(2, 3, 4.0)));
+    }
+
+    #[test]
+    fn test_fast_path_with_tolerant_and_custom_types() {
+        // GIVEN
+        let input = "type,client,tx,amount\nWithdraw,1,1,2.0\nFEE,1,2,1.0\n";
+        let type_names = TypeNames::tolerant().custom("fee");
+
+        // WHEN
+        let results = read_both(input, type_names);
+
+        // THEN
+        assert_eq!(
+            results[1].1,
+            Some(Transaction::new(
+                TransactionType::Custom("fee"),
+                1,
+                2,
+                Some(1.0)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_fast_path_falls_back_for_quotes_and_other_headers() {
+        // GIVEN
+        let quoted = "type,client,tx,amount,description\n\
+                      deposit,1,1,2.0,\"rent,\nJune\"\n";
+        let other_header = "client,type,tx,amount,timestamp\n1,deposit,1,2.0,60\n";
+
+        // WHEN
+        let quoted = read_both(quoted, TypeNames::strict());
+        let other_header = read_both(other_header, TypeNames::strict());
+
+        // THEN
+        assert_eq!(
+            quoted[0].1,
+            Some(Transaction::deposit(1, 1, 2.0).with_description("rent,\nJune"))
+        );
+        assert_eq!(
+            other_header[0].1,
+            Some(Transaction::deposit(1, 1, 2.0).with_timestamp(60))
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! C interface for embedding the engine; see `include/bank.h`.
+
+use crate::bank::Bank;
+use crate::input::{read_transactions, TypeNames};
+use std::ffi::{c_char, CString};
+use std::ptr;
+use std::slice;
+
+/**
+ * Creates a bank with the default policies and exact decimal amounts.
+ * Release it with `bank_free`.
+ */
+#[no_mangle]
+pub extern "C" fn bank_new() -> *mut Bank {
+    Box::into_raw(Box::new(Bank::new()))
+}
+
+/**
+ * Applies the transactions in `len` bytes of CSV at `data`, which must start
+ * with a header row. Returns how many rows were processed, all of them
+ * unless one was malformed; processing stops there, and the rows before it
+ * stay applied. A chunk that cannot be parsed applies no row. If `error` is
+ * not null it is set to the error that stopped the chunk, to release with
+ * `bank_string_free`, or to null. Returns -1 if `bank` or `data` is null.
+ *
+ * # Safety
+ *
+ * `bank` must come from `bank_new` and not be freed. `data` must point to
+ * `len` readable bytes. `error` must be null or writable.
+ */
+#[no_mangle]
+pub unsafe extern "C" fn bank_apply_csv_chunk(
+    bank: *mut Bank,
+    data: *const u8,
+    len: usize,
+    error: *mut *mut c_char,
+) -> i64 {
+    let (Some(bank), false) = (bank.as_ref(), data.is_null()) else {
+        return -1;
+    };
+    let chunk = slice::from_raw_parts(data, len);
+    let mut processed = 0;
+    let result = read_transactions(chunk, &TypeNames::strict()).and_then(|transactions| {
+        bank.batch_process_with(transactions, |_, _| processed += 1)
+            .map_err(Into::into)
+    });
+    if !error.is_null() {
+        *error = match result {
+            Ok(()) => ptr::null_mut(),
+            Err(e) => c_string(e.to_string()),
+        };
+    }
+    processed
+}
+
+/**
+ * Returns the account report as a JSON array of objects with the report
+ * columns, or null on failure. Release it with `bank_string_free`.
+ *
+ * # Safety
+ *
+ * `bank` must come from `bank_new` and not be freed.
+ */
+#[no_mangle]
+pub unsafe extern "C" fn bank_report_json(bank: *const Bank) -> *mut c_char {
+    let Some(bank) = bank.as_ref() else {
+        return ptr::null_mut();
+    };
+    serde_json::to_string(&bank.accounts()).map_or(ptr::null_mut(), c_string)
+}
+
+/// A string for C, or null if it contains a NUL byte.
+fn c_string(text: String) -> *mut c_char {
+    CString::new(text).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/**
+ * Releases a string returned by `bank_report_json` or set by
+ * `bank_apply_csv_chunk`. Null is ignored.
+ *
+ * # Safety
+ *
+ * `string` must come from this interface and not be freed already.
+ */
+#[no_mangle]
+pub unsafe extern "C" fn bank_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/**
+ * Releases a bank. Null is ignored.
+ *
+ * # Safety
+ *
+ * `bank` must come from `bank_new` and not be freed already.
+ */
+#[no_mangle]
+pub unsafe extern "C" fn bank_free(bank: *mut Bank) {
+    if !bank.is_null() {
+        drop(Box::from_raw(bank));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_ffi_round_trip() {
+        // GIVEN
+        let bank = bank_new();
+        let chunk = "type,client,tx,amount\ndeposit,1,1,2.5\nwithdrawal,1,2,1.0\n";
+
+        // WHEN
+        let mut error = ptr::null_mut();
+        let applied =
+            unsafe { bank_apply_csv_chunk(bank, chunk.as_ptr(), chunk.len(), &mut error) };
+        let bad = "type,client,tx,amount\ndeposit,one,3,1.0\n";
+        let invalid =
+            unsafe { bank_apply_csv_chunk(bank, bad.as_ptr(), bad.len(), ptr::null_mut()) };
+        let report = unsafe { bank_report_json(bank) };
+
+        // THEN
+        assert_eq!(applied, 2);
+        assert!(error.is_null());
+        assert_eq!(invalid, 0);
+        assert_eq!(
+            unsafe { CStr::from_ptr(report) }.to_str().unwrap(),
+            r#"[{"client":1,"available":"1.5000","held":"0.0000","total":"1.5000","locked":false,"frozen":false}]"#
+        );
+        unsafe {
+            bank_string_free(report);
+            bank_free(bank);
+        }
+    }
+
+    #[test]
+    fn test_ffi_chunk_reports_rows_applied_before_error() {
+        // GIVEN
+        let bank = bank_new();
+        let chunk = "type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,1,2,\ndeposit,1,3,1.0\n";
+        let mut error = ptr::null_mut();
+
+        // WHEN
+        let applied =
+            unsafe { bank_apply_csv_chunk(bank, chunk.as_ptr(), chunk.len(), &mut error) };
+
+        // THEN
+        assert_eq!(applied, 1);
+        assert!(unsafe { CStr::from_ptr(error) }
+            .to_str()
+            .unwrap()
+            .starts_with("E1001"));
+        unsafe {
+            bank_string_free(error);
+            bank_free(bank);
+        }
+    }
+}
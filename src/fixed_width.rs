@@ -0,0 +1,191 @@
+use crate::bank::AccountView;
+use crate::money::{fixed_text, Money};
+use serde::Deserialize;
+use std::error::Error;
+use std::io::{self, Write};
+
+/**
+ * A report column that can be placed in a fixed-width record.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Column {
+    Client,
+    Available,
+    Held,
+    Total,
+    /// `Y` or `N`.
+    Locked,
+    /// `Y` or `N`.
+    Frozen,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    Left,
+    #[default]
+    Right,
+}
+
+/**
+ * One field of a record: a column written in `width` characters, padded
+ * with `pad` on the side away from `align`.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub struct Field {
+    pub column: Column,
+    pub width: usize,
+    #[serde(default)]
+    pub align: Align,
+    #[serde(default = "default_pad")]
+    pub pad: char,
+}
+
+fn default_pad() -> char {
+    ' '
+}
+
+/**
+ * The fields of each record of a fixed-width report, in order. Records
+ * have no separators and end with a newline.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FixedWidthLayout {
+    #[serde(rename = "field")]
+    pub fields: Vec<Field>,
+}
+
+impl Default for FixedWidthLayout {
+    /**
+     * The layout our regulator expects for the end-of-day balance file:
+     * a zero-padded client id, the three balances right-aligned in 20
+     * characters and the locked flag.
+     */
+    fn default() -> Self {
+        let amount = |column| Field {
+            column,
+            width: 20,
+            align: Align::Right,
+            pad: ' ',
+        };
+        FixedWidthLayout {
+            fields: vec![
+                Field {
+                    column: Column::Client,
+                    width: 5,
+                    align: Align::Right,
+                    pad: '0',
+                },
+                amount(Column::Available),
+                amount(Column::Held),
+                amount(Column::Total),
+                Field {
+                    column: Column::Locked,
+                    width: 1,
+                    align: Align::Left,
+                    pad: ' ',
+                },
+            ],
+        }
+    }
+}
+
+/**
+ * Reads a layout from TOML made of `[[field]]` tables with `column`,
+ * `width` and optional `align` (`left` or `right`, the default) and `pad`
+ * (a space by default).
+ */
+pub fn read_layout(input: &str) -> Result<FixedWidthLayout, Box<dyn Error>> {
+    Ok(toml::from_str(input)?)
+}
+
+/**
+ * Writes one record per account in `layout`, amounts as in the report.
+ * A value wider than its field is an error rather than being cut.
+ */
+pub fn write_fixed_width_report<M: Money, W: io::Write>(
+    accounts: &[AccountView<M>],
+    layout: &FixedWidthLayout,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = io::BufWriter::new(writer);
+    let mut record = String::new();
+    for account in accounts {
+        record.clear();
+        for field in &layout.fields {
+            let value = match field.column {
+                Column::Client => account.client_id.to_string(),
+                Column::Available => fixed_text(account.available),
+                Column::Held => fixed_text(account.held),
+                Column::Total => fixed_text(account.total),
+                Column::Locked => flag(account.locked),
+                Column::Frozen => flag(account.frozen),
+            };
+            let length = value.chars().count();
+            if length > field.width {
+                return Err(format!(
+                    "{:?} of client {} does not fit in {} characters: {}",
+                    field.column, account.client_id, field.width, value
+                )
+                .into());
+            }
+            let padding = (length..field.width).map(|_| field.pad);
+            match field.align {
+                Align::Left => record.extend(value.chars().chain(padding)),
+                Align::Right => record.extend(padding.chain(value.chars())),
+            }
+        }
+        writeln!(writer, "{}", record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn flag(value: bool) -> String {
+    String::from(if value { "Y" } else { "N" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{Bank, Transaction};
+
+    fn write(bank: &Bank<f32>, layout: &FixedWidthLayout) -> Result<String, Box<dyn Error>> {
+        let mut output = Vec::new();
+        write_fixed_width_report(&bank.accounts(), layout, &mut output)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_fixed_width_report() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![
+            Transaction::deposit(7, 1, 1.5),
+            Transaction::deposit(12, 2, 20.0),
+            Transaction::dispute(12, 2),
+            Transaction::chargeback(12, 2),
+        ])
+        .unwrap();
+        let layout = read_layout(
+            "[[field]]\ncolumn = \"client\"\nwidth = 4\nalign = \"left\"\npad = \"_\"\n\
+             [[field]]\ncolumn = \"total\"\nwidth = 8\n",
+        )
+        .unwrap();
+        let narrow = read_layout("[[field]]\ncolumn = \"total\"\nwidth = 5\n").unwrap();
+
+        // WHEN
+        let default = write(&bank, &FixedWidthLayout::default()).unwrap();
+        let custom = write(&bank, &layout).unwrap();
+
+        // THEN
+        assert_eq!(
+            default,
+            "00007              1.5000              0.0000              1.5000N\n\
+             00012              0.0000              0.0000              0.0000Y\n"
+        );
+        assert_eq!(custom, "7___  1.5000\n12__  0.0000\n");
+        assert!(write(&bank, &narrow).is_err());
+    }
+}
@@ -0,0 +1,622 @@
+use crate::bank::{Transaction, TransactionType};
+use crate::error::{BankError, ErrorCode};
+use crate::money::Money;
+use csv::{ByteRecord, Trim};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+
+/**
+ * How names in the `type` column are matched to transaction types. Strict
+ * matching only accepts the canonical lowercase names. Tolerant matching
+ * ignores case and also accepts any registered alias. Custom types are
+ * accepted in both modes.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeNames {
+    tolerant: bool,
+    aliases: HashMap<String, TransactionType>,
+    custom: Vec<&'static str>,
+}
+
+impl TypeNames {
+    pub fn strict() -> Self {
+        TypeNames {
+            tolerant: false,
+            aliases: HashMap::new(),
+            custom: Vec::new(),
+        }
+    }
+
+    /**
+     * Case-insensitive matching with the common aliases `withdraw`,
+     * `charge_back` and `charge-back` registered.
+     */
+    pub fn tolerant() -> Self {
+        TypeNames {
+            tolerant: true,
+            aliases: HashMap::new(),
+            custom: Vec::new(),
+        }
+        .alias("withdraw", TransactionType::Withdrawal)
+        .alias("charge_back", TransactionType::Chargeback)
+        .alias("charge-back", TransactionType::Chargeback)
+    }
+
+    /**
+     * Accepts `name`, in any case, for `tx_type`. Ignored in strict mode.
+     */
+    pub fn alias(mut self, name: &str, tx_type: TransactionType) -> Self {
+        self.aliases.insert(name.to_lowercase(), tx_type);
+        self
+    }
+
+    /**
+     * Accepts `name` as `TransactionType::Custom(name)`, for types handled
+     * by `BankBuilder::custom_type`.
+     */
+    pub fn custom(mut self, name: &'static str) -> Self {
+        self.custom.push(name);
+        self
+    }
+
+    pub fn parse(&self, name: &str) -> Option<TransactionType> {
+        if !self.tolerant {
+            return name.parse().ok().or_else(|| self.parse_custom(name));
+        }
+        let name = name.to_lowercase();
+        name.parse()
+            .ok()
+            .or_else(|| self.aliases.get(&name).copied())
+            .or_else(|| self.parse_custom(&name))
+    }
+
+    fn parse_custom(&self, name: &str) -> Option<TransactionType> {
+        self.custom
+            .iter()
+            .find(|custom| {
+                if self.tolerant {
+                    custom.eq_ignore_ascii_case(name)
+                } else {
+                    **custom == name
+                }
+            })
+            .map(|custom| TransactionType::Custom(custom))
+    }
+}
+
+impl Default for TypeNames {
+    fn default() -> Self {
+        TypeNames::strict()
+    }
+}
+
+/**
+ * Where the engine reads transactions from, one at a time.
+ */
+pub trait TransactionSource<M: Money = Decimal> {
+    /**
+     * The next transaction, an error for an entry that could not be read,
+     * or `None` once the source is exhausted.
+     */
+    fn next_tx(&mut self) -> Option<Result<Transaction<M>, Box<dyn Error>>>;
+
+    /**
+     * The input line (from 1) the last transaction started on, for sources
+     * that keep track of it.
+     */
+    fn line(&self) -> Option<u64> {
+        None
+    }
+}
+
+/**
+ * A CSV row or JSON object, borrowing the type name from the input where it
+ * can, so that text fields are only copied once the row has been turned
+ * into a `Transaction`.
+ */
+#[derive(Deserialize)]
+struct Row<'a, M> {
+    #[serde(rename = "type", borrow)]
+    tx_type: Cow<'a, str>,
+    client: u16,
+    tx: u32,
+    amount: Option<M>,
+    #[serde(default)]
+    timestamp: Option<u64>,
+    #[serde(default, borrow)]
+    tags: Option<Cow<'a, str>>,
+    #[serde(default, borrow)]
+    description: Option<Cow<'a, str>>,
+    #[serde(default, borrow)]
+    external_id: Option<Cow<'a, str>>,
+    #[serde(default, borrow)]
+    mandate: Option<Cow<'a, str>>,
+}
+
+impl<M: Money> Row<'_, M> {
+    /**
+     * The transaction this row describes, its type resolved with
+     * `type_names`.
+     */
+    fn into_transaction(self, type_names: &TypeNames) -> Result<Transaction<M>, Box<dyn Error>> {
+        let tx_type = type_names.parse(&self.tx_type).ok_or_else(|| {
+            BankError::new(
+                ErrorCode::UnknownTransactionType,
+                format!("Unknown transaction type {:?}", self.tx_type),
+            )
+        })?;
+        let mut transaction = Transaction::new(tx_type, self.client, self.tx, self.amount);
+        if let Some(timestamp) = self.timestamp {
+            transaction = transaction.with_timestamp(timestamp);
+        }
+        if let Some(tags) = self.tags {
+            transaction = transaction.with_tags(tags);
+        }
+        if let Some(description) = self.description {
+            transaction = transaction.with_description(description);
+        }
+        if let Some(external_id) = self.external_id {
+            transaction = transaction.with_external_id(external_id);
+        }
+        if let Some(mandate) = self.mandate {
+            transaction = transaction.with_mandate(mandate);
+        }
+        Ok(transaction)
+    }
+}
+
+/**
+ * Reads transactions from CSV with headers, trimming whitespace. The `type`
+ * column is resolved with `type_names`. Rows are read into a single reused
+ * buffer.
+ */
+pub struct CsvSource<R> {
+    reader: csv::Reader<R>,
+    headers: ByteRecord,
+    record: ByteRecord,
+    type_names: TypeNames,
+}
+
+impl<R: io::Read> CsvSource<R> {
+    pub fn new(reader: R, type_names: TypeNames) -> Result<Self, Box<dyn Error>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(Trim::All)
+            .from_reader(reader);
+        let headers = reader.byte_headers().map_err(read_error)?.clone();
+        Ok(CsvSource {
+            reader,
+            headers,
+            record: ByteRecord::new(),
+            type_names,
+        })
+    }
+}
+
+impl<M: Money, R: io::Read> TransactionSource<M> for CsvSource<R> {
+    fn next_tx(&mut self) -> Option<Result<Transaction<M>, Box<dyn Error>>> {
+        match self.reader.read_byte_record(&mut self.record) {
+            Ok(true) => Some(parse_record(&self.record, &self.headers, &self.type_names)),
+            Ok(false) => None,
+            Err(e) => Some(Err(read_error(e).into())),
+        }
+    }
+
+    fn line(&self) -> Option<u64> {
+        self.record.position().map(|position| position.line())
+    }
+}
+
+/**
+ * Reads one JSON object per line, with the same fields as the CSV columns.
+ * The `type` field is resolved with `type_names`. Blank lines are skipped.
+ */
+pub struct JsonLinesSource<R> {
+    lines: io::Lines<R>,
+    line: u64,
+    type_names: TypeNames,
+}
+
+impl<R: io::BufRead> JsonLinesSource<R> {
+    pub fn new(reader: R, type_names: TypeNames) -> Self {
+        JsonLinesSource {
+            lines: reader.lines(),
+            line: 0,
+            type_names,
+        }
+    }
+}
+
+impl<M: Money, R: io::BufRead> TransactionSource<M> for JsonLinesSource<R> {
+    fn next_tx(&mut self) -> Option<Result<Transaction<M>, Box<dyn Error>>> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => {
+                    return Some(Err(
+                        BankError::new(ErrorCode::ReadFailed, e.to_string()).into()
+                    ))
+                }
+            };
+            self.line += 1;
+            if !line.trim().is_empty() {
+                return Some(
+                    serde_json::from_str::<Row<M>>(&line)
+                        .map_err(|e| BankError::new(ErrorCode::InvalidRow, e.to_string()).into())
+                        .and_then(|row| row.into_transaction(&self.type_names)),
+                );
+            }
+        }
+    }
+
+    fn line(&self) -> Option<u64> {
+        Some(self.line).filter(|line| *line > 0)
+    }
+}
+
+/**
+ * Reads a JSON array of objects with the same fields as the CSV columns.
+ * The `type` field is resolved with `type_names`. The whole array is parsed
+ * before the first transaction is returned, so large inputs are better
+ * written as JSON Lines.
+ */
+pub struct JsonArraySource<M> {
+    transactions: std::vec::IntoIter<Result<Transaction<M>, Box<dyn Error>>>,
+}
+
+impl<M: Money> JsonArraySource<M> {
+    pub fn new<R: io::Read>(mut reader: R, type_names: TypeNames) -> Result<Self, Box<dyn Error>> {
+        let mut input = String::new();
+        reader
+            .read_to_string(&mut input)
+            .map_err(|e| BankError::new(ErrorCode::ReadFailed, e.to_string()))?;
+        let rows: Vec<Row<M>> = serde_json::from_str(&input)
+            .map_err(|e| BankError::new(ErrorCode::InvalidRow, e.to_string()))?;
+        let transactions: Vec<_> = rows
+            .into_iter()
+            .map(|row| row.into_transaction(&type_names))
+            .collect();
+        Ok(JsonArraySource {
+            transactions: transactions.into_iter(),
+        })
+    }
+}
+
+impl<M: Money> TransactionSource<M> for JsonArraySource<M> {
+    fn next_tx(&mut self) -> Option<Result<Transaction<M>, Box<dyn Error>>> {
+        self.transactions.next()
+    }
+}
+
+/// An entry read ahead, with the input line it came from.
+type Peeked<M> = (Option<Result<Transaction<M>, Box<dyn Error>>>, Option<u64>);
+
+/**
+ * Interleaves several sources, each ordered by timestamp, into one ordered
+ * by timestamp (a k-way merge). Ties go to the source listed first. A
+ * transaction without a timestamp counts as happening at the timestamp
+ * before it in its own source, or 0, so it stays right after its
+ * predecessor. A read error is returned as soon as it reaches the front of
+ * its source.
+ */
+pub struct MergedSource<'a, M: Money = Decimal> {
+    sources: Vec<Box<dyn TransactionSource<M> + 'a>>,
+    heads: Vec<Peeked<M>>,
+    /// Latest timestamp seen in each source.
+    clocks: Vec<u64>,
+    line: Option<u64>,
+}
+
+impl<'a, M: Money> MergedSource<'a, M> {
+    pub fn new(mut sources: Vec<Box<dyn TransactionSource<M> + 'a>>) -> Self {
+        let heads = sources
+            .iter_mut()
+            .map(|source| peek(source.as_mut()))
+            .collect();
+        MergedSource {
+            clocks: vec![0; sources.len()],
+            sources,
+            heads,
+            line: None,
+        }
+    }
+
+    fn head_time(&self, index: usize) -> Option<u64> {
+        match &self.heads[index].0 {
+            Some(Ok(tx)) => Some(tx.timestamp().unwrap_or(self.clocks[index])),
+            // Errors go first, so they surface where they occur.
+            Some(Err(_)) => Some(0),
+            None => None,
+        }
+    }
+}
+
+fn peek<M: Money>(source: &mut dyn TransactionSource<M>) -> Peeked<M> {
+    let entry = source.next_tx();
+    (entry, source.line())
+}
+
+impl<M: Money> TransactionSource<M> for MergedSource<'_, M> {
+    fn next_tx(&mut self) -> Option<Result<Transaction<M>, Box<dyn Error>>> {
+        let index = (0..self.heads.len())
+            .filter_map(|index| Some((self.head_time(index)?, index)))
+            .min()?
+            .1;
+        let next = peek(self.sources[index].as_mut());
+        let (head, line) = std::mem::replace(&mut self.heads[index], next);
+        if let Some(Ok(tx)) = &head {
+            self.clocks[index] = tx.timestamp().unwrap_or(self.clocks[index]);
+        }
+        self.line = line;
+        head
+    }
+
+    /// The line within the source the last transaction came from.
+    fn line(&self) -> Option<u64> {
+        self.line
+    }
+}
+
+/**
+ * Turns a CSV record into a transaction, resolving its type with
+ * `type_names`.
+ */
+pub(crate) fn parse_record<M: Money>(
+    record: &ByteRecord,
+    headers: &ByteRecord,
+    type_names: &TypeNames,
+) -> Result<Transaction<M>, Box<dyn Error>> {
+    let row: Row<M> = record.deserialize(Some(headers)).map_err(read_error)?;
+    row.into_transaction(type_names)
+}
+
+/**
+ * Codes a CSV error by its kind.
+ */
+pub(crate) fn read_error(error: csv::Error) -> BankError {
+    let code = match error.kind() {
+        csv::ErrorKind::Io(_) => ErrorCode::ReadFailed,
+        csv::ErrorKind::UnequalLengths { .. } => ErrorCode::FieldCountMismatch,
+        _ => ErrorCode::InvalidRow,
+    };
+    BankError::new(code, error.to_string())
+}
+
+/**
+ * Reads every transaction from CSV; see `CsvSource`.
+ */
+pub fn read_transactions<M: Money, R: io::Read>(
+    reader: R,
+    type_names: &TypeNames,
+) -> Result<Vec<Transaction<M>>, Box<dyn Error>> {
+    let mut source = CsvSource::new(reader, type_names.clone())?;
+    let mut results = Vec::new();
+    while let Some(tx) = source.next_tx() {
+        results.push(tx?);
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tolerant_type_names() {
+        // GIVEN
+        let names = TypeNames::tolerant().alias("refund", TransactionType::Deposit);
+
+        // THEN
+        assert_eq!(names.parse("DEPOSIT"), Some(TransactionType::Deposit));
+        assert_eq!(names.parse("Withdraw"), Some(TransactionType::Withdrawal));
+        assert_eq!(
+            names.parse("charge_back"),
+            Some(TransactionType::Chargeback)
+        );
+        assert_eq!(names.parse("Refund"), Some(TransactionType::Deposit));
+        assert_eq!(names.parse("transfer"), None);
+    }
+
+    #[test]
+    fn test_strict_type_names() {
+        // GIVEN
+        let names = TypeNames::strict().alias("refund", TransactionType::Deposit);
+
+        // THEN
+        assert_eq!(names.parse("deposit"), Some(TransactionType::Deposit));
+        assert_eq!(names.parse("Deposit"), None);
+        assert_eq!(names.parse("refund"), None);
+    }
+
+    #[test]
+    fn test_read_transactions_by_mode() {
+        // GIVEN
+        let input = "type, client, tx, amount\nDeposit, 1, 1, 2.0\nwithdraw, 1, 2, 1.0\n";
+
+        // WHEN
+        let tolerant = read_transactions(input.as_bytes(), &TypeNames::tolerant());
+        let strict = read_transactions::<f32, _>(input.as_bytes(), &TypeNames::strict());
+
+        // THEN
+        assert_eq!(
+            tolerant.unwrap(),
+            vec![
+                Transaction::deposit(1, 1, 2.0),
+                Transaction::withdrawal(1, 2, 1.0)
+            ]
+        );
+        assert!(strict.is_err());
+    }
+
+    #[test]
+    fn test_read_custom_types_in_strict_mode() {
+        // GIVEN
+        let names = TypeNames::strict().custom("fee");
+
+        // WHEN
+        let exact = read_transactions("type,client,tx,amount\nfee,1,1,2.0\n".as_bytes(), &names);
+        let other_case =
+            read_transactions::<f32, _>("type,client,tx,amount\nFee,1,1,2.0\n".as_bytes(), &names);
+
+        // THEN
+        assert_eq!(
+            exact.unwrap(),
+            vec![Transaction::new(
+                TransactionType::Custom("fee"),
+                1,
+                1,
+                Some(2.0)
+            )]
+        );
+        assert!(other_case.is_err());
+    }
+
+    #[test]
+    fn test_json_lines_source() {
+        // GIVEN
+        let input = concat!(
+            r#"{"type":"deposit","client":1,"tx":1,"amount":2.0}"#,
+            "\n\n",
+            r#"{"type":"dispute","client":1,"tx":1}"#,
+            "\n",
+            r#"{"type":"deposit","client":"one","tx":2}"#,
+            "\n",
+        );
+        let mut source = JsonLinesSource::new(input.as_bytes(), TypeNames::strict());
+
+        // WHEN
+        let first: Result<Transaction<f32>, _> = source.next_tx().unwrap();
+        let second: Result<Transaction<f32>, _> = source.next_tx().unwrap();
+        let third: Result<Transaction<f32>, _> = source.next_tx().unwrap();
+
+        // THEN
+        assert_eq!(first.unwrap(), Transaction::deposit(1, 1, 2.0));
+        assert_eq!(second.unwrap(), Transaction::dispute(1, 1));
+        assert!(third.is_err());
+        assert!(TransactionSource::<f32>::next_tx(&mut source).is_none());
+    }
+
+    #[test]
+    fn test_json_lines_source_resolves_types() {
+        // GIVEN
+        let input = concat!(
+            r#"{"type":"Withdraw","client":1,"tx":1,"amount":2.0,"description":"rent,\nJune"}"#,
+            "\n",
+        );
+
+        // WHEN
+        let tolerant: Result<Transaction<f32>, _> =
+            JsonLinesSource::new(input.as_bytes(), TypeNames::tolerant())
+                .next_tx()
+                .unwrap();
+        let strict: Result<Transaction<f32>, _> =
+            JsonLinesSource::new(input.as_bytes(), TypeNames::strict())
+                .next_tx()
+                .unwrap();
+
+        // THEN
+        assert_eq!(
+            tolerant.unwrap(),
+            Transaction::withdrawal(1, 1, 2.0).with_description("rent,\nJune")
+        );
+        assert_eq!(
+            strict.unwrap_err().downcast::<BankError>().unwrap().code,
+            ErrorCode::UnknownTransactionType
+        );
+    }
+
+    #[test]
+    fn test_json_array_source() {
+        // GIVEN
+        let input = r#"[
+            {"type": "deposit", "client": 1, "tx": 1, "amount": 2.0},
+            {"type": "Dispute", "client": 1, "tx": 1}
+        ]"#;
+
+        // WHEN
+        let mut source = JsonArraySource::new(input.as_bytes(), TypeNames::tolerant()).unwrap();
+        let mut strict = JsonArraySource::new(input.as_bytes(), TypeNames::strict()).unwrap();
+
+        // THEN
+        assert_eq!(
+            source.next_tx().unwrap().unwrap(),
+            Transaction::deposit(1, 1, 2.0)
+        );
+        assert_eq!(
+            source.next_tx().unwrap().unwrap(),
+            Transaction::dispute(1, 1)
+        );
+        assert!(source.next_tx().is_none());
+        assert!(strict.next_tx().unwrap().is_ok());
+        assert!(TransactionSource::<f32>::next_tx(&mut strict)
+            .unwrap()
+            .is_err());
+        assert!(JsonArraySource::<f32>::new(
+            r#"[{"type": "deposit"}]"#.as_bytes(),
+            TypeNames::strict()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_read_optional_columns() {
+        // GIVEN
+        let input = "type,client,tx,amount,timestamp,tags,external_id\n\
+                     deposit,1,1,2.0,60,batch-9,PSP-1\n\
+                     dispute,1,1,,,,\n";
+
+        // WHEN
+        let transactions = read_transactions(input.as_bytes(), &TypeNames::strict());
+
+        // THEN
+        assert_eq!(
+            transactions.unwrap(),
+            vec![
+                Transaction::deposit(1, 1, 2.0)
+                    .with_timestamp(60)
+                    .with_tags("batch-9")
+                    .with_external_id("PSP-1"),
+                Transaction::dispute(1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merged_source_orders_by_timestamp() {
+        // GIVEN
+        let first = "type,client,tx,amount,timestamp\n\
+                     deposit,1,1,1.0,10\n\
+                     deposit,1,2,1.0,\n\
+                     deposit,1,3,1.0,30\n";
+        let second = "type,client,tx,amount,timestamp\n\
+                      deposit,2,4,1.0,10\n\
+                      deposit,2,5,1.0,20\n";
+        let sources: Vec<Box<dyn TransactionSource<f32>>> = vec![
+            Box::new(CsvSource::new(first.as_bytes(), TypeNames::strict()).unwrap()),
+            Box::new(CsvSource::new(second.as_bytes(), TypeNames::strict()).unwrap()),
+        ];
+        let mut merged = MergedSource::new(sources);
+
+        // WHEN
+        let mut order = Vec::new();
+        while let Some(tx) = merged.next_tx() {
+            order.push((tx.unwrap().id(), merged.line()));
+        }
+
+        // THEN
+        assert_eq!(
+            order,
+            vec![
+                (1, Some(2)),
+                (2, Some(3)),
+                (4, Some(2)),
+                (5, Some(3)),
+                (3, Some(4))
+            ]
+        );
+    }
+}
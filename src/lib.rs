@@ -0,0 +1,49 @@
+//! A payments engine processing deposits, withdrawals, disputes and their
+//! resolutions per client account. `Bank` applies transactions read by the
+//! sources in `input` and produces the account report; the other modules
+//! are policies, reports and integrations built around it. The command line
+//! program is a thin wrapper over this crate.
+
+#[cfg(feature = "server")]
+pub mod admin;
+pub mod anonymize;
+mod arena;
+#[cfg(feature = "tokio")]
+pub mod async_input;
+pub mod bank;
+pub mod compliance;
+pub mod diagnostics;
+pub mod error;
+pub mod fast_csv;
+pub mod ffi;
+pub mod fixed_width;
+pub mod input;
+pub mod liquidity;
+pub mod locale;
+pub mod mandates;
+pub mod migrate;
+pub mod money;
+pub mod overrides;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+pub mod provenance;
+pub mod reorder;
+pub mod retry;
+pub mod review;
+pub mod risk;
+pub mod rules;
+pub mod shadow;
+pub mod sinks;
+pub mod snapshot;
+pub mod statements;
+pub mod stats;
+mod store;
+pub mod testkit;
+pub mod validation;
+
+pub use crate::bank::{
+    AccountView, Bank, BankBuilder, BatchSummary, Transaction, TransactionType, TxOutcome,
+};
+pub use crate::error::{BankError, ErrorCode};
+pub use crate::input::{read_transactions, CsvSource, TransactionSource, TypeNames};
+pub use crate::money::Money;
@@ -0,0 +1,225 @@
+use crate::bank::{add, sub, AccountView, TransactionRecord, TransactionStatus, TransactionType};
+use crate::error::BankError;
+use crate::money::{serialize_fixed, Money};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+/// Movements listed in `LiquidityReport::largest_movements`.
+const LARGEST_MOVEMENTS: usize = 10;
+
+/**
+ * The bank-wide cash position for treasury, as returned by
+ * `Bank::liquidity_report`.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(bound(serialize = "M: Money"))]
+pub struct LiquidityReport<M = Decimal> {
+    /// What the bank owes its clients: the sum of every account's total.
+    #[serde(serialize_with = "serialize_fixed")]
+    pub liabilities: M,
+    #[serde(serialize_with = "serialize_fixed")]
+    pub held: M,
+    /// Funds moved on each day with timestamped movements, oldest first.
+    pub daily_flows: Vec<DailyFlow<M>>,
+    /// Largest movements first, ties by transaction id.
+    pub largest_movements: Vec<Movement<M>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(bound(serialize = "M: Money"))]
+pub struct DailyFlow<M = Decimal> {
+    /// UTC date, `YYYY-MM-DD`.
+    pub date: String,
+    #[serde(serialize_with = "serialize_fixed")]
+    pub inflow: M,
+    #[serde(serialize_with = "serialize_fixed")]
+    pub outflow: M,
+    /// `inflow - outflow`.
+    #[serde(serialize_with = "serialize_fixed")]
+    pub net: M,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(bound(serialize = "M: Money"))]
+pub struct Movement<M = Decimal> {
+    pub tx: u32,
+    pub client: u16,
+    #[serde(rename = "type")]
+    pub tx_type: TransactionType,
+    #[serde(serialize_with = "serialize_fixed")]
+    pub amount: M,
+}
+
+impl<M: Money> LiquidityReport<M> {
+    /**
+     * Aggregates the accounts and the movements among the stored
+     * transactions: deposits in, withdrawals and direct debits out. Pending,
+     * declined, charged back and returned ones moved no funds in the end and
+     * are left out, and so are withdrawals kept without their details.
+     */
+    pub(crate) fn build<'a>(
+        accounts: &[AccountView<M>],
+        transactions: impl Iterator<Item = &'a TransactionRecord<M>>,
+    ) -> Result<Self, BankError> {
+        let mut liabilities = M::zero();
+        let mut held = M::zero();
+        for account in accounts {
+            liabilities = add(liabilities, account.total)?;
+            held = add(held, account.held)?;
+        }
+
+        let mut days: BTreeMap<u64, (M, M)> = BTreeMap::new();
+        let mut movements = Vec::new();
+        for (tx, status) in transactions {
+            let settled = matches!(
+                status,
+                TransactionStatus::Processed
+                    | TransactionStatus::Disputed
+                    | TransactionStatus::Resolved
+            );
+            let inflow = match tx.tx_type() {
+                TransactionType::Deposit => true,
+                TransactionType::Withdrawal | TransactionType::DirectDebit => false,
+                _ => continue,
+            };
+            let Some(amount) = tx.amount().filter(|_| settled) else {
+                continue;
+            };
+            if let Some(timestamp) = tx.timestamp() {
+                let (day_in, day_out) = days.entry(timestamp / SECONDS_PER_DAY).or_default();
+                if inflow {
+                    *day_in = add(*day_in, amount)?;
+                } else {
+                    *day_out = add(*day_out, amount)?;
+                }
+            }
+            movements.push(Movement {
+                tx: tx.id(),
+                client: tx.client_id(),
+                tx_type: tx.tx_type(),
+                amount,
+            });
+        }
+
+        let daily_flows = days
+            .into_iter()
+            .map(|(day, (inflow, outflow))| {
+                Ok(DailyFlow {
+                    date: civil_date(day),
+                    inflow,
+                    outflow,
+                    net: sub(inflow, outflow)?,
+                })
+            })
+            .collect::<Result<_, BankError>>()?;
+        movements.sort_by(|a, b| {
+            b.amount
+                .partial_cmp(&a.amount)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.tx.cmp(&b.tx))
+        });
+        movements.truncate(LARGEST_MOVEMENTS);
+
+        Ok(LiquidityReport {
+            liabilities,
+            held,
+            daily_flows,
+            largest_movements: movements,
+        })
+    }
+}
+
+/**
+ * The proleptic Gregorian date of `days` since the Unix epoch.
+ */
+fn civil_date(days: u64) -> String {
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub fn write_liquidity_report<M: Money, W: io::Write>(
+    report: &LiquidityReport<M>,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    serde_json::to_writer_pretty(writer, report)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{Bank, Transaction};
+
+    #[test]
+    fn test_liquidity_report() {
+        // GIVEN
+        let day = SECONDS_PER_DAY;
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 100.0).with_timestamp(0),
+            Transaction::deposit(2, 2, 50.0).with_timestamp(10),
+            Transaction::withdrawal(1, 3, 30.0).with_timestamp(day),
+            Transaction::deposit(2, 4, 20.0).with_timestamp(day),
+            Transaction::dispute(2, 4),
+            Transaction::chargeback(2, 4),
+            Transaction::deposit(1, 5, 5.0),
+            Transaction::dispute(1, 5),
+        ])
+        .unwrap();
+
+        // WHEN
+        let report = bank.liquidity_report().unwrap();
+
+        // THEN
+        assert_eq!((report.liabilities, report.held), (125.0, 5.0));
+        assert_eq!(
+            report.daily_flows,
+            vec![
+                DailyFlow {
+                    date: String::from("1970-01-01"),
+                    inflow: 150.0,
+                    outflow: 0.0,
+                    net: 150.0,
+                },
+                DailyFlow {
+                    date: String::from("1970-01-02"),
+                    inflow: 0.0,
+                    outflow: 30.0,
+                    net: -30.0,
+                },
+            ]
+        );
+        assert_eq!(
+            report
+                .largest_movements
+                .iter()
+                .map(|movement| movement.tx)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3, 5]
+        );
+    }
+
+    #[test]
+    fn test_civil_date() {
+        assert_eq!(civil_date(0), "1970-01-01");
+        assert_eq!(civil_date(11_016), "2000-02-29");
+        assert_eq!(civil_date(20_743), "2026-10-17");
+    }
+}
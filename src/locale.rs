@@ -0,0 +1,139 @@
+use crate::bank::AccountView;
+use crate::money::Money;
+use std::error::Error;
+use std::io;
+use std::str::FromStr;
+
+/**
+ * How amounts are written for people rather than programs: the decimal
+ * separator and an optional separator between groups of three digits.
+ * Only used by `write_localized_report`; the default report is unaffected.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NumberFormat {
+    pub decimal: char,
+    pub thousands: Option<char>,
+}
+
+impl NumberFormat {
+    /**
+     * Rewrites a plain decimal such as `-1234.5000` in this format.
+     */
+    pub fn format(&self, plain: &str) -> String {
+        let (sign, digits) = match plain.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", plain),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let mut formatted = String::from(sign);
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                formatted.extend(self.thousands);
+            }
+            formatted.push(digit);
+        }
+        if !fraction.is_empty() {
+            formatted.push(self.decimal);
+            formatted.push_str(fraction);
+        }
+        formatted
+    }
+
+    /**
+     * `;` when either separator is a comma, as spreadsheets in those
+     * locales expect, `,` otherwise.
+     */
+    fn field_delimiter(&self) -> u8 {
+        if self.decimal == ',' || self.thousands == Some(',') {
+            b';'
+        } else {
+            b','
+        }
+    }
+}
+
+impl FromStr for NumberFormat {
+    type Err = String;
+
+    fn from_str(locale: &str) -> Result<Self, Self::Err> {
+        let (decimal, thousands) = match locale {
+            "en" => ('.', ','),
+            "de" => (',', '.'),
+            "fr" => (',', '\u{a0}'),
+            "de-CH" => ('.', '\''),
+            _ => return Err(format!("Unknown number format {:?}", locale)),
+        };
+        Ok(NumberFormat {
+            decimal,
+            thousands: Some(thousands),
+        })
+    }
+}
+
+/**
+ * Writes the account report with amounts in `format`. The output is meant
+ * for reading, not for feeding back into other tools.
+ */
+pub fn write_localized_report<M: Money, W: io::Write>(
+    accounts: &[AccountView<M>],
+    format: &NumberFormat,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(format.field_delimiter())
+        .from_writer(writer);
+    writer.write_record(["client", "available", "held", "total", "locked", "frozen"])?;
+    for account in accounts {
+        writer.write_record([
+            account.client_id.to_string(),
+            format.format(&account.available.to_fixed()),
+            format.format(&account.held.to_fixed()),
+            format.format(&account.total.to_fixed()),
+            account.locked.to_string(),
+            account.frozen.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_formats() {
+        let de: NumberFormat = "de".parse().unwrap();
+        let fr: NumberFormat = "fr".parse().unwrap();
+        let ch: NumberFormat = "de-CH".parse().unwrap();
+
+        assert_eq!(de.format("-1234567.5000"), "-1.234.567,5000");
+        assert_eq!(de.format("123.0000"), "123,0000");
+        assert_eq!(fr.format("1234.5000"), "1\u{a0}234,5000");
+        assert_eq!(ch.format("1000.0000"), "1'000.0000");
+        assert!("xx".parse::<NumberFormat>().is_err());
+    }
+
+    #[test]
+    fn test_write_localized_report() {
+        // GIVEN
+        let accounts = [AccountView {
+            client_id: 1,
+            available: 1234.5_f32,
+            held: 0.0,
+            total: 1234.5,
+            locked: false,
+            frozen: false,
+        }];
+        let mut report = Vec::new();
+
+        // WHEN
+        write_localized_report(&accounts, &"de".parse().unwrap(), &mut report).unwrap();
+
+        // THEN
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "client;available;held;total;locked;frozen\n1;1.234,5000;0,0000;1.234,5000;false;false\n"
+        );
+    }
+}
@@ -1,50 +1,607 @@
-use csv::Trim;
 use std::env;
-use std::{error::Error, ffi::OsString};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 
-mod bank;
-use crate::bank::{Bank as RustBank, Transaction};
+mod cli;
+
+use crate::cli::{InputFormat, MigrateOptions, Options};
+use memmap2::Mmap;
+#[cfg(feature = "server")]
+use rs_bank_assignment::admin::SharedAdminState;
+use rs_bank_assignment::anonymize::{
+    write_anonymized_compliance_report, write_anonymized_report, Anonymizer,
+};
+#[cfg(feature = "tokio")]
+use rs_bank_assignment::async_input::csv_stream;
+use rs_bank_assignment::bank::{
+    Bank as RustBank, BankBuilder, BatchSummary, ProcessedTx, ReportFormat, TxOutcome,
+};
+use rs_bank_assignment::compliance::{read_denylist, write_compliance_report, AmlConfig};
+use rs_bank_assignment::diagnostics::Diagnostics;
+use rs_bank_assignment::fast_csv::fast_csv_source;
+use rs_bank_assignment::fixed_width::{read_layout, write_fixed_width_report, FixedWidthLayout};
+use rs_bank_assignment::input::{
+    CsvSource, JsonArraySource, JsonLinesSource, MergedSource, TransactionSource, TypeNames,
+};
+use rs_bank_assignment::liquidity::write_liquidity_report;
+use rs_bank_assignment::locale::write_localized_report;
+use rs_bank_assignment::mandates::read_mandates;
+use rs_bank_assignment::migrate::{migrate, read_mapping};
+use rs_bank_assignment::money::{MinorUnits, Money};
+use rs_bank_assignment::overrides::{read_joint_holders, read_overrides};
+#[cfg(feature = "profiling")]
+use rs_bank_assignment::profiling::Profiler;
+use rs_bank_assignment::provenance::Provenance;
+use rs_bank_assignment::reorder::ReorderingSource;
+use rs_bank_assignment::retry::{RetryPolicy, RetryingReader};
+use rs_bank_assignment::review::{write_pending_transactions, write_review_queue};
+use rs_bank_assignment::rules::read_rules;
+use rs_bank_assignment::shadow::{compare_accounts, compare_outcomes, Divergence, ShadowEngine};
+use rs_bank_assignment::sinks::{CdcSink, FileSink, HttpSink};
+use rs_bank_assignment::statements::write_client_statement;
+use rs_bank_assignment::stats::{peak_memory_kb, RunStats, TimedSource};
+use rust_decimal::Decimal;
+use std::cell::Cell;
+#[cfg(feature = "server")]
+use std::net::TcpListener;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// How long `--updates-url` waits to connect, send or hear back.
+const UPDATE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Typical size of a `type,client,tx,amount` row, for sizing hints.
+const ESTIMATED_ROW_BYTES: u64 = 20;
 
 fn main() {
-    match get_first_arg() {
-        Ok(file_path) => match parse_transactions(file_path) {
-            Ok(transactions) => {
-                let bank = RustBank::new();
-                if let Err(e) = bank.batch_process(transactions) {
-                    eprintln!("{}", e);
-                    return;
-                }
-                if let Err(e) = bank.print_report() {
-                    eprintln!("{}", e);
-                }
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args_os().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "migrate") {
+        args.next();
+        return run_migrate(cli::parse_migrate_args(args)?);
+    }
+    let options = cli::parse_args(args)?;
+    let file = File::open(&options.input)?;
+    let expected_rows = match options.expected_rows {
+        Some(rows) => rows,
+        None => (file.metadata()?.len() / ESTIMATED_ROW_BYTES) as usize,
+    };
+    let map = if options.mmap || options.fast_csv {
+        // SAFETY: the input is only read, and is expected not to be modified
+        // while the run lasts; a truncated file would fault on access.
+        Some(unsafe { Mmap::map(&file)? })
+    } else {
+        None
+    };
+    let read_retries = Rc::new(Cell::new(0));
+    let late = Rc::new(Cell::new(0));
+    let mut source = open_input(
+        &options,
+        file,
+        map.as_deref(),
+        read_retries.clone(),
+        late.clone(),
+    )?;
+    let parse_time = Rc::new(Cell::new(Duration::ZERO));
+    if options.stats {
+        source = Box::new(TimedSource::new(source, parse_time.clone()));
+    }
+    #[cfg(feature = "server")]
+    let admin = start_admin(&options)?;
+    let builder = configure_bank(&options, expected_rows)?;
+    #[cfg(feature = "server")]
+    let builder = match &admin {
+        Some(state) => builder.update_sink(state.clone()),
+        None => builder,
+    };
+    let bank = builder.build();
+    let errors_output: Box<dyn Write> = match &options.errors_output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stderr()),
+    };
+    let mut diagnostics = Diagnostics::new(options.errors, errors_output);
+    let mut summary = BatchSummary::default();
+    let mut outcomes = Vec::new();
+    #[cfg(feature = "profiling")]
+    let profiler = match &options.profile {
+        Some(path) => Some(Profiler::start(path)?),
+        None => None,
+    };
+    let processing_started = Instant::now();
+    let on_outcome = |processed: &ProcessedTx, outcome| {
+        summary.record(processed.client_id, outcome);
+        #[cfg(feature = "server")]
+        if let Some(state) = &admin {
+            state.record(outcome);
+        }
+        if options.shadow.is_some() {
+            outcomes.push((processed.tx_id, outcome));
+        }
+        diagnostics.report(processed, outcome);
+    };
+    #[cfg(feature = "tokio")]
+    let processed = if options.async_input {
+        process_async(&bank, &options, on_outcome)
+    } else {
+        process_all(
+            &bank,
+            &options,
+            source.as_mut(),
+            read_retries.clone(),
+            on_outcome,
+        )
+    };
+    #[cfg(not(feature = "tokio"))]
+    let processed = process_all(
+        &bank,
+        &options,
+        source.as_mut(),
+        read_retries.clone(),
+        on_outcome,
+    );
+    let processing_time = processing_started.elapsed();
+    summary.duration = processing_time;
+    #[cfg(feature = "profiling")]
+    if let Some(profiler) = profiler {
+        if let Err(error) = profiler.finish() {
+            eprintln!("Could not write the profile: {}", error);
+        }
+    }
+    if let Err((error, line)) = &processed {
+        diagnostics.report_fatal(error.as_ref(), *line);
+    }
+    diagnostics.finish()?;
+    let skipped = processed.map_err(|(error, _)| error)?;
+    if skipped > 0 {
+        eprintln!("Skipped {} already applied transactions", skipped);
+    }
+    if read_retries.get() > 0 {
+        eprintln!("Retried {} transient read failures", read_retries.get());
+    }
+    if late.get() > 0 {
+        eprintln!(
+            "{} transactions arrived beyond the reorder window",
+            late.get()
+        );
+    }
+    if let Some(engine) = options.shadow {
+        let divergences = match engine {
+            ShadowEngine::F32 => run_shadow::<f32>(&bank, &outcomes, &options, expected_rows)?,
+            ShadowEngine::MinorUnits => {
+                run_shadow::<MinorUnits>(&bank, &outcomes, &options, expected_rows)?
             }
-            Err(e) => {
-                eprintln!("{}", e);
-                return;
+        };
+        eprintln!("Shadow run found {} divergences", divergences.len());
+        for divergence in divergences {
+            eprintln!("  {}", divergence);
+        }
+    }
+    for &client_id in &options.purge_clients {
+        match bank.purge_client(client_id) {
+            Ok(purged) => eprintln!(
+                "Erased the details of {} transactions of client {}",
+                purged, client_id
+            ),
+            Err(error) => eprintln!("Could not purge client {}: {}", client_id, error),
+        }
+    }
+    let report_started = Instant::now();
+    let provenance = if options.metadata_header {
+        Some(Provenance::new(
+            &bank,
+            File::open(&options.input)?,
+            summary.rows(),
+        )?)
+    } else {
+        None
+    };
+    if let Some(provenance) = &provenance {
+        provenance.write_header(io::stdout().lock())?;
+    }
+    let anonymizer = options
+        .anonymize
+        .as_ref()
+        .map(|salt| Anonymizer::new(salt.as_str(), options.amount_bucket.unwrap_or(100.0)));
+    if let Some(anonymizer) = &anonymizer {
+        write_anonymized_report(&bank.accounts(), anonymizer, io::stdout().lock())?;
+    } else if let Some(format) = &options.number_format {
+        write_localized_report(&bank.accounts(), format, io::stdout().lock())?;
+    } else if options.extended_report {
+        bank.write_extended_report(io::stdout().lock())?;
+    } else if options.output_format != ReportFormat::Csv {
+        bank.write_report_as(&options.output_format, io::stdout().lock())?;
+    } else {
+        match options.report_threads {
+            Some(threads) if threads > 1 => {
+                bank.write_report_sharded(io::stdout().lock(), threads)?
             }
-        },
-        Err(e) => eprintln!("{}", e),
+            _ => bank.print_report()?,
+        }
+    }
+    print_summary(&bank, &summary);
+    write_extra_reports(&bank, &options, provenance.as_ref(), anonymizer.as_ref())?;
+    if options.stats {
+        let stats = RunStats {
+            rows: summary.rows(),
+            parse: parse_time.get(),
+            apply: processing_time.saturating_sub(parse_time.get()),
+            report: report_started.elapsed(),
+            peak_memory_kb: peak_memory_kb(),
+        };
+        match &options.stats_output {
+            Some(path) => stats.write(File::create(path)?)?,
+            None => stats.write(io::stderr().lock())?,
+        }
     }
+    Ok(())
+}
+
+/**
+ * The `migrate` subcommand: converts a legacy file to the input format.
+ */
+fn run_migrate(options: MigrateOptions) -> Result<(), Box<dyn Error>> {
+    let mapping = read_mapping(&fs::read_to_string(&options.mapping)?)?;
+    let input = BufReader::new(File::open(&options.input)?);
+    let rows = match &options.output {
+        Some(path) => migrate(&mapping, input, File::create(path)?)?,
+        None => migrate(&mapping, input, io::stdout().lock())?,
+    };
+    eprintln!("Migrated {} rows", rows);
+    Ok(())
 }
 
-fn get_first_arg() -> Result<OsString, String> {
-    match env::args_os().nth(1) {
-        None => Err(From::from("Expected 1 argument, but got none")),
-        Some(file_path) => Ok(file_path),
+/**
+ * Human-readable notes for operators, written to stderr after the report.
+ */
+fn print_summary(bank: &RustBank, summary: &BatchSummary) {
+    eprintln!(
+        "Processed {} transactions of {} clients: {} applied, {} refused or ignored",
+        summary.rows(),
+        summary.accounts_touched(),
+        summary.applied,
+        summary.rows() - summary.applied
+    );
+    if bank.expired_authorizations() > 0 {
+        eprintln!(
+            "{} transactions were declined waiting for a second authorization",
+            bank.expired_authorizations()
+        );
+    }
+    if bank.sink_failures() > 0 {
+        eprintln!(
+            "{} account updates could not be delivered",
+            bank.sink_failures()
+        );
+    }
+    let negative = bank.negative_available_accounts();
+    if !negative.is_empty() {
+        eprintln!(
+            "Negative available ({} alerts raised by disputes):",
+            bank.negative_available_alerts()
+        );
+        for account in negative {
+            eprintln!(
+                "  client {}: available {}",
+                account.client_id, account.available
+            );
+        }
     }
 }
 
-fn parse_transactions(file_path: OsString) -> Result<Vec<Transaction>, Box<dyn Error>> {
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .trim(Trim::All)
-        .from_path(file_path)?;
+/**
+ * Starts the admin API if `--admin-addr` was given.
+ */
+#[cfg(feature = "server")]
+fn start_admin(options: &Options) -> Result<Option<SharedAdminState>, Box<dyn Error>> {
+    let Some(address) = &options.admin_addr else {
+        return Ok(None);
+    };
+    let listener = TcpListener::bind(address)?;
+    eprintln!("Admin API listening on {}", listener.local_addr()?);
+    let state = SharedAdminState::new();
+    rs_bank_assignment::admin::serve(listener, state.clone());
+    Ok(Some(state))
+}
 
-    let mut results = Vec::new();
-    for record in reader.deserialize() {
-        let transaction: Transaction = record?;
-        results.push(transaction);
+/**
+ * Processes `source` and then the `--backfill` files, handing the outcome
+ * of every transaction to `on_outcome`. Returns the number of transactions
+ * skipped by the backfill, or the error that stopped processing with the
+ * input line it was read from, if known.
+ */
+fn process_all<M: Money>(
+    bank: &RustBank<M>,
+    options: &Options,
+    source: &mut dyn TransactionSource<M>,
+    retries: Rc<Cell<u64>>,
+    mut on_outcome: impl FnMut(&ProcessedTx, TxOutcome),
+) -> Result<usize, (Box<dyn Error>, Option<u64>)> {
+    bank.process_source(source, &mut on_outcome)
+        .map_err(|error| (error, source.line()))?;
+    let mut skipped = 0;
+    for path in &options.backfill {
+        let file = File::open(path).map_err(|error| (error.into(), None))?;
+        let mut history =
+            open_source(options, file, None, retries.clone()).map_err(|error| (error, None))?;
+        skipped += bank
+            .backfill_source(history.as_mut(), &mut on_outcome)
+            .map_err(|error| (error, history.line()))?;
     }
+    Ok(skipped)
+}
 
-    Ok(results)
+/**
+ * Processes the input as `process_all` does, reading it with tokio on a
+ * single-threaded runtime.
+ */
+#[cfg(feature = "tokio")]
+fn process_async<M: Money>(
+    bank: &RustBank<M>,
+    options: &Options,
+    on_outcome: impl FnMut(&ProcessedTx, TxOutcome),
+) -> Result<usize, (Box<dyn Error>, Option<u64>)> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .map_err(|error| (error.into(), None))?;
+    runtime
+        .block_on(async {
+            let file = tokio::fs::File::open(&options.input).await?;
+            let reader = tokio::io::BufReader::new(file);
+            let stream = csv_stream(reader, type_names(options)).await?;
+            bank.process_source_stream(stream, on_outcome).await
+        })
+        .map(|()| 0)
+        .map_err(|error| (error, None))
+}
+
+/**
+ * Runs the input again through a bank with amount type `M` and the same
+ * policies as `primary`, which processed it with `outcomes`, and returns
+ * where the two runs differ. Update sinks are left to the primary run.
+ */
+fn run_shadow<M: Money>(
+    primary: &RustBank,
+    outcomes: &[(u32, TxOutcome)],
+    options: &Options,
+    expected_rows: usize,
+) -> Result<Vec<Divergence>, Box<dyn Error>> {
+    let shadow = configure_policies::<M>(options, expected_rows)?.build();
+    let retries = Rc::new(Cell::new(0));
+    let file = File::open(&options.input)?;
+    let mut source = open_input(options, file, None, retries.clone(), Rc::new(Cell::new(0)))?;
+    let mut shadow_outcomes = Vec::new();
+    process_all(
+        &shadow,
+        options,
+        source.as_mut(),
+        retries,
+        |processed, outcome| shadow_outcomes.push((processed.tx_id, outcome)),
+    )
+    .map_err(|(error, _)| error)?;
+    let mut divergences = compare_outcomes(outcomes, &shadow_outcomes);
+    divergences.extend(compare_accounts(primary, &shadow));
+    Ok(divergences)
+}
+
+fn configure_bank(options: &Options, expected_rows: usize) -> Result<BankBuilder, Box<dyn Error>> {
+    let mut builder = configure_policies(options, expected_rows)?;
+    if let Some(path) = &options.updates {
+        builder = builder.update_sink(FileSink::create(path)?);
+    }
+    if let Some(path) = &options.cdc {
+        builder = builder.update_sink(CdcSink::create(path)?);
+    }
+    if let Some(url) = &options.updates_url {
+        builder = builder.update_sink(HttpSink::new(url, UPDATE_TIMEOUT)?);
+    }
+    Ok(builder)
+}
+
+fn configure_policies<M: Money>(
+    options: &Options,
+    expected_rows: usize,
+) -> Result<BankBuilder<M>, Box<dyn Error>> {
+    let mut builder = BankBuilder::default().expected_transactions(expected_rows);
+    if options.compliance_report.is_some() {
+        let mut aml = AmlConfig::default();
+        if let Some(threshold) = options.aml_threshold {
+            aml.threshold = amount(threshold)?;
+        }
+        builder = builder.aml(aml);
+    }
+    if let Some(cap) = options.unverified_balance_cap {
+        builder = builder.unverified_balance_cap(amount(cap)?);
+    }
+    if let Some(path) = &options.denylist {
+        builder = builder.denylist(read_denylist(BufReader::new(File::open(path)?))?);
+    }
+    if let Some(days) = options.auto_unlock_days {
+        builder = builder.auto_unlock_after(Duration::from_secs(days * 24 * 60 * 60));
+    }
+    if let Some(path) = &options.rules {
+        builder = builder.rules(read_rules(&fs::read_to_string(path)?)?);
+    }
+    if let Some(path) = &options.overrides {
+        for client_override in read_overrides(File::open(path)?)? {
+            builder = builder.client_override(client_override);
+        }
+    }
+    if let Some(path) = &options.mandates {
+        for mandate in read_mandates(File::open(path)?)? {
+            builder = builder.mandate(mandate);
+        }
+    }
+    if let Some(threshold) = options.dual_auth_threshold {
+        builder = builder.dual_authorization(amount(threshold)?);
+    }
+    if let Some(secs) = options.dual_auth_expiry {
+        builder = builder.dual_authorization_expiry(Duration::from_secs(secs));
+    }
+    if let Some(days) = options.debit_return_days {
+        builder = builder.debit_return_window(Duration::from_secs(days * 24 * 60 * 60));
+    }
+    if let Some(path) = &options.joint_accounts {
+        for holder in read_joint_holders(File::open(path)?)? {
+            builder = builder.joint_account(holder.account_id, [holder.client_id]);
+        }
+    }
+    Ok(builder)
+}
+
+/**
+ * Converts an amount given on the command line to `M`.
+ */
+fn amount<M: Money>(value: Decimal) -> Result<M, Box<dyn Error>> {
+    value
+        .to_string()
+        .parse()
+        .map_err(|_| format!("Amount {} is out of range", value).into())
+}
+
+fn write_extra_reports(
+    bank: &RustBank,
+    options: &Options,
+    provenance: Option<&Provenance>,
+    anonymizer: Option<&Anonymizer>,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = &options.compliance_report {
+        let file = create_export(path, provenance)?;
+        match anonymizer {
+            Some(anonymizer) => {
+                write_anonymized_compliance_report(&bank.compliance_entries(), anonymizer, file)?
+            }
+            None => write_compliance_report(&bank.compliance_entries(), file)?,
+        }
+    }
+    if let Some(path) = &options.review_queue {
+        write_review_queue(&bank.review_queue(), create_export(path, provenance)?)?;
+    }
+    if let Some(path) = &options.regulatory_export {
+        let layout = match &options.regulatory_layout {
+            Some(layout) => read_layout(&fs::read_to_string(layout)?)?,
+            None => FixedWidthLayout::default(),
+        };
+        write_fixed_width_report(&bank.accounts(), &layout, File::create(path)?)?;
+    }
+    if let Some(path) = &options.liquidity_report {
+        write_liquidity_report(
+            &bank.liquidity_report()?,
+            BufWriter::new(File::create(path)?),
+        )?;
+    }
+    if let Some(path) = &options.pending {
+        write_pending_transactions(&bank.pending_transactions(), File::create(path)?)?;
+    }
+    if let Some(dir) = &options.output_dir {
+        fs::create_dir_all(dir)?;
+        for statement in bank.client_statements() {
+            let path = dir.join(format!("client_{}.json", statement.account.client_id));
+            write_client_statement(&statement, BufWriter::new(File::create(path)?))?;
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Creates an export file, starting with the provenance header if asked for.
+ */
+fn create_export(path: &Path, provenance: Option<&Provenance>) -> io::Result<File> {
+    let mut file = File::create(path)?;
+    if let Some(provenance) = provenance {
+        provenance.write_header(&mut file)?;
+    }
+    Ok(file)
+}
+
+/**
+ * Opens the input with `open_source`, merged with the `--merge` files and
+ * reordered by `--reorder-window` if asked for, counting late transactions
+ * in `late`.
+ */
+fn open_input<'a, M: Money>(
+    options: &Options,
+    file: File,
+    map: Option<&'a [u8]>,
+    retries: Rc<Cell<u64>>,
+    late: Rc<Cell<u64>>,
+) -> Result<Box<dyn TransactionSource<M> + 'a>, Box<dyn Error>> {
+    let mut source = open_source(options, file, map, retries.clone())?;
+    if !options.merge.is_empty() {
+        let mut sources = vec![source];
+        for path in &options.merge {
+            sources.push(open_source(
+                options,
+                File::open(path)?,
+                None,
+                retries.clone(),
+            )?);
+        }
+        source = Box::new(MergedSource::new(sources));
+    }
+    if let Some(window) = options.reorder_window {
+        source = Box::new(ReorderingSource::new(
+            source,
+            window,
+            options.late_policy,
+            late,
+        ));
+    }
+    Ok(source)
+}
+
+/**
+ * Reads from `map`, the memory-mapped input, when given, and through a
+ * buffered `file` otherwise, retrying transient read errors of the file and
+ * counting them in `retries`.
+ */
+fn open_source<'a, M: Money>(
+    options: &Options,
+    file: File,
+    map: Option<&'a [u8]>,
+    retries: Rc<Cell<u64>>,
+) -> Result<Box<dyn TransactionSource<M> + 'a>, Box<dyn Error>> {
+    let input: Box<dyn BufRead + 'a> = match map {
+        Some(bytes) => Box::new(bytes),
+        None => {
+            let mut policy = RetryPolicy::default();
+            if let Some(max_retries) = options.retries {
+                policy.max_retries = max_retries;
+            }
+            if let Some(backoff_ms) = options.retry_backoff_ms {
+                policy.initial_backoff = Duration::from_millis(backoff_ms);
+            }
+            Box::new(BufReader::new(RetryingReader::new(file, policy, retries)))
+        }
+    };
+    match options.input_format {
+        InputFormat::Csv => {}
+        InputFormat::Json => {
+            return Ok(Box::new(JsonArraySource::new(input, type_names(options))?))
+        }
+        InputFormat::JsonLines => {
+            return Ok(Box::new(JsonLinesSource::new(input, type_names(options))))
+        }
+    }
+    match map {
+        Some(bytes) if options.fast_csv => fast_csv_source(bytes, type_names(options)),
+        _ => Ok(Box::new(CsvSource::new(input, type_names(options))?)),
+    }
+}
+
+fn type_names(options: &Options) -> TypeNames {
+    let mut type_names = if options.tolerant_types {
+        TypeNames::tolerant()
+    } else {
+        TypeNames::strict()
+    };
+    for (name, tx_type) in &options.type_aliases {
+        type_names = type_names.alias(name, *tx_type);
+    }
+    type_names
 }
@@ -0,0 +1,74 @@
+use crate::money::Money;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::error::Error;
+use std::io;
+
+/**
+ * A client's authorization for a creditor to pull funds from their account
+ * with `direct_debit` transactions referencing the mandate.
+ */
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Mandate<M = Decimal> {
+    /// Reference quoted by the direct debits, unique across clients.
+    #[serde(rename = "mandate")]
+    pub id: String,
+    /// The client whose account is debited.
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    /// Who may pull under the mandate, as named by the upstream system.
+    pub creditor: String,
+    /// Largest single pull allowed, if limited.
+    #[serde(default)]
+    pub max_amount: Option<M>,
+}
+
+impl<M: Money> Mandate<M> {
+    /**
+     * Whether a pull of `amount` from `client_id` is covered.
+     */
+    pub fn authorizes(&self, client_id: u16, amount: M) -> bool {
+        self.client_id == client_id && self.max_amount.is_none_or(|max| amount <= max)
+    }
+}
+
+/**
+ * Reads mandates from CSV with `mandate`, `client` and `creditor` columns
+ * and an optional `max_amount`.
+ */
+pub fn read_mandates<M: Money, R: io::Read>(reader: R) -> Result<Vec<Mandate<M>>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+
+    let mut results = Vec::new();
+    for record in reader.deserialize() {
+        let mandate: Mandate<M> = record?;
+        results.push(mandate);
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_mandates() {
+        // GIVEN
+        let input =
+            "mandate, client, creditor, max_amount\nMD-1, 1, utility, 50.0\nMD-2, 2, gym,\n";
+
+        // WHEN
+        let mandates: Vec<Mandate<f32>> = read_mandates(input.as_bytes()).unwrap();
+
+        // THEN
+        assert_eq!(mandates.len(), 2);
+        assert!(mandates[0].authorizes(1, 50.0));
+        assert!(!mandates[0].authorizes(1, 50.5));
+        assert!(!mandates[0].authorizes(2, 1.0));
+        assert_eq!(mandates[1].creditor, "gym");
+        assert!(mandates[1].authorizes(2, 1000.0));
+    }
+}
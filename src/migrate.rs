@@ -0,0 +1,352 @@
+//! Converts legacy and partner CSV layouts into the input schema, so old
+//! archives can be replayed through the engine.
+
+use crate::bank::TransactionType;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+
+/**
+ * A column of the legacy file, by header name or by position (from 1).
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ColumnRef {
+    Position(usize),
+    Name(String),
+}
+
+/**
+ * Where each input column is found in the legacy file. A file without a
+ * type column must have `credit` and `debit` columns instead of `amount`:
+ * rows with a credit become deposits and rows with a debit withdrawals.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Columns {
+    #[serde(rename = "type")]
+    pub tx_type: Option<ColumnRef>,
+    pub client: ColumnRef,
+    pub tx: ColumnRef,
+    pub amount: Option<ColumnRef>,
+    pub credit: Option<ColumnRef>,
+    pub debit: Option<ColumnRef>,
+    pub timestamp: Option<ColumnRef>,
+}
+
+/**
+ * A declarative description of a legacy layout, read from TOML.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Mapping {
+    /// Whether the first row holds column names. Without it columns can
+    /// only be given by position.
+    #[serde(default = "default_headers")]
+    pub headers: bool,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    /// Amounts are whole numbers of this many decimal places, e.g. `1250`
+    /// for `12.50` with 2.
+    #[serde(default)]
+    pub implied_decimals: u32,
+    /// Amounts use a comma as decimal separator, e.g. `12,50`.
+    #[serde(default)]
+    pub decimal_comma: bool,
+    pub columns: Columns,
+    /// Legacy type names and the input types they stand for. Unlisted
+    /// names must already be input types.
+    #[serde(default)]
+    pub types: HashMap<String, TransactionType>,
+}
+
+fn default_headers() -> bool {
+    true
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+pub fn read_mapping(input: &str) -> Result<Mapping, Box<dyn Error>> {
+    let mapping: Mapping = toml::from_str(input)?;
+    let columns = &mapping.columns;
+    let split = columns.credit.is_some() || columns.debit.is_some();
+    if split && (columns.credit.is_none() || columns.debit.is_none()) {
+        return Err("credit and debit columns must be mapped together".into());
+    }
+    if split && columns.amount.is_some() {
+        return Err("amount cannot be mapped together with credit and debit".into());
+    }
+    if columns.tx_type.is_none() && !split {
+        return Err("a mapping without a type column needs credit and debit columns".into());
+    }
+    if !mapping.delimiter.is_ascii() {
+        return Err(format!("Delimiter {:?} is not ASCII", mapping.delimiter).into());
+    }
+    Ok(mapping)
+}
+
+/**
+ * Where the mapped columns are in each record, from 0.
+ */
+struct Positions {
+    tx_type: Option<usize>,
+    client: usize,
+    tx: usize,
+    amount: Option<usize>,
+    split: Option<(usize, usize)>,
+    timestamp: Option<usize>,
+}
+
+impl Positions {
+    fn resolve(columns: &Columns, headers: Option<&csv::StringRecord>) -> Result<Self, String> {
+        let find = |column: &ColumnRef| match (column, headers) {
+            (ColumnRef::Position(0), _) => Err(String::from("Column positions start at 1")),
+            (ColumnRef::Position(position), _) => Ok(position - 1),
+            (ColumnRef::Name(name), Some(headers)) => headers
+                .iter()
+                .position(|header| header == name)
+                .ok_or_else(|| format!("No column named {:?}", name)),
+            (ColumnRef::Name(name), None) => Err(format!(
+                "Column {:?} is named, but the file has no headers",
+                name
+            )),
+        };
+        let find_opt = |column: &Option<ColumnRef>| column.as_ref().map(find).transpose();
+        let split = match (&columns.credit, &columns.debit) {
+            (Some(credit), Some(debit)) => Some((find(credit)?, find(debit)?)),
+            _ => None,
+        };
+        Ok(Positions {
+            tx_type: find_opt(&columns.tx_type)?,
+            client: find(&columns.client)?,
+            tx: find(&columns.tx)?,
+            amount: find_opt(&columns.amount)?,
+            split,
+            timestamp: find_opt(&columns.timestamp)?,
+        })
+    }
+}
+
+/**
+ * Rewrites the legacy CSV read from `reader` as input CSV with `type`,
+ * `client`, `tx` and `amount` columns, and `timestamp` when mapped.
+ * Returns the number of rows written; a row that cannot be converted is
+ * an error naming its line.
+ */
+pub fn migrate<R: io::Read, W: io::Write>(
+    mapping: &Mapping,
+    reader: R,
+    writer: W,
+) -> Result<usize, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(mapping.headers)
+        .delimiter(mapping.delimiter as u8)
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(reader);
+    let headers = if mapping.headers {
+        Some(reader.headers()?.clone())
+    } else {
+        None
+    };
+    let positions = Positions::resolve(&mapping.columns, headers.as_ref())?;
+    let mut writer = csv::Writer::from_writer(writer);
+    let mut header = vec!["type", "client", "tx", "amount"];
+    if positions.timestamp.is_some() {
+        header.push("timestamp");
+    }
+    writer.write_record(&header)?;
+
+    let mut rows = 0;
+    let mut record = csv::StringRecord::new();
+    while reader.read_record(&mut record)? {
+        let line = record.position().map_or(0, |position| position.line());
+        let row = convert(mapping, &positions, &record)
+            .map_err(|error| format!("Line {}: {}", line, error))?;
+        writer.write_record(&row)?;
+        rows += 1;
+    }
+    writer.flush()?;
+    Ok(rows)
+}
+
+fn convert(
+    mapping: &Mapping,
+    positions: &Positions,
+    record: &csv::StringRecord,
+) -> Result<Vec<String>, String> {
+    let field = |position: usize| {
+        record
+            .get(position)
+            .ok_or_else(|| format!("missing column {}", position + 1))
+    };
+    // Disputes and the like have neither a credit nor a debit, and take
+    // their type from the type column.
+    let (inferred, amount) = match positions.split {
+        Some((credit, debit)) => match (field(credit)?, field(debit)?) {
+            ("", "") => (None, ""),
+            (credit, "") => (Some(TransactionType::Deposit), credit),
+            ("", debit) => (Some(TransactionType::Withdrawal), debit),
+            _ => return Err(String::from("expected either a credit or a debit")),
+        },
+        None => (None, positions.amount.map(field).transpose()?.unwrap_or("")),
+    };
+    let tx_type = match (positions.tx_type, inferred) {
+        (Some(position), _) => {
+            let name = field(position)?;
+            match mapping.types.get(name) {
+                Some(tx_type) => *tx_type,
+                None => name.parse()?,
+            }
+        }
+        (None, Some(tx_type)) => tx_type,
+        (None, None) => return Err(String::from("expected either a credit or a debit")),
+    };
+    let client: u16 = parse(field(positions.client)?, "client id")?;
+    let tx: u32 = parse(field(positions.tx)?, "transaction id")?;
+    let mut row = vec![
+        tx_type.as_str().to_string(),
+        client.to_string(),
+        tx.to_string(),
+        convert_amount(mapping, amount)?,
+    ];
+    if let Some(position) = positions.timestamp {
+        let timestamp = field(position)?;
+        if !timestamp.is_empty() {
+            parse::<u64>(timestamp, "timestamp")?;
+        }
+        row.push(timestamp.to_string());
+    }
+    Ok(row)
+}
+
+fn parse<T: std::str::FromStr>(value: &str, what: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid {} {:?}", what, value))
+}
+
+/**
+ * The amount as a plain decimal number, or empty when there is none.
+ */
+fn convert_amount(mapping: &Mapping, amount: &str) -> Result<String, String> {
+    if amount.is_empty() {
+        return Ok(String::new());
+    }
+    let text = if mapping.decimal_comma {
+        amount.replace(',', ".")
+    } else {
+        amount.to_string()
+    };
+    let mut value: Decimal = parse(&text, "amount")?;
+    if mapping.implied_decimals > 0 {
+        if value.scale() != 0 {
+            return Err(format!(
+                "expected a whole number of minor units, got {:?}",
+                amount
+            ));
+        }
+        value
+            .set_scale(mapping.implied_decimals)
+            .map_err(|_| format!("invalid amount {:?}", amount))?;
+    }
+    Ok(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(mapping: &str, input: &str) -> Result<String, Box<dyn Error>> {
+        let mut output = Vec::new();
+        migrate(&read_mapping(mapping)?, input.as_bytes(), &mut output)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_migrate_renamed_columns_and_types() {
+        // GIVEN
+        let mapping = r#"
+            delimiter = ";"
+            decimal_comma = true
+
+            [columns]
+            type = "Kind"
+            client = "Customer"
+            tx = "Ref"
+            amount = "Amount"
+
+            [types]
+            DEP = "deposit"
+            WDL = "withdrawal"
+            DSP = "dispute"
+        "#;
+        let input = "Ref;Customer;Kind;Amount\n1;7;DEP;12,50\n2;7;WDL;2,25\n1;7;DSP;\n";
+
+        // WHEN
+        let output = run(mapping, input);
+
+        // THEN
+        assert_eq!(
+            output.unwrap(),
+            "type,client,tx,amount\n\
+             deposit,7,1,12.50\n\
+             withdrawal,7,2,2.25\n\
+             dispute,7,1,\n"
+        );
+    }
+
+    #[test]
+    fn test_migrate_headerless_credit_debit_columns() {
+        // GIVEN
+        let mapping = r#"
+            headers = false
+            implied_decimals = 2
+
+            [columns]
+            client = 1
+            tx = 2
+            credit = 3
+            debit = 4
+            timestamp = 5
+        "#;
+        let input = "3,10,1250,,1700000000\n3,11,,99,1700000060\n";
+
+        // WHEN
+        let output = run(mapping, input);
+
+        // THEN
+        assert_eq!(
+            output.unwrap(),
+            "type,client,tx,amount,timestamp\n\
+             deposit,3,10,12.50,1700000000\n\
+             withdrawal,3,11,0.99,1700000060\n"
+        );
+    }
+
+    #[test]
+    fn test_migrate_errors_name_the_line() {
+        // GIVEN
+        let mapping = "[columns]\ntype = 1\nclient = 2\ntx = 3\namount = 4\n";
+
+        // WHEN
+        let error = run(
+            mapping,
+            "kind,client,tx,amount\ndeposit,1,1,1\nrefund,1,2,1\n",
+        );
+
+        // THEN
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "Line 3: Unknown transaction type \"refund\""
+        );
+        assert!(read_mapping("[columns]\nclient = 1\ntx = 2\namount = 3\n").is_err());
+        assert!(run(
+            "headers = false\n[columns]\ntype = 1\nclient = \"c\"\ntx = 2\n",
+            ""
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,320 @@
+use rust_decimal::Decimal;
+use serde::de::{self, DeserializeOwned, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/**
+ * An amount of money. The engine only adds, subtracts and compares amounts,
+ * so any type with checked arithmetic works; `Decimal`, the default, and
+ * `MinorUnits` are exact, `f32` rounds.
+ */
+pub trait Money:
+    Copy
+    + Default
+    + PartialEq
+    + PartialOrd
+    + fmt::Debug
+    + fmt::Display
+    + FromStr
+    + Serialize
+    + DeserializeOwned
+    + Send
+    + Sync
+    + 'static
+{
+    fn zero() -> Self;
+
+    /// A whole amount, e.g. for default thresholds.
+    fn from_whole(units: i32) -> Self;
+
+    /// `None` if the result is not representable.
+    fn checked_add(self, other: Self) -> Option<Self>;
+
+    /// `None` if the result is not representable.
+    fn checked_sub(self, other: Self) -> Option<Self>;
+
+    /// Approximate value, for ratios and tolerances only.
+    fn to_f64(self) -> f64;
+
+    /// Exactly `OUTPUT_DECIMALS` decimal places, rounded.
+    fn to_fixed(self) -> String {
+        format!("{:.*}", OUTPUT_DECIMALS, self)
+    }
+
+    fn abs_diff(self, other: Self) -> Option<Self> {
+        if self >= other {
+            self.checked_sub(other)
+        } else {
+            other.checked_sub(self)
+        }
+    }
+}
+
+impl Money for f32 {
+    fn zero() -> Self {
+        0.0
+    }
+
+    fn from_whole(units: i32) -> Self {
+        units as f32
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        Some(self + other).filter(|sum| sum.is_finite())
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        Some(self - other).filter(|difference| difference.is_finite())
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+}
+
+impl Money for Decimal {
+    fn zero() -> Self {
+        Decimal::ZERO
+    }
+
+    fn from_whole(units: i32) -> Self {
+        Decimal::from(units)
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        Decimal::checked_add(self, other)
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        Decimal::checked_sub(self, other)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.try_into().unwrap_or(f64::NAN)
+    }
+
+    fn to_fixed(self) -> String {
+        // Decimal's formatting truncates to the requested precision.
+        format!(
+            "{:.*}",
+            OUTPUT_DECIMALS,
+            self.round_dp(OUTPUT_DECIMALS as u32)
+        )
+    }
+}
+
+/// Decimal places written for amounts in reports.
+const OUTPUT_DECIMALS: usize = 4;
+
+/**
+ * Serializes an amount as a string with exactly four decimal places, e.g.
+ * `30.0000`, rounding away float noise. A value that rounds to zero is
+ * written without a minus sign.
+ */
+pub fn serialize_fixed<M: Money, S: Serializer>(
+    amount: &M,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&fixed_text(*amount))
+}
+
+/**
+ * The text `serialize_fixed` writes for `amount`.
+ */
+pub fn fixed_text<M: Money>(amount: M) -> String {
+    let formatted = amount.to_fixed();
+    let unsigned = formatted.strip_prefix('-').unwrap_or(&formatted);
+    if unsigned.bytes().all(|b| b == b'0' || b == b'.') {
+        unsigned.to_string()
+    } else {
+        formatted
+    }
+}
+
+/**
+ * `serialize_fixed` for an amount that may be absent, written as an empty
+ * field then.
+ */
+pub fn serialize_fixed_opt<M: Money, S: Serializer>(
+    amount: &Option<M>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match amount {
+        Some(amount) => serialize_fixed(amount, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Fractional digits kept by `MinorUnits`.
+const MINOR_DIGITS: u32 = 4;
+const MINOR_PER_UNIT: i64 = 10_i64.pow(MINOR_DIGITS);
+
+/**
+ * An amount counted in ten-thousandths, read and written as a decimal
+ * number such as `2.5` or `-0.0001`. Input with more than four fractional
+ * digits is refused rather than rounded.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MinorUnits(pub i64);
+
+impl Money for MinorUnits {
+    fn zero() -> Self {
+        MinorUnits(0)
+    }
+
+    fn from_whole(units: i32) -> Self {
+        MinorUnits(i64::from(units) * MINOR_PER_UNIT)
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(MinorUnits)
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(MinorUnits)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.0 as f64 / MINOR_PER_UNIT as f64
+    }
+}
+
+impl fmt::Display for MinorUnits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let per_unit = MINOR_PER_UNIT.unsigned_abs();
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            units / per_unit,
+            units % per_unit,
+            width = MINOR_DIGITS as usize
+        )
+    }
+}
+
+impl FromStr for MinorUnits {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid amount {:?}", value);
+        let (negative, digits) = match value.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, value),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() || !all_digits(whole) || !all_digits(fraction) {
+            return Err(invalid());
+        }
+        if fraction.len() > MINOR_DIGITS as usize {
+            return Err(invalid());
+        }
+        let scale = 10_i64.pow(MINOR_DIGITS - fraction.len() as u32);
+        let fraction: i64 = if fraction.is_empty() {
+            0
+        } else {
+            fraction.parse().map_err(|_| invalid())?
+        };
+        let magnitude = whole
+            .parse::<i64>()
+            .ok()
+            .and_then(|whole| whole.checked_mul(MINOR_PER_UNIT))
+            .and_then(|whole| whole.checked_add(fraction * scale))
+            .ok_or_else(invalid)?;
+        Ok(MinorUnits(if negative { -magnitude } else { magnitude }))
+    }
+}
+
+impl Serialize for MinorUnits {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MinorUnits {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(MinorUnitsVisitor)
+    }
+}
+
+struct MinorUnitsVisitor;
+
+impl Visitor<'_> for MinorUnitsVisitor {
+    type Value = MinorUnits;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an amount with at most {} decimals", MINOR_DIGITS)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<MinorUnits, E> {
+        value.parse().map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<MinorUnits, E> {
+        value
+            .checked_mul(MINOR_PER_UNIT)
+            .map(MinorUnits)
+            .ok_or_else(|| E::custom("amount out of range"))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<MinorUnits, E> {
+        i64::try_from(value)
+            .map_err(|_| E::custom("amount out of range"))
+            .and_then(|value| self.visit_i64(value))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<MinorUnits, E> {
+        // Formatting with the shortest round-trip representation keeps e.g.
+        // 0.1 exact instead of going through binary rounding.
+        self.visit_str(&value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minor_units_parse_and_display() {
+        assert_eq!("2.5".parse(), Ok(MinorUnits(25_000)));
+        assert_eq!("-0.0001".parse(), Ok(MinorUnits(-1)));
+        assert_eq!("7".parse(), Ok(MinorUnits(70_000)));
+        assert!("1.00001".parse::<MinorUnits>().is_err());
+        assert!("1e3".parse::<MinorUnits>().is_err());
+        assert!("".parse::<MinorUnits>().is_err());
+        assert_eq!(MinorUnits(25_000).to_string(), "2.5000");
+        assert_eq!(MinorUnits(-1).to_string(), "-0.0001");
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        assert_eq!(MinorUnits(i64::MAX).checked_add(MinorUnits(1)), None);
+        assert_eq!(f32::MAX.checked_add(f32::MAX), None);
+        assert_eq!(
+            Decimal::new(15, 1).checked_sub(Decimal::new(5, 1)),
+            Some(Decimal::ONE)
+        );
+        assert_eq!(MinorUnits(3).abs_diff(MinorUnits(5)), Some(MinorUnits(2)));
+    }
+
+    #[test]
+    fn test_serialize_fixed() {
+        #[derive(Serialize)]
+        #[serde(bound(serialize = "M: Money"))]
+        struct Row<M>(#[serde(serialize_with = "serialize_fixed")] M);
+        fn json<M: Money>(value: Row<M>) -> String {
+            serde_json::to_string(&value).unwrap()
+        }
+
+        assert_eq!(json(Row(30.0_f32)), r#""30.0000""#);
+        assert_eq!(json(Row(0.1_f32 + 0.2_f32)), r#""0.3000""#);
+        assert_eq!(json(Row(-0.00001_f32)), r#""0.0000""#);
+        assert_eq!(json(Row(-2.5_f32)), r#""-2.5000""#);
+        assert_eq!(json(Row(Decimal::new(123456, 5))), r#""1.2346""#);
+        assert_eq!(json(Row(MinorUnits(25_000))), r#""2.5000""#);
+    }
+}
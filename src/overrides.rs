@@ -0,0 +1,176 @@
+use crate::money::Money;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer};
+use std::error::Error;
+use std::io;
+use std::time::Duration;
+
+/**
+ * Know-your-customer status of a client. Clients without a status are
+ * treated as verified.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KycStatus {
+    Verified,
+    Unverified,
+}
+
+/**
+ * Per-client policy settings that take precedence over the bank-wide ones.
+ * Settings left as `None` fall back to the bank-wide value.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Deserialize)]
+pub struct ClientOverride<M = Decimal> {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    #[serde(default)]
+    pub daily_withdrawal_limit: Option<M>,
+    #[serde(default)]
+    pub overdraft: Option<M>,
+    /// Read from the file in seconds.
+    #[serde(default, deserialize_with = "deserialize_seconds")]
+    pub dispute_window: Option<Duration>,
+    #[serde(default)]
+    pub kyc: Option<KycStatus>,
+    /// Makes the client's account a credit account that can borrow up to
+    /// this much.
+    #[serde(default)]
+    pub credit_limit: Option<M>,
+    /// Amount a credit account owes when it is opened.
+    #[serde(default)]
+    pub principal: Option<M>,
+}
+
+impl<M: Money> ClientOverride<M> {
+    pub fn new(client_id: u16) -> Self {
+        ClientOverride {
+            client_id,
+            ..Default::default()
+        }
+    }
+}
+
+fn deserialize_seconds<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_secs))
+}
+
+/**
+ * Reads overrides from CSV with a `client` column and any of the
+ * `daily_withdrawal_limit`, `overdraft`, `dispute_window`, `kyc`,
+ * `credit_limit` and `principal` columns.
+ * Empty cells keep the bank-wide setting.
+ */
+pub fn read_overrides<M: Money, R: io::Read>(
+    reader: R,
+) -> Result<Vec<ClientOverride<M>>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+
+    let mut results = Vec::new();
+    for record in reader.deserialize() {
+        let client_override: ClientOverride<M> = record?;
+        results.push(client_override);
+    }
+    Ok(results)
+}
+
+/**
+ * A client allowed to transact on another client's account.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub struct JointHolder {
+    /// The account, identified by its owner's client id.
+    #[serde(rename = "account")]
+    pub account_id: u16,
+    #[serde(rename = "client")]
+    pub client_id: u16,
+}
+
+/**
+ * Reads joint account holders from CSV with `account` and `client` columns,
+ * one row per authorized client. A client can hold a single account.
+ */
+pub fn read_joint_holders<R: io::Read>(reader: R) -> Result<Vec<JointHolder>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+
+    let mut results: Vec<JointHolder> = Vec::new();
+    for record in reader.deserialize() {
+        let holder: JointHolder = record?;
+        if let Some(other) = results.iter().find(|h| h.client_id == holder.client_id) {
+            if other.account_id != holder.account_id {
+                return Err(format!(
+                    "Client {} is authorized on accounts {} and {}",
+                    holder.client_id, other.account_id, holder.account_id
+                )
+                .into());
+            }
+        }
+        results.push(holder);
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_overrides_partial_columns() {
+        // GIVEN
+        let input = "client, overdraft, dispute_window, kyc\n1, 50.0,,\n2, , 3600, unverified\n";
+
+        // WHEN
+        let overrides: Vec<ClientOverride<f32>> = read_overrides(input.as_bytes()).unwrap();
+
+        // THEN
+        assert_eq!(
+            overrides,
+            vec![
+                ClientOverride {
+                    overdraft: Some(50.0),
+                    ..ClientOverride::new(1)
+                },
+                ClientOverride {
+                    dispute_window: Some(Duration::from_secs(3600)),
+                    kyc: Some(KycStatus::Unverified),
+                    ..ClientOverride::new(2)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_joint_holders() {
+        // GIVEN
+        let input = "account, client\n1, 2\n1, 3\n";
+        let conflicting = "account,client\n1,2\n4,2\n";
+
+        // WHEN
+        let holders = read_joint_holders(input.as_bytes()).unwrap();
+
+        // THEN
+        assert_eq!(
+            holders,
+            vec![
+                JointHolder {
+                    account_id: 1,
+                    client_id: 2
+                },
+                JointHolder {
+                    account_id: 1,
+                    client_id: 3
+                },
+            ]
+        );
+        assert!(read_joint_holders(conflicting.as_bytes()).is_err());
+    }
+}
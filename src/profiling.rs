@@ -0,0 +1,73 @@
+//! CPU profiling of the processing loop; built with the `profiling`
+//! feature.
+use pprof::{ProfilerGuard, ProfilerGuardBuilder};
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Samples per second; a prime, so sampling doesn't fall into step with
+/// periodic work.
+const SAMPLE_FREQUENCY: i32 = 997;
+
+/**
+ * Samples the stacks of this process from `start` until `finish`, which
+ * writes them to a flamegraph SVG.
+ */
+pub struct Profiler {
+    guard: ProfilerGuard<'static>,
+    output: PathBuf,
+}
+
+impl Profiler {
+    pub fn start(output: &Path) -> Result<Self, Box<dyn Error>> {
+        let guard = ProfilerGuardBuilder::default()
+            .frequency(SAMPLE_FREQUENCY)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()?;
+        Ok(Profiler {
+            guard,
+            output: output.to_path_buf(),
+        })
+    }
+
+    /**
+     * Writes the flamegraph. Fails without writing it if no samples were
+     * taken, as happens when processing takes only a few milliseconds.
+     */
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        let report = self.guard.report().build()?;
+        if report.data.is_empty() {
+            return Err("no samples were taken, the run was too short".into());
+        }
+        report.flamegraph(File::create(&self.output)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::hint::black_box;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_profile_writes_flamegraph() {
+        // GIVEN
+        let output = std::env::temp_dir().join(format!("profile-{}.svg", std::process::id()));
+        let profiler = Profiler::start(&output).unwrap();
+
+        // WHEN
+        let started = Instant::now();
+        let mut sum = 0u64;
+        while started.elapsed() < Duration::from_millis(200) {
+            sum = black_box(sum.wrapping_add(1));
+        }
+        profiler.finish().unwrap();
+
+        // THEN
+        let svg = fs::read_to_string(&output).unwrap();
+        fs::remove_file(&output).unwrap();
+        assert!(svg.contains("<svg"));
+    }
+}
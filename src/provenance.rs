@@ -0,0 +1,90 @@
+use crate::bank::Bank;
+use crate::money::Money;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::io;
+
+/**
+ * Where a report came from, written as a leading `#` comment so downstream
+ * consumers can check they received the output of the run they expect.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// Package name and version, e.g. `rs-bank-assignment/0.1.0`.
+    pub engine_version: &'static str,
+    /// SHA-256 of the input file, hex encoded.
+    pub input_sha256: String,
+    /// Transactions read from the input, applied or not.
+    pub rows: usize,
+    pub accounts: usize,
+    /// SHA-256 of the plain account report, hex encoded, which identifies
+    /// the final balances whatever format the file itself is in.
+    pub state_sha256: String,
+}
+
+impl Provenance {
+    /**
+     * Gathers the metadata after `bank` processed `rows` transactions read
+     * from `input`.
+     */
+    pub fn new<M: Money, R: io::Read>(
+        bank: &Bank<M>,
+        mut input: R,
+        rows: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut input_hash = Sha256::new();
+        io::copy(&mut input, &mut input_hash)?;
+        let mut state_hash = Sha256::new();
+        bank.write_report(&mut state_hash)?;
+        Ok(Provenance {
+            engine_version: concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")),
+            input_sha256: format!("{:x}", input_hash.finalize()),
+            rows,
+            accounts: bank.accounts().len(),
+            state_sha256: format!("{:x}", state_hash.finalize()),
+        })
+    }
+
+    pub fn write_header<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "# engine={} input_sha256={} rows={} accounts={} state_sha256={}",
+            self.engine_version, self.input_sha256, self.rows, self.accounts, self.state_sha256
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::Transaction;
+
+    #[test]
+    fn test_provenance_header() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 2.0),
+            Transaction::deposit(2, 2, 1.0),
+        ])
+        .unwrap();
+        let mut header = Vec::new();
+
+        // WHEN
+        let provenance = Provenance::new(&bank, "input".as_bytes(), 2).unwrap();
+        provenance.write_header(&mut header).unwrap();
+
+        // THEN
+        let mut report = Vec::new();
+        bank.write_report(&mut report).unwrap();
+        assert_eq!(
+            String::from_utf8(header).unwrap(),
+            format!(
+                "# engine={} input_sha256={} rows=2 accounts=2 state_sha256={:x}\n",
+                concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")),
+                "c96c6d5be8d08a12e7b5cdc1b207fa6b2430974c86803d8891675e76fd992c20",
+                Sha256::digest(&report)
+            )
+        );
+    }
+}
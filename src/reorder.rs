@@ -0,0 +1,230 @@
+use crate::bank::Transaction;
+use crate::input::TransactionSource;
+use crate::money::Money;
+use rust_decimal::Decimal;
+use std::cell::Cell;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::error::Error;
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// Tag added to transactions let through by `LatePolicy::Flag`.
+pub const LATE_TAG: &str = "late";
+
+/**
+ * What happens to a transaction that arrives after a later transaction of
+ * the same client was already released.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum LatePolicy {
+    /// Drop it (default).
+    #[default]
+    Reject,
+    /// Let it through out of order, tagged with `LATE_TAG`.
+    Flag,
+}
+
+impl FromStr for LatePolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "reject" => Ok(LatePolicy::Reject),
+            "flag" => Ok(LatePolicy::Flag),
+            _ => Err(format!("Unknown late policy {:?}", name)),
+        }
+    }
+}
+
+/// A buffered transaction, ordered by event time and then arrival.
+struct Buffered<M> {
+    timestamp: u64,
+    arrival: u64,
+    tx: Transaction<M>,
+    line: Option<u64>,
+}
+
+impl<M> PartialEq for Buffered<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<M> Eq for Buffered<M> {}
+
+impl<M> PartialOrd for Buffered<M> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<M> Ord for Buffered<M> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.timestamp, self.arrival).cmp(&(other.timestamp, other.arrival))
+    }
+}
+
+/**
+ * Holds transactions back for up to `lateness` seconds of event time and
+ * releases them in timestamp order, so events that arrive slightly out of
+ * order are applied in the order they happened. A transaction is released
+ * once a transaction `lateness` seconds newer has been read, or at the end
+ * of the input. Transactions without a timestamp count as happening at the
+ * newest timestamp read so far. Read errors are passed on immediately.
+ */
+pub struct ReorderingSource<'a, M: Money = Decimal> {
+    inner: Box<dyn TransactionSource<M> + 'a>,
+    lateness: u64,
+    policy: LatePolicy,
+    buffer: BinaryHeap<Reverse<Buffered<M>>>,
+    newest: u64,
+    arrivals: u64,
+    /// Timestamp of the last released transaction of each client.
+    released: HashMap<u16, u64>,
+    late: Rc<Cell<u64>>,
+    line: Option<u64>,
+    exhausted: bool,
+}
+
+impl<'a, M: Money> ReorderingSource<'a, M> {
+    /**
+     * Reorders `inner`, counting late transactions in `late`.
+     */
+    pub fn new(
+        inner: Box<dyn TransactionSource<M> + 'a>,
+        lateness: u64,
+        policy: LatePolicy,
+        late: Rc<Cell<u64>>,
+    ) -> Self {
+        ReorderingSource {
+            inner,
+            lateness,
+            policy,
+            buffer: BinaryHeap::new(),
+            newest: 0,
+            arrivals: 0,
+            released: HashMap::new(),
+            late,
+            line: None,
+            exhausted: false,
+        }
+    }
+
+    /**
+     * The oldest buffered transaction, if it is due or the input is done.
+     */
+    fn release(&mut self) -> Option<Buffered<M>> {
+        let due = self.buffer.peek().is_some_and(|Reverse(oldest)| {
+            self.exhausted || oldest.timestamp.saturating_add(self.lateness) <= self.newest
+        });
+        if due {
+            self.buffer.pop().map(|Reverse(buffered)| buffered)
+        } else {
+            None
+        }
+    }
+}
+
+impl<M: Money> TransactionSource<M> for ReorderingSource<'_, M> {
+    fn next_tx(&mut self) -> Option<Result<Transaction<M>, Box<dyn Error>>> {
+        loop {
+            if let Some(buffered) = self.release() {
+                let client = buffered.tx.client_id();
+                let released = self.released.entry(client).or_insert(buffered.timestamp);
+                *released = (*released).max(buffered.timestamp);
+                self.line = buffered.line;
+                return Some(Ok(buffered.tx));
+            }
+            if self.exhausted {
+                return None;
+            }
+            let tx = match self.inner.next_tx() {
+                None => {
+                    self.exhausted = true;
+                    continue;
+                }
+                Some(Err(e)) => {
+                    self.line = self.inner.line();
+                    return Some(Err(e));
+                }
+                Some(Ok(tx)) => tx,
+            };
+            let timestamp = tx.timestamp().unwrap_or(self.newest);
+            let is_late = self
+                .released
+                .get(&tx.client_id())
+                .is_some_and(|released| timestamp < *released);
+            if is_late {
+                self.late.set(self.late.get() + 1);
+                if self.policy == LatePolicy::Flag {
+                    let tags = match tx.tags() {
+                        Some(tags) => format!("{};{}", tags, LATE_TAG),
+                        None => String::from(LATE_TAG),
+                    };
+                    self.line = self.inner.line();
+                    return Some(Ok(tx.with_tags(tags)));
+                }
+                continue;
+            }
+            self.newest = self.newest.max(timestamp);
+            self.arrivals += 1;
+            self.buffer.push(Reverse(Buffered {
+                timestamp,
+                arrival: self.arrivals,
+                tx,
+                line: self.inner.line(),
+            }));
+        }
+    }
+
+    fn line(&self) -> Option<u64> {
+        self.line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{CsvSource, TypeNames};
+
+    fn reorder(policy: LatePolicy) -> (Vec<(u32, Option<String>)>, u64) {
+        let input = "type,client,tx,amount,timestamp\n\
+                     deposit,1,1,1.0,100\n\
+                     deposit,1,2,1.0,90\n\
+                     deposit,2,3,1.0,130\n\
+                     deposit,2,6,1.0,120\n\
+                     deposit,1,4,1.0,200\n\
+                     deposit,1,5,1.0,95\n";
+        let source = CsvSource::new(input.as_bytes(), TypeNames::strict()).unwrap();
+        let late = Rc::new(Cell::new(0));
+        let mut reordering = ReorderingSource::new(Box::new(source), 30, policy, late.clone());
+        let mut released = Vec::new();
+        while let Some(tx) = reordering.next_tx() {
+            let tx: Transaction<f32> = tx.unwrap();
+            released.push((tx.id(), tx.tags().map(String::from)));
+        }
+        (released, late.get())
+    }
+
+    #[test]
+    fn test_releases_in_timestamp_order_and_rejects_late() {
+        // WHEN
+        let (released, late) = reorder(LatePolicy::Reject);
+
+        // THEN
+        let ids: Vec<_> = released.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![2, 1, 6, 3, 4]);
+        assert_eq!(late, 1);
+    }
+
+    #[test]
+    fn test_flags_late_transactions() {
+        // WHEN
+        let (released, late) = reorder(LatePolicy::Flag);
+
+        // THEN
+        assert_eq!(late, 1);
+        assert!(released.contains(&(5, Some(String::from(LATE_TAG)))));
+    }
+}
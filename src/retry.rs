@@ -0,0 +1,187 @@
+use std::cell::Cell;
+use std::io;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/**
+ * How often and how patiently to retry reads that failed transiently.
+ * The wait before retry `n` (from 0) is a random duration of up to
+ * `initial_backoff * 2^n`, capped at `max_backoff`.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(retry);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/**
+ * Timeouts and interruptions, which may succeed when tried again.
+ */
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/**
+ * Retries transient read errors of `inner` according to `policy`, counting
+ * every retry in `retries`. Other errors, and transient ones once the
+ * retries are used up, are returned as they are.
+ */
+pub struct RetryingReader<R> {
+    inner: R,
+    policy: RetryPolicy,
+    retries: Rc<Cell<u64>>,
+    jitter: u64,
+}
+
+impl<R: io::Read> RetryingReader<R> {
+    pub fn new(inner: R, policy: RetryPolicy, retries: Rc<Cell<u64>>) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos());
+        RetryingReader {
+            inner,
+            policy,
+            retries,
+            jitter: u64::from(seed) | 1,
+        }
+    }
+
+    /**
+     * A random fraction of `backoff`, spreading out retries of readers that
+     * failed at the same time.
+     */
+    fn jittered(&mut self, backoff: Duration) -> Duration {
+        // xorshift64
+        self.jitter ^= self.jitter << 13;
+        self.jitter ^= self.jitter >> 7;
+        self.jitter ^= self.jitter << 17;
+        backoff.mul_f64((self.jitter >> 11) as f64 / (1_u64 << 53) as f64)
+    }
+}
+
+impl<R: io::Read> io::Read for RetryingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut retry = 0;
+        loop {
+            match self.inner.read(buf) {
+                Err(error) if is_transient(&error) && retry < self.policy.max_retries => {
+                    let backoff = self.jittered(self.policy.backoff(retry));
+                    thread::sleep(backoff);
+                    retry += 1;
+                    self.retries.set(self.retries.get() + 1);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    /// Fails with `kind` the first `failures` reads, then reads `data`.
+    struct FlakyReader {
+        failures: u32,
+        kind: io::ErrorKind,
+        data: &'static [u8],
+    }
+
+    impl io::Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(self.kind.into());
+            }
+            self.data.read(buf)
+        }
+    }
+
+    fn policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+        }
+    }
+
+    #[test]
+    fn test_retries_transient_errors() {
+        // GIVEN
+        let retries = Rc::new(Cell::new(0));
+        let flaky = FlakyReader {
+            failures: 2,
+            kind: io::ErrorKind::TimedOut,
+            data: b"deposit",
+        };
+        let mut reader = RetryingReader::new(flaky, policy(3), retries.clone());
+
+        // WHEN
+        let mut read = String::new();
+        let result = reader.read_to_string(&mut read);
+
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(read, "deposit");
+        assert_eq!(retries.get(), 2);
+    }
+
+    #[test]
+    fn test_gives_up_after_max_retries_and_on_other_errors() {
+        // GIVEN
+        let retries = Rc::new(Cell::new(0));
+        let flaky = FlakyReader {
+            failures: 5,
+            kind: io::ErrorKind::WouldBlock,
+            data: b"",
+        };
+        let broken = FlakyReader {
+            failures: 1,
+            kind: io::ErrorKind::NotFound,
+            data: b"",
+        };
+
+        // WHEN
+        let exhausted = RetryingReader::new(flaky, policy(2), retries.clone()).read(&mut [0; 4]);
+        let fatal = RetryingReader::new(broken, policy(2), retries.clone()).read(&mut [0; 4]);
+
+        // THEN
+        assert_eq!(exhausted.unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(fatal.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(retries.get(), 2);
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(800));
+        assert_eq!(policy.backoff(40), Duration::from_secs(5));
+    }
+}
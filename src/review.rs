@@ -0,0 +1,178 @@
+use crate::bank::Transaction;
+use crate::money::Money;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io;
+use std::time::Duration;
+
+/**
+ * An account locked by a chargeback, waiting for manual review.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct ReviewEntry {
+    #[serde(rename = "client")]
+    pub client_id: u16,
+    pub chargebacks: usize,
+    #[serde(rename = "last_tx")]
+    pub last_tx_id: u32,
+    /// When the latest chargeback happened, if the input carried timestamps.
+    pub last_chargeback_at: Option<u64>,
+}
+
+/**
+ * Locked accounts by client id. With `auto_unlock_after` set, an account
+ * whose latest chargeback is older than that is released from the queue;
+ * time is taken from the timestamps of incoming transactions.
+ */
+pub(crate) struct ReviewQueue {
+    auto_unlock_after: Option<Duration>,
+    entries: BTreeMap<u16, ReviewEntry>,
+}
+
+impl ReviewQueue {
+    pub(crate) fn new(auto_unlock_after: Option<Duration>) -> Self {
+        ReviewQueue {
+            auto_unlock_after,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn enqueue<M: Money>(&mut self, chargeback: &Transaction<M>) {
+        let entry = self
+            .entries
+            .entry(chargeback.client_id())
+            .or_insert(ReviewEntry {
+                client_id: chargeback.client_id(),
+                chargebacks: 0,
+                last_tx_id: chargeback.id(),
+                last_chargeback_at: None,
+            });
+        entry.chargebacks += 1;
+        entry.last_tx_id = chargeback.id();
+        entry.last_chargeback_at = chargeback.timestamp();
+    }
+
+    /**
+     * Removes and returns the clients due for an automatic unlock at `now`.
+     * Entries without a chargeback timestamp are never unlocked.
+     */
+    pub(crate) fn take_due(&mut self, now: u64) -> Vec<u16> {
+        let after = match self.auto_unlock_after {
+            Some(after) => after.as_secs(),
+            None => return Vec::new(),
+        };
+        let due: Vec<u16> = self
+            .entries
+            .values()
+            .filter(|entry| {
+                entry
+                    .last_chargeback_at
+                    .is_some_and(|at| now.saturating_sub(at) >= after)
+            })
+            .map(|entry| entry.client_id)
+            .collect();
+        for client_id in &due {
+            self.entries.remove(client_id);
+        }
+        due
+    }
+
+    pub(crate) fn entries(&self) -> Vec<ReviewEntry> {
+        self.entries.values().copied().collect()
+    }
+}
+
+pub fn write_review_queue<W: io::Write>(
+    entries: &[ReviewEntry],
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for entry in entries {
+        writer.serialize(entry)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/**
+ * Writes transactions held for manual review in the input format, so they
+ * can be approved or declined by a later run.
+ */
+pub fn write_pending_transactions<M: Money, W: io::Write>(
+    transactions: &[Transaction<M>],
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for tx in transactions {
+        writer.serialize(tx)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{Bank, BankBuilder, TxOutcome};
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn lock(client_id: u16, tx_id: u32, timestamp: u64) -> Vec<Transaction<f32>> {
+        vec![
+            Transaction::deposit(client_id, tx_id, 10.0).with_timestamp(timestamp),
+            Transaction::dispute(client_id, tx_id).with_timestamp(timestamp),
+            Transaction::chargeback(client_id, tx_id).with_timestamp(timestamp),
+        ]
+    }
+
+    #[test]
+    fn test_chargebacks_enter_review_queue() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        let mut transactions = lock(1, 1, 0);
+        transactions.extend(lock(1, 2, DAY));
+
+        // WHEN
+        let result = bank.batch_process(transactions);
+
+        // THEN
+        assert!(result.is_ok());
+        assert_eq!(
+            bank.review_queue(),
+            vec![ReviewEntry {
+                client_id: 1,
+                chargebacks: 2,
+                last_tx_id: 2,
+                last_chargeback_at: Some(DAY),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_auto_unlock_after_quiet_period() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .auto_unlock_after(Duration::from_secs(7 * DAY))
+            .build();
+        let mut transactions = lock(1, 1, 0);
+        transactions.extend(lock(2, 2, 3 * DAY));
+
+        // WHEN
+        bank.batch_process(transactions).unwrap();
+        let outcome =
+            bank.process_transaction(Transaction::deposit(3, 3, 1.0).with_timestamp(8 * DAY));
+
+        // THEN
+        assert_eq!(outcome, Ok(TxOutcome::Applied));
+        assert!(!bank.account(1).unwrap().locked);
+        assert!(bank.account(2).unwrap().locked);
+        assert_eq!(
+            bank.review_queue()
+                .iter()
+                .map(|entry| entry.client_id)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+}
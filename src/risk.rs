@@ -0,0 +1,295 @@
+use crate::bank::{Transaction, TransactionType};
+use crate::money::Money;
+use rust_decimal::Decimal;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::Duration;
+
+/**
+ * What to do with a withdrawal that breaks a velocity rule, from least to
+ * most severe.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RiskAction {
+    /// Apply it, but report it as `TxOutcome::Flagged`.
+    Flag,
+    /// Hold it for manual review, reported as `TxOutcome::Pending`.
+    Review,
+    /// Refuse it, reported as `TxOutcome::Blocked`.
+    Block,
+}
+
+/**
+ * Limits how many withdrawals, and how much in total, a client may make
+ * within `window`, counting the withdrawal being checked. Windows are
+ * measured on transaction timestamps; withdrawals without one are not
+ * tracked.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VelocityRule<M = Decimal> {
+    pub window: Duration,
+    pub max_count: Option<usize>,
+    pub max_sum: Option<M>,
+    pub action: RiskAction,
+}
+
+/**
+ * The velocity rule a withdrawal broke.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RiskFlag {
+    WithdrawalCount {
+        window: Duration,
+    },
+    WithdrawalSum {
+        window: Duration,
+    },
+    /// Matched the flagging rule at this position (from 1) in the rules file.
+    Rule(usize),
+}
+
+impl fmt::Display for RiskFlag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RiskFlag::WithdrawalCount { window } => {
+                write!(f, "too many withdrawals within {}s", window.as_secs())
+            }
+            RiskFlag::WithdrawalSum { window } => {
+                write!(f, "withdrawn amount too high within {}s", window.as_secs())
+            }
+            RiskFlag::Rule(position) => write!(f, "matched rule #{}", position),
+        }
+    }
+}
+
+/**
+ * A withdrawal the tracker counts towards velocity windows.
+ */
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct TrackedWithdrawal<M> {
+    client_id: u16,
+    timestamp: u64,
+    amount: M,
+}
+
+/**
+ * Rolling per-client history of applied withdrawals, kept for as long as the
+ * longest rule window.
+ */
+pub(crate) struct VelocityTracker<M> {
+    rules: Vec<VelocityRule<M>>,
+    withdrawals: HashMap<u16, VecDeque<(u64, M)>>,
+}
+
+impl<M: Money> VelocityTracker<M> {
+    pub(crate) fn new(rules: Vec<VelocityRule<M>>) -> Self {
+        VelocityTracker {
+            rules,
+            withdrawals: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn track(&self, tx: &Transaction<M>) -> Option<TrackedWithdrawal<M>> {
+        if self.rules.is_empty() || tx.tx_type() != TransactionType::Withdrawal {
+            return None;
+        }
+        Some(TrackedWithdrawal {
+            client_id: tx.client_id(),
+            timestamp: tx.timestamp()?,
+            amount: tx.amount()?,
+        })
+    }
+
+    /**
+     * Returns the rule `withdrawal` would break if applied. Blocking rules win
+     * over flagging ones.
+     */
+    pub(crate) fn check(
+        &mut self,
+        withdrawal: &TrackedWithdrawal<M>,
+    ) -> Option<(RiskAction, RiskFlag)> {
+        self.evict(withdrawal.client_id, withdrawal.timestamp);
+        let history = self.withdrawals.get(&withdrawal.client_id);
+
+        let mut breach: Option<(RiskAction, RiskFlag)> = None;
+        for rule in &self.rules {
+            let since = withdrawal.timestamp.saturating_sub(rule.window.as_secs());
+            let (count, sum) = history
+                .iter()
+                .flat_map(|entries| entries.iter())
+                .filter(|(at, _)| *at > since)
+                .fold((1, Some(withdrawal.amount)), |(count, sum), (_, amount)| {
+                    (count + 1, sum.and_then(|sum| sum.checked_add(*amount)))
+                });
+
+            let flag = if rule.max_count.is_some_and(|max| count > max) {
+                RiskFlag::WithdrawalCount {
+                    window: rule.window,
+                }
+            } else if rule
+                .max_sum
+                .is_some_and(|max| sum.is_none_or(|sum| sum > max))
+            {
+                RiskFlag::WithdrawalSum {
+                    window: rule.window,
+                }
+            } else {
+                continue;
+            };
+            let escalates = breach.is_none_or(|(action, _)| rule.action > action);
+            if escalates {
+                breach = Some((rule.action, flag));
+            }
+        }
+        breach
+    }
+
+    pub(crate) fn record(&mut self, withdrawal: TrackedWithdrawal<M>) {
+        self.withdrawals
+            .entry(withdrawal.client_id)
+            .or_default()
+            .push_back((withdrawal.timestamp, withdrawal.amount));
+    }
+
+    fn evict(&mut self, client_id: u16, now: u64) {
+        let longest = self
+            .rules
+            .iter()
+            .map(|rule| rule.window.as_secs())
+            .max()
+            .unwrap_or(0);
+        if let Some(entries) = self.withdrawals.get_mut(&client_id) {
+            while entries
+                .front()
+                .is_some_and(|(at, _)| at.saturating_add(longest) <= now)
+            {
+                entries.pop_front();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{Bank, BankBuilder, TxOutcome};
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn process_all(bank: &Bank<f32>, transactions: Vec<Transaction<f32>>) -> Vec<TxOutcome> {
+        let mut outcomes = Vec::new();
+        bank.batch_process_with(transactions, |_, outcome| outcomes.push(outcome))
+            .unwrap();
+        outcomes
+    }
+
+    #[test]
+    fn test_velocity_count_flags_withdrawal_within_window() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .velocity_rule(VelocityRule {
+                window: HOUR,
+                max_count: Some(2),
+                max_sum: None,
+                action: RiskAction::Flag,
+            })
+            .build();
+
+        // WHEN
+        let outcomes = process_all(
+            &bank,
+            vec![
+                Transaction::deposit(1, 1, 100.0).with_timestamp(0),
+                Transaction::withdrawal(1, 2, 1.0).with_timestamp(100),
+                Transaction::withdrawal(1, 3, 1.0).with_timestamp(200),
+                Transaction::withdrawal(1, 4, 1.0).with_timestamp(300),
+                Transaction::withdrawal(1, 5, 1.0).with_timestamp(3_800),
+            ],
+        );
+
+        // THEN
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Applied,
+                TxOutcome::Applied,
+                TxOutcome::Applied,
+                TxOutcome::Flagged(RiskFlag::WithdrawalCount { window: HOUR }),
+                TxOutcome::Applied,
+            ]
+        );
+        assert_eq!(bank.account(1).unwrap().total, 96.0);
+    }
+
+    #[test]
+    fn test_velocity_sum_blocks_withdrawal() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .velocity_rule(VelocityRule {
+                window: HOUR,
+                max_count: None,
+                max_sum: Some(50.0),
+                action: RiskAction::Block,
+            })
+            .build();
+
+        // WHEN
+        let outcomes = process_all(
+            &bank,
+            vec![
+                Transaction::deposit(1, 1, 100.0).with_timestamp(0),
+                Transaction::withdrawal(1, 2, 30.0).with_timestamp(10),
+                Transaction::withdrawal(1, 3, 30.0).with_timestamp(20),
+                Transaction::withdrawal(1, 4, 20.0).with_timestamp(30),
+            ],
+        );
+
+        // THEN
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Applied,
+                TxOutcome::Applied,
+                TxOutcome::Blocked(RiskFlag::WithdrawalSum { window: HOUR }),
+                TxOutcome::Applied,
+            ]
+        );
+        assert_eq!(bank.account(1).unwrap().total, 50.0);
+    }
+
+    #[test]
+    fn test_velocity_block_wins_over_flag() {
+        // GIVEN
+        let day = Duration::from_secs(86_400);
+        let bank = BankBuilder::<f32>::default()
+            .velocity_rule(VelocityRule {
+                window: HOUR,
+                max_count: Some(1),
+                max_sum: None,
+                action: RiskAction::Flag,
+            })
+            .velocity_rule(VelocityRule {
+                window: day,
+                max_count: Some(1),
+                max_sum: None,
+                action: RiskAction::Block,
+            })
+            .build();
+
+        // WHEN
+        let outcomes = process_all(
+            &bank,
+            vec![
+                Transaction::deposit(1, 1, 100.0).with_timestamp(0),
+                Transaction::withdrawal(1, 2, 1.0).with_timestamp(10),
+                Transaction::withdrawal(1, 3, 1.0).with_timestamp(20),
+            ],
+        );
+
+        // THEN
+        assert_eq!(
+            outcomes[2],
+            TxOutcome::Blocked(RiskFlag::WithdrawalCount { window: day })
+        );
+    }
+}
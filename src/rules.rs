@@ -0,0 +1,327 @@
+use crate::bank::{Transaction, TransactionType};
+use crate::money::Money;
+use crate::overrides::KycStatus;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::time::Duration;
+
+/**
+ * What a matching rule does with the transaction.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction<M = Decimal> {
+    /// Refuse it, reported as `Rejection::Rule`.
+    Reject,
+    /// Hold it for manual review, reported as `TxOutcome::Pending`.
+    Hold,
+    /// Apply it, but report it as `TxOutcome::Flagged`.
+    Flag,
+    /// Apply it and charge the client this amount on top.
+    Fee(M),
+}
+
+/**
+ * Matches when the client made more than `max_count` transactions of the
+ * rule's types within `window`, counting the one being checked. Only
+ * transactions with a timestamp are counted.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub struct VelocityCondition {
+    #[serde(deserialize_with = "deserialize_seconds")]
+    pub window: Duration,
+    pub max_count: usize,
+}
+
+/**
+ * A condition on a transaction and an action taken when it holds. Every
+ * condition left out matches anything.
+ */
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Rule<M = Decimal> {
+    #[serde(rename = "type", default)]
+    pub types: Vec<TransactionType>,
+    pub min_amount: Option<M>,
+    pub max_amount: Option<M>,
+    #[serde(default)]
+    pub clients: Vec<u16>,
+    pub kyc: Option<KycStatus>,
+    pub velocity: Option<VelocityCondition>,
+    pub action: RuleAction<M>,
+}
+
+impl<M: Money> Rule<M> {
+    fn matches_type(&self, tx_type: TransactionType) -> bool {
+        self.types.is_empty() || self.types.contains(&tx_type)
+    }
+
+    fn matches(&self, tx: &Transaction<M>, kyc: Option<KycStatus>, recent: &Recent) -> bool {
+        let amount = tx.amount();
+        self.matches_type(tx.tx_type())
+            && self
+                .min_amount
+                .is_none_or(|min| amount.is_some_and(|a| a >= min))
+            && self
+                .max_amount
+                .is_none_or(|max| amount.is_some_and(|a| a <= max))
+            && (self.clients.is_empty() || self.clients.contains(&tx.client_id()))
+            && self.kyc.is_none_or(|status| kyc == Some(status))
+            && self.velocity.is_none_or(|velocity| {
+                tx.timestamp().is_some_and(|now| {
+                    let window = velocity.window.as_secs();
+                    let count = recent
+                        .iter()
+                        .filter(|(at, tx_type)| {
+                            now.saturating_sub(*at) < window && self.matches_type(*tx_type)
+                        })
+                        .count();
+                    count > velocity.max_count
+                })
+            })
+    }
+}
+
+#[derive(Deserialize)]
+struct RuleFile<M> {
+    #[serde(rename = "rule", default)]
+    rules: Vec<Rule<M>>,
+}
+
+/**
+ * Parses a TOML file of `[[rule]]` tables, e.g.
+ *
+ * ```toml
+ * [[rule]]
+ * type = ["withdrawal"]
+ * min_amount = 5000
+ * kyc = "unverified"
+ * action = "hold"
+ *
+ * [[rule]]
+ * type = ["withdrawal"]
+ * velocity = { window = 3600, max_count = 5 }
+ * action = { fee = 2.5 }
+ * ```
+ */
+pub fn read_rules<M: Money>(input: &str) -> Result<Vec<Rule<M>>, Box<dyn Error>> {
+    Ok(toml::from_str::<RuleFile<M>>(input)?.rules)
+}
+
+/// A client's timestamped transactions, oldest first.
+type Recent = VecDeque<(u64, TransactionType)>;
+
+/**
+ * Evaluates rules in order; the first one that matches decides.
+ */
+pub(crate) struct RuleEngine<M> {
+    rules: Vec<Rule<M>>,
+    longest_window: u64,
+    recent: HashMap<u16, Recent>,
+}
+
+impl<M: Money> RuleEngine<M> {
+    pub(crate) fn new(rules: Vec<Rule<M>>) -> Self {
+        let longest_window = rules
+            .iter()
+            .filter_map(|rule| rule.velocity)
+            .map(|velocity| velocity.window.as_secs())
+            .max()
+            .unwrap_or(0);
+        RuleEngine {
+            rules,
+            longest_window,
+            recent: HashMap::new(),
+        }
+    }
+
+    /**
+     * Records `tx` for velocity conditions and returns the position (from 1)
+     * and action of the first matching rule.
+     */
+    pub(crate) fn evaluate(
+        &mut self,
+        tx: &Transaction<M>,
+        kyc: Option<KycStatus>,
+    ) -> Option<(usize, RuleAction<M>)> {
+        if self.rules.is_empty() {
+            return None;
+        }
+        let recent = self.recent.entry(tx.client_id()).or_default();
+        if let Some(now) = tx.timestamp() {
+            while recent
+                .front()
+                .is_some_and(|(at, _)| now.saturating_sub(*at) >= self.longest_window)
+            {
+                recent.pop_front();
+            }
+            if self.longest_window > 0 {
+                recent.push_back((now, tx.tx_type()));
+            }
+        }
+        let recent = &*recent;
+        self.rules
+            .iter()
+            .position(|rule| rule.matches(tx, kyc, recent))
+            .map(|index| (index + 1, self.rules[index].action))
+    }
+}
+
+fn deserialize_seconds<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Duration::from_secs(u64::deserialize(deserializer)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{Bank, BankBuilder, TxOutcome};
+    use crate::overrides::ClientOverride;
+    use crate::risk::RiskFlag;
+    use crate::validation::Rejection;
+
+    const RULES: &str = r#"
+        [[rule]]
+        type = ["withdrawal"]
+        min_amount = 1000
+        action = "reject"
+
+        [[rule]]
+        type = ["deposit"]
+        kyc = "unverified"
+        action = "hold"
+
+        [[rule]]
+        type = ["withdrawal"]
+        velocity = { window = 60, max_count = 2 }
+        action = { fee = 1.0 }
+
+        [[rule]]
+        clients = [7]
+        action = "flag"
+    "#;
+
+    fn rules_bank() -> Bank<f32> {
+        let mut unverified = ClientOverride::new(2);
+        unverified.kyc = Some(KycStatus::Unverified);
+        BankBuilder::<f32>::default()
+            .rules(read_rules(RULES).unwrap())
+            .client_override(unverified)
+            .build()
+    }
+
+    #[test]
+    fn test_read_rules() {
+        // WHEN
+        let rules: Vec<Rule<f32>> = read_rules(RULES).unwrap();
+
+        // THEN
+        assert_eq!(rules.len(), 4);
+        assert_eq!(rules[0].types, vec![TransactionType::Withdrawal]);
+        assert_eq!(rules[0].min_amount, Some(1000.0));
+        assert_eq!(
+            rules[2].velocity,
+            Some(VelocityCondition {
+                window: Duration::from_secs(60),
+                max_count: 2
+            })
+        );
+        assert_eq!(rules[2].action, RuleAction::Fee(1.0));
+        assert!(read_rules::<f32>("[[rule]]\naction = \"explode\"").is_err());
+    }
+
+    #[test]
+    fn test_rule_actions() {
+        // GIVEN
+        let bank = rules_bank();
+        let mut outcomes = Vec::new();
+
+        // WHEN
+        let result = bank.batch_process_with(
+            vec![
+                Transaction::deposit(1, 1, 5_000.0),
+                Transaction::withdrawal(1, 2, 2_000.0),
+                Transaction::deposit(2, 3, 10.0),
+                Transaction::withdrawal(1, 4, 10.0).with_timestamp(0),
+                Transaction::withdrawal(1, 5, 10.0).with_timestamp(30),
+                Transaction::withdrawal(1, 6, 10.0).with_timestamp(50),
+                Transaction::deposit(7, 7, 1.0),
+            ],
+            |_, outcome| outcomes.push(outcome),
+        );
+
+        // THEN
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Applied,
+                TxOutcome::Rejected(Rejection::Rule(1)),
+                TxOutcome::Pending,
+                TxOutcome::Applied,
+                TxOutcome::Applied,
+                TxOutcome::Applied,
+                TxOutcome::Flagged(RiskFlag::Rule(4)),
+            ]
+        );
+        assert_eq!(bank.account(1).unwrap().total, 4_969.0);
+    }
+
+    #[test]
+    fn test_fee_the_account_cannot_cover_refuses_transaction() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .rules(
+                read_rules("[[rule]]\ntype = [\"withdrawal\"]\naction = { fee = 5.0 }\n").unwrap(),
+            )
+            .build();
+        bank.process_transaction(Transaction::deposit(1, 1, 10.0))
+            .unwrap();
+
+        // WHEN
+        let unpaid = bank.process_transaction(Transaction::withdrawal(1, 2, 8.0));
+        let paid = bank.process_transaction(Transaction::withdrawal(1, 3, 5.0));
+
+        // THEN
+        assert_eq!(unpaid, Ok(TxOutcome::RejectedInsufficientFunds));
+        assert_eq!(paid, Ok(TxOutcome::Applied));
+        assert_eq!(bank.account(1).unwrap().available, 0.0);
+        assert_eq!(bank.account(1).unwrap().total, 0.0);
+    }
+
+    #[test]
+    fn test_fee_of_held_transaction_is_charged_on_approval() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .rules(
+                read_rules("[[rule]]\ntype = [\"withdrawal\"]\naction = { fee = 5.0 }\n").unwrap(),
+            )
+            .dual_authorization(1.0)
+            .build();
+        bank.process_transaction(Transaction::deposit(1, 1, 1.0))
+            .unwrap();
+        bank.process_transaction(Transaction::deposit(1, 2, 9.0))
+            .unwrap();
+        bank.authorize(2).unwrap();
+
+        // WHEN
+        let held = bank.process_transaction(Transaction::withdrawal(1, 3, 8.0));
+        let unpaid = bank.authorize(3);
+        let declined = bank.process_transaction(Transaction::decline(1, 3));
+        bank.process_transaction(Transaction::withdrawal(1, 4, 4.0))
+            .unwrap();
+        let paid = bank.authorize(4);
+
+        // THEN
+        assert_eq!(held, Ok(TxOutcome::Pending));
+        assert_eq!(unpaid, Ok(TxOutcome::RejectedInsufficientFunds));
+        assert_eq!(declined, Ok(TxOutcome::Applied));
+        assert_eq!(paid, Ok(TxOutcome::Applied));
+        let account = bank.account(1).unwrap();
+        assert_eq!((account.available, account.held), (1.0, 0.0));
+        assert_eq!(account.total, 1.0);
+    }
+}
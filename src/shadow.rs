@@ -0,0 +1,205 @@
+use crate::bank::{AccountView, Bank, TxOutcome};
+use crate::money::Money;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/**
+ * Amount type of the engine run alongside the `Decimal` one of the
+ * command line program by `--shadow`.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShadowEngine {
+    /// The floating point amounts the program used before.
+    F32,
+    MinorUnits,
+}
+
+impl FromStr for ShadowEngine {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "f32" => Ok(ShadowEngine::F32),
+            "minor-units" => Ok(ShadowEngine::MinorUnits),
+            _ => Err(format!("Unknown shadow engine {:?}", name)),
+        }
+    }
+}
+
+/**
+ * A difference between two runs of the same input.
+ */
+#[derive(Debug, PartialEq)]
+pub enum Divergence {
+    /// The transaction had different outcomes.
+    Outcome {
+        tx_id: u32,
+        primary: TxOutcome,
+        shadow: TxOutcome,
+    },
+    /// The client's final account differs as written in the report, or
+    /// only exists in one of the runs.
+    Account {
+        client_id: u16,
+        primary: Option<String>,
+        shadow: Option<String>,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Divergence::Outcome {
+                tx_id,
+                primary,
+                shadow,
+            } => write!(f, "transaction #{}: {} vs {}", tx_id, primary, shadow),
+            Divergence::Account {
+                client_id,
+                primary,
+                shadow,
+            } => write!(
+                f,
+                "client {}: {} vs {}",
+                client_id,
+                primary.as_deref().unwrap_or("no account"),
+                shadow.as_deref().unwrap_or("no account")
+            ),
+        }
+    }
+}
+
+/**
+ * Compares the outcomes of two runs, transaction by transaction. Both are
+ * expected to have processed the same input, so only transactions both
+ * reached are compared.
+ */
+pub fn compare_outcomes(
+    primary: &[(u32, TxOutcome)],
+    shadow: &[(u32, TxOutcome)],
+) -> Vec<Divergence> {
+    primary
+        .iter()
+        .zip(shadow)
+        .filter(|(primary, shadow)| primary != shadow)
+        .map(|(&(tx_id, primary), &(_, shadow))| Divergence::Outcome {
+            tx_id,
+            primary,
+            shadow,
+        })
+        .collect()
+}
+
+/**
+ * Compares the final accounts of two banks, by their report columns, in
+ * client id order.
+ */
+pub fn compare_accounts<A: Money, B: Money>(
+    primary: &Bank<A>,
+    shadow: &Bank<B>,
+) -> Vec<Divergence> {
+    let mut rows: BTreeMap<u16, (Option<String>, Option<String>)> = BTreeMap::new();
+    for account in primary.accounts() {
+        rows.entry(account.client_id).or_default().0 = Some(report_row(&account));
+    }
+    for account in shadow.accounts() {
+        rows.entry(account.client_id).or_default().1 = Some(report_row(&account));
+    }
+    rows.into_iter()
+        .filter(|(_, (primary, shadow))| primary != shadow)
+        .map(|(client_id, (primary, shadow))| Divergence::Account {
+            client_id,
+            primary,
+            shadow,
+        })
+        .collect()
+}
+
+fn report_row<M: Money>(account: &AccountView<M>) -> String {
+    format!(
+        "{},{},{},{},{}",
+        account.available.to_fixed(),
+        account.held.to_fixed(),
+        account.total.to_fixed(),
+        account.locked,
+        account.frozen
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{BankBuilder, Transaction};
+    use crate::money::MinorUnits;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_float_and_exact_engines_diverge() {
+        // GIVEN
+        let float = Bank::<f32>::default();
+        let exact = BankBuilder::<MinorUnits>::default().build();
+        let mut float_outcomes = Vec::new();
+        let mut exact_outcomes = Vec::new();
+
+        // WHEN
+        float
+            .batch_process_with(
+                vec![
+                    Transaction::deposit(1, 1, 16_777_216.0),
+                    Transaction::deposit(1, 2, 1.0),
+                    Transaction::deposit(1, 3, 1.0),
+                    Transaction::withdrawal(1, 4, 16_777_218.0),
+                ],
+                |tx_id, outcome| float_outcomes.push((tx_id, outcome)),
+            )
+            .unwrap();
+        exact
+            .batch_process_with(
+                vec![
+                    Transaction::deposit(1, 1, "16777216".parse().unwrap()),
+                    Transaction::deposit(1, 2, "1".parse().unwrap()),
+                    Transaction::deposit(1, 3, "1".parse().unwrap()),
+                    Transaction::withdrawal(1, 4, "16777218".parse().unwrap()),
+                ],
+                |tx_id, outcome| exact_outcomes.push((tx_id, outcome)),
+            )
+            .unwrap();
+
+        // THEN
+        assert_eq!(
+            compare_outcomes(&float_outcomes, &exact_outcomes),
+            vec![Divergence::Outcome {
+                tx_id: 4,
+                primary: TxOutcome::RejectedInsufficientFunds,
+                shadow: TxOutcome::Applied,
+            }]
+        );
+        assert_eq!(
+            compare_accounts(&float, &exact),
+            vec![Divergence::Account {
+                client_id: 1,
+                primary: Some(String::from(
+                    "16777216.0000,0.0000,16777216.0000,false,false"
+                )),
+                shadow: Some(String::from("0.0000,0.0000,0.0000,false,false")),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_matching_runs_have_no_divergence() {
+        // GIVEN
+        let float = Bank::<f32>::default();
+        let decimal = BankBuilder::<Decimal>::default().build();
+        float
+            .batch_process(vec![Transaction::deposit(2, 1, 1.5)])
+            .unwrap();
+        decimal
+            .batch_process(vec![Transaction::deposit(2, 1, Decimal::new(15, 1))])
+            .unwrap();
+
+        // THEN
+        assert!(compare_accounts(&float, &decimal).is_empty());
+    }
+}
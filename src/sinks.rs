@@ -0,0 +1,357 @@
+use crate::bank::AccountView;
+use crate::money::Money;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/**
+ * An account as it is after transaction `tx_id` changed it. Serialized
+ * without `previous`.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[serde(bound(serialize = "M: Money"))]
+pub struct AccountUpdate<M = Decimal> {
+    #[serde(rename = "tx")]
+    pub tx_id: u32,
+    #[serde(flatten)]
+    pub account: AccountView<M>,
+    /// The account before the change; zero balances for a new account.
+    #[serde(skip)]
+    pub previous: AccountView<M>,
+}
+
+/**
+ * Receives every committed change of an account's balances or flags, in
+ * processing order. A failing sink doesn't stop processing; see
+ * `Bank::sink_failures`.
+ */
+pub trait AccountUpdateSink<M: Money = Decimal> {
+    fn update(&mut self, update: &AccountUpdate<M>) -> Result<(), Box<dyn Error>>;
+}
+
+/**
+ * Appends updates to a file as JSON lines, flushed after each one.
+ */
+pub struct FileSink<W = BufWriter<File>> {
+    writer: W,
+}
+
+impl FileSink {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(FileSink::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> FileSink<W> {
+    pub fn new(writer: W) -> Self {
+        FileSink { writer }
+    }
+}
+
+impl<M: Money, W: Write> AccountUpdateSink<M> for FileSink<W> {
+    fn update(&mut self, update: &AccountUpdate<M>) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut self.writer, update)?;
+        writeln!(self.writer)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/**
+ * Writes a change-data-capture stream as CSV: one
+ * `client,field,old,new,tx` row per changed balance or flag, so consumers
+ * can apply changes without diffing reports. Amounts are written like in
+ * the report.
+ */
+pub struct CdcSink<W: Write = BufWriter<File>> {
+    writer: csv::Writer<W>,
+}
+
+impl CdcSink {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        CdcSink::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> CdcSink<W> {
+    pub fn new(writer: W) -> Result<Self, Box<dyn Error>> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["client", "field", "old", "new", "tx"])?;
+        writer.flush()?;
+        Ok(CdcSink { writer })
+    }
+}
+
+impl<M: Money, W: Write> AccountUpdateSink<M> for CdcSink<W> {
+    fn update(&mut self, update: &AccountUpdate<M>) -> Result<(), Box<dyn Error>> {
+        let (old, new) = (&update.previous, &update.account);
+        let changes = [
+            (
+                "available",
+                old.available.to_fixed(),
+                new.available.to_fixed(),
+            ),
+            ("held", old.held.to_fixed(), new.held.to_fixed()),
+            ("total", old.total.to_fixed(), new.total.to_fixed()),
+            ("locked", old.locked.to_string(), new.locked.to_string()),
+            ("frozen", old.frozen.to_string(), new.frozen.to_string()),
+        ];
+        let client = new.client_id.to_string();
+        let tx = update.tx_id.to_string();
+        for (field, old, new) in changes {
+            if old != new {
+                self.writer
+                    .write_record([&client, field, &old, &new, &tx])?;
+            }
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/**
+ * Sends updates to another thread. Fails once the receiver is gone.
+ */
+pub struct ChannelSink<M = Decimal> {
+    sender: Sender<AccountUpdate<M>>,
+}
+
+impl<M> ChannelSink<M> {
+    pub fn new(sender: Sender<AccountUpdate<M>>) -> Self {
+        ChannelSink { sender }
+    }
+}
+
+impl<M: Money> AccountUpdateSink<M> for ChannelSink<M> {
+    fn update(&mut self, update: &AccountUpdate<M>) -> Result<(), Box<dyn Error>> {
+        self.sender.send(*update)?;
+        Ok(())
+    }
+}
+
+/**
+ * POSTs each update as a JSON object to a plain `http://` URL, one
+ * connection per update. Any status other than 2xx is an error.
+ */
+pub struct HttpSink {
+    host: String,
+    port: u16,
+    path: String,
+    timeout: Duration,
+}
+
+impl HttpSink {
+    /**
+     * Parses `http://host[:port][/path]`; HTTPS is not supported.
+     */
+    pub fn new(url: &str, timeout: Duration) -> Result<Self, String> {
+        let invalid = || format!("Invalid update URL {:?}", url);
+        let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(HttpSink {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            timeout,
+        })
+    }
+}
+
+impl<M: Money> AccountUpdateSink<M> for HttpSink {
+    fn update(&mut self, update: &AccountUpdate<M>) -> Result<(), Box<dyn Error>> {
+        let body = serde_json::to_vec(update)?;
+        let address = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| format!("Could not resolve {}", self.host))?;
+        let mut stream = TcpStream::connect_timeout(&address, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            self.host,
+            body.len()
+        )?;
+        stream.write_all(&body)?;
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line)?;
+        match status_line.split_whitespace().nth(1) {
+            Some(status) if status.starts_with('2') => Ok(()),
+            _ => Err(format!("Update rejected: {}", status_line.trim_end()).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{BankBuilder, Transaction};
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn test_sinks_receive_committed_changes() {
+        // GIVEN
+        let (sender, receiver) = mpsc::channel();
+        let bank = BankBuilder::<f32>::default()
+            .update_sink(ChannelSink::new(sender))
+            .update_sink(FileSink::new(Vec::new()))
+            .build();
+
+        // WHEN
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 2.0),
+            Transaction::withdrawal(1, 2, 5.0),
+            Transaction::dispute(1, 1),
+        ])
+        .unwrap();
+
+        // THEN
+        let updates: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].tx_id, 1);
+        assert_eq!(updates[1].tx_id, 1);
+        assert_eq!(updates[1].account.held, 2.0);
+        assert_eq!(bank.sink_failures(), 0);
+    }
+
+    #[test]
+    fn test_cdc_sink_writes_changed_fields() {
+        // GIVEN
+        let (sender, receiver) = mpsc::channel();
+        let bank = BankBuilder::<f32>::default()
+            .update_sink(ChannelSink::new(sender))
+            .build();
+        let mut cdc = CdcSink::new(Vec::new()).unwrap();
+
+        // WHEN
+        bank.batch_process(vec![
+            Transaction::deposit(1, 1, 2.0),
+            Transaction::dispute(1, 1),
+            Transaction::chargeback(1, 1),
+        ])
+        .unwrap();
+        for update in receiver.try_iter() {
+            cdc.update(&update).unwrap();
+        }
+
+        // THEN
+        assert_eq!(
+            String::from_utf8(cdc.writer.into_inner().unwrap()).unwrap(),
+            "client,field,old,new,tx\n\
+             1,available,0.0000,2.0000,1\n\
+             1,total,0.0000,2.0000,1\n\
+             1,available,2.0000,0.0000,1\n\
+             1,held,0.0000,2.0000,1\n\
+             1,held,2.0000,0.0000,1\n\
+             1,total,2.0000,0.0000,1\n\
+             1,locked,false,true,1\n"
+        );
+    }
+
+    #[test]
+    fn test_file_sink_writes_json_lines() {
+        // GIVEN
+        let mut sink = FileSink::new(Vec::new());
+        let update = AccountUpdate {
+            tx_id: 7,
+            account: AccountView {
+                client_id: 1,
+                available: 1.5_f32,
+                held: 0.0,
+                total: 1.5,
+                locked: false,
+                frozen: false,
+            },
+            previous: AccountView {
+                client_id: 1,
+                available: 0.0,
+                held: 0.0,
+                total: 0.0,
+                locked: false,
+                frozen: false,
+            },
+        };
+
+        // WHEN
+        sink.update(&update).unwrap();
+
+        // THEN
+        assert_eq!(
+            String::from_utf8(sink.writer).unwrap(),
+            "{\"tx\":7,\"client\":1,\"available\":\"1.5000\",\"held\":\"0.0000\",\
+             \"total\":\"1.5000\",\"locked\":false,\"frozen\":false}\n"
+        );
+    }
+
+    #[test]
+    fn test_http_sink_posts_updates() {
+        // GIVEN
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/updates", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !String::from_utf8_lossy(&request).contains("\"frozen\":false}") {
+                let read = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let mut sink = HttpSink::new(&url, Duration::from_secs(5)).unwrap();
+        let update = AccountUpdate {
+            tx_id: 1,
+            account: AccountView {
+                client_id: 3,
+                available: 1.0_f32,
+                held: 0.0,
+                total: 1.0,
+                locked: false,
+                frozen: false,
+            },
+            previous: AccountView {
+                client_id: 3,
+                available: 0.0,
+                held: 0.0,
+                total: 0.0,
+                locked: false,
+                frozen: false,
+            },
+        };
+
+        // WHEN
+        let result = sink.update(&update);
+
+        // THEN
+        assert!(result.is_ok());
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /updates HTTP/1.1\r\n"));
+        assert!(request.contains("\"client\":3"));
+        assert!(HttpSink::new("https://example.com", Duration::from_secs(1)).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use crate::bank::AccountView;
+use crate::money::Money;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/**
+ * The state of a bank at one point, detached from it: it can be kept,
+ * inspected or serialized on another thread while the bank goes on
+ * processing. Taken with `Bank::snapshot`.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(bound(serialize = "M: Money"))]
+pub struct BankSnapshot<M = Decimal> {
+    /// Ordered by client id.
+    pub accounts: Vec<AccountView<M>>,
+    pub disputes: DisputeStats,
+}
+
+/**
+ * Counts of the stored transactions by where they are in the dispute
+ * lifecycle.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize)]
+pub struct DisputeStats {
+    /// All stored transactions, disputed or not.
+    pub transactions: usize,
+    /// Under dispute now.
+    pub open: usize,
+    pub resolved: usize,
+    pub charged_back: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bank::{Bank, Transaction};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_snapshot_is_unaffected_by_later_processing() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![
+            Transaction::deposit(2, 1, 5.0),
+            Transaction::deposit(1, 2, 3.0),
+            Transaction::dispute(2, 1),
+        ])
+        .unwrap();
+
+        // WHEN
+        let before = bank.snapshot();
+        let unchanged = bank.snapshot();
+        bank.batch_process(vec![Transaction::chargeback(2, 1)])
+            .unwrap();
+        let after = bank.snapshot();
+        let json = {
+            let before = before.clone();
+            thread::spawn(move || serde_json::to_string(&before.disputes).unwrap())
+                .join()
+                .unwrap()
+        };
+
+        // THEN
+        assert!(Arc::ptr_eq(&before, &unchanged));
+        assert_eq!(
+            before
+                .accounts
+                .iter()
+                .map(|a| a.client_id)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(before.accounts[1].held, 5.0);
+        assert_eq!(
+            json,
+            "{\"transactions\":2,\"open\":1,\"resolved\":0,\"charged_back\":0}"
+        );
+        assert!(after.accounts[1].locked);
+        assert_eq!(after.disputes.open, 0);
+        assert_eq!(after.disputes.charged_back, 1);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::bank::{AccountView, Transaction, TransactionStatus};
+use crate::money::Money;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::error::Error;
+use std::io;
+
+/**
+ * A client's final balances and stored transactions, for delivery to the
+ * client as a file of their own.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(bound(serialize = "M: Money"))]
+pub struct ClientStatement<M = Decimal> {
+    pub account: AccountView<M>,
+    /// Ordered by transaction id.
+    pub transactions: Vec<StatementEntry<M>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementEntry<M = Decimal> {
+    #[serde(flatten)]
+    pub transaction: Transaction<M>,
+    pub status: TransactionStatus,
+}
+
+pub fn write_client_statement<M: Money, W: io::Write>(
+    statement: &ClientStatement<M>,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    serde_json::to_writer_pretty(writer, statement)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::Bank;
+
+    #[test]
+    fn test_client_statements() {
+        // GIVEN
+        let bank = Bank::<f32>::default();
+        bank.batch_process(vec![
+            Transaction::deposit(2, 3, 5.0),
+            Transaction::deposit(1, 2, 1.0),
+            Transaction::deposit(1, 1, 2.0),
+            Transaction::dispute(1, 1),
+        ])
+        .unwrap();
+
+        // WHEN
+        let statements = bank.client_statements();
+        let mut json = Vec::new();
+        write_client_statement(&statements[0], &mut json).unwrap();
+
+        // THEN
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[1].account, bank.account(2).unwrap());
+        assert_eq!(
+            statements[0].transactions,
+            vec![
+                StatementEntry {
+                    transaction: Transaction::deposit(1, 1, 2.0),
+                    status: TransactionStatus::Disputed,
+                },
+                StatementEntry {
+                    transaction: Transaction::deposit(1, 2, 1.0),
+                    status: TransactionStatus::Processed,
+                },
+            ]
+        );
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains(r#""status": "disputed""#));
+        assert!(json.contains(r#""type": "deposit""#));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::bank::Transaction;
+use crate::input::TransactionSource;
+use crate::money::Money;
+use rust_decimal::Decimal;
+use std::cell::Cell;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/**
+ * Adds the time spent reading and parsing transactions from `inner` to a
+ * shared total.
+ */
+pub struct TimedSource<'a, M: Money = Decimal> {
+    inner: Box<dyn TransactionSource<M> + 'a>,
+    elapsed: Rc<Cell<Duration>>,
+}
+
+impl<'a, M: Money> TimedSource<'a, M> {
+    pub fn new(inner: Box<dyn TransactionSource<M> + 'a>, elapsed: Rc<Cell<Duration>>) -> Self {
+        TimedSource { inner, elapsed }
+    }
+}
+
+impl<M: Money> TransactionSource<M> for TimedSource<'_, M> {
+    fn next_tx(&mut self) -> Option<Result<Transaction<M>, Box<dyn Error>>> {
+        let started = Instant::now();
+        let tx = self.inner.next_tx();
+        self.elapsed.set(self.elapsed.get() + started.elapsed());
+        tx
+    }
+
+    fn line(&self) -> Option<u64> {
+        self.inner.line()
+    }
+}
+
+/**
+ * Throughput and resource figures of a run, for capacity planning.
+ */
+#[derive(Debug, Default, PartialEq)]
+pub struct RunStats {
+    pub rows: usize,
+    /// Reading and parsing the input.
+    pub parse: Duration,
+    /// Applying the parsed transactions.
+    pub apply: Duration,
+    /// Writing the report and exports.
+    pub report: Duration,
+    /// Peak resident memory, where the platform reports it.
+    pub peak_memory_kb: Option<u64>,
+}
+
+impl RunStats {
+    /**
+     * Rows processed per second of parsing and applying.
+     */
+    pub fn rows_per_sec(&self) -> f64 {
+        let seconds = (self.parse + self.apply).as_secs_f64();
+        if seconds > 0.0 {
+            self.rows as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /**
+     * Writes the figures as `key=value` lines.
+     */
+    pub fn write<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "rows={}", self.rows)?;
+        writeln!(writer, "rows_per_sec={:.0}", self.rows_per_sec())?;
+        writeln!(writer, "parse_ms={}", self.parse.as_millis())?;
+        writeln!(writer, "apply_ms={}", self.apply.as_millis())?;
+        writeln!(writer, "report_ms={}", self.report.as_millis())?;
+        match self.peak_memory_kb {
+            Some(kb) => writeln!(writer, "peak_memory_kb={}", kb),
+            None => writeln!(writer, "peak_memory_kb="),
+        }
+    }
+}
+
+/**
+ * Peak resident memory of this process so far, from `/proc/self/status`;
+ * `None` where that isn't available.
+ */
+pub fn peak_memory_kb() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{CsvSource, TypeNames};
+
+    #[test]
+    fn test_timed_source_passes_transactions_through() {
+        // GIVEN
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\n";
+        let source = CsvSource::new(input.as_bytes(), TypeNames::strict()).unwrap();
+        let elapsed = Rc::new(Cell::new(Duration::ZERO));
+        let mut timed = TimedSource::new(Box::new(source), elapsed.clone());
+
+        // WHEN
+        let mut ids = Vec::new();
+        while let Some(tx) = timed.next_tx() {
+            let tx: Transaction<f32> = tx.unwrap();
+            ids.push(tx.id());
+        }
+
+        // THEN
+        assert_eq!(ids, vec![1, 2]);
+        assert!(elapsed.get() > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_write_stats() {
+        // GIVEN
+        let stats = RunStats {
+            rows: 3_000,
+            parse: Duration::from_millis(500),
+            apply: Duration::from_millis(1_000),
+            report: Duration::from_millis(20),
+            peak_memory_kb: None,
+        };
+        let mut output = Vec::new();
+
+        // WHEN
+        stats.write(&mut output).unwrap();
+
+        // THEN
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "rows=3000\nrows_per_sec=2000\nparse_ms=500\napply_ms=1000\nreport_ms=20\npeak_memory_kb=\n"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use crate::arena::Arena;
+use crate::bank::TransactionRecord;
+use crate::money::Money;
+use std::collections::HashMap;
+
+/**
+ * The bank's transaction records by id, with the ids stored for each client
+ * so per-client lookups don't scan every record, and every id, so records
+ * can be walked by id without sorting them. Both indexes are sorted vectors
+ * of ids: ids mostly come in increasing order and are appended, without an
+ * allocation per record.
+ *
+ * Removals leave the indexes alone. Nearly all of them take a record out
+ * while it is updated and store it back under the same id, so lookups skip
+ * the ids that have no record, or a record of another client, instead.
+ */
+pub(crate) struct TransactionStore<M> {
+    records: Arena<TransactionRecord<M>>,
+    by_client: HashMap<u16, Vec<u32>>,
+    ids: Vec<u32>,
+}
+
+impl<M: Money> TransactionStore<M> {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        TransactionStore {
+            records: Arena::with_capacity(capacity),
+            by_client: HashMap::new(),
+            ids: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn get(&self, id: u32) -> Option<&TransactionRecord<M>> {
+        self.records.get(id)
+    }
+
+    pub(crate) fn contains_key(&self, id: u32) -> bool {
+        self.records.contains_key(id)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /**
+     * Stores `record` under `id`, returning the record it replaces.
+     */
+    pub(crate) fn insert(
+        &mut self,
+        id: u32,
+        record: TransactionRecord<M>,
+    ) -> Option<TransactionRecord<M>> {
+        index(self.by_client.entry(record.0.client_id()).or_default(), id);
+        index(&mut self.ids, id);
+        self.records.insert(id, record)
+    }
+
+    pub(crate) fn remove(&mut self, id: u32) -> Option<TransactionRecord<M>> {
+        self.records.remove(id)
+    }
+
+    /**
+     * Every stored record, in no particular order.
+     */
+    pub(crate) fn values(&self) -> impl Iterator<Item = &TransactionRecord<M>> {
+        self.records.values()
+    }
+
+    /**
+     * The records stored for `client_id`, by transaction id.
+     */
+    pub(crate) fn for_client(&self, client_id: u16) -> impl Iterator<Item = &TransactionRecord<M>> {
+        self.starting_at(0, Some(client_id))
+            .map(|(_, record)| record)
+    }
+
+    /**
+     * The records with an id of at least `from`, by transaction id; only
+     * those stored for `client_id` if given.
+     */
+    pub(crate) fn starting_at(
+        &self,
+        from: u32,
+        client_id: Option<u16>,
+    ) -> impl Iterator<Item = (u32, &TransactionRecord<M>)> {
+        let ids = match client_id {
+            Some(client_id) => self
+                .by_client
+                .get(&client_id)
+                .map_or(&[][..], Vec::as_slice),
+            None => &self.ids,
+        };
+        let start = ids.partition_point(|&id| id < from);
+        ids[start..].iter().filter_map(move |&id| {
+            let record = self.records.get(id)?;
+            client_id
+                .is_none_or(|client_id| record.0.client_id() == client_id)
+                .then_some((id, record))
+        })
+    }
+}
+
+/**
+ * Adds `id` to the sorted `ids` unless it is there already.
+ */
+fn index(ids: &mut Vec<u32>, id: u32) {
+    if ids.last().is_none_or(|&last| last < id) {
+        ids.push(id);
+    } else if let Err(position) = ids.binary_search(&id) {
+        ids.insert(position, id);
+    }
+}
+
+impl<M: Money> FromIterator<(u32, TransactionRecord<M>)> for TransactionStore<M> {
+    fn from_iter<I: IntoIterator<Item = (u32, TransactionRecord<M>)>>(iter: I) -> Self {
+        let mut store = TransactionStore::with_capacity(0);
+        for (id, record) in iter {
+            store.insert(id, record);
+        }
+        store
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{Transaction, TransactionStatus};
+
+    fn ids(store: &TransactionStore<f32>, client_id: u16) -> Vec<u32> {
+        store.for_client(client_id).map(|(tx, _)| tx.id()).collect()
+    }
+
+    #[test]
+    fn test_lookups_follow_inserts_and_removals() {
+        // GIVEN
+        let processed = |tx| (tx, TransactionStatus::Processed);
+        let mut store: TransactionStore<f32> = [
+            (3, processed(Transaction::deposit(1, 3, 1.0))),
+            (1, processed(Transaction::deposit(1, 1, 1.0))),
+            (2, processed(Transaction::deposit(2, 2, 1.0))),
+        ]
+        .into_iter()
+        .collect();
+
+        // WHEN
+        let taken = store.remove(1).unwrap();
+        let while_taken = ids(&store, 1);
+        store.insert(1, taken);
+        store.insert(3, processed(Transaction::deposit(2, 3, 1.0)));
+
+        // THEN
+        assert_eq!(while_taken, vec![3]);
+        assert_eq!(ids(&store, 1), vec![1]);
+        assert_eq!(ids(&store, 2), vec![2, 3]);
+        assert_eq!(ids(&store, 9), Vec::<u32>::new());
+        assert_eq!(
+            store
+                .starting_at(2, None)
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(
+            store
+                .starting_at(0, Some(2))
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+    }
+}
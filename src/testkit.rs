@@ -0,0 +1,170 @@
+//! Fixtures for testing code built on the engine: banks in known states,
+//! canned transaction sequences and assertions on the report. Sequences
+//! take the first transaction id to use, so several can be combined
+//! without clashing.
+use crate::bank::{Bank, BankBuilder, Transaction};
+use crate::money::Money;
+
+/**
+ * A bank with default policies where each `(client, amount)` pair was
+ * deposited, with transaction ids `1..=deposits.len()`.
+ */
+pub fn bank_with_deposits<M: Money>(deposits: &[(u16, M)]) -> Bank<M> {
+    let bank = BankBuilder::default().build();
+    bank.batch_process(
+        deposits
+            .iter()
+            .zip(1..)
+            .map(|(&(client_id, amount), id)| Transaction::deposit(client_id, id, amount))
+            .collect(),
+    )
+    .expect("fixture deposits are well-formed");
+    bank
+}
+
+/**
+ * `count` deposits of `amount`, each disputed right away, then alternately
+ * resolved and charged back. Ends with the client's account locked and
+ * `count / 2` of the deposits charged back. Uses `count` ids.
+ */
+pub fn dispute_storm<M: Money>(
+    client_id: u16,
+    first_id: u32,
+    count: u32,
+    amount: M,
+) -> Vec<Transaction<M>> {
+    let ids = first_id..first_id + count;
+    let mut transactions: Vec<_> = ids
+        .clone()
+        .flat_map(|id| {
+            [
+                Transaction::deposit(client_id, id, amount),
+                Transaction::dispute(client_id, id),
+            ]
+        })
+        .collect();
+    transactions.extend(ids.map(|id| {
+        if (id - first_id).is_multiple_of(2) {
+            Transaction::resolve(client_id, id)
+        } else {
+            Transaction::chargeback(client_id, id)
+        }
+    }));
+    transactions
+}
+
+/**
+ * The same deposit of `amount` with id `id`, `copies` times.
+ */
+pub fn duplicate_ids<M: Money>(
+    client_id: u16,
+    id: u32,
+    amount: M,
+    copies: usize,
+) -> Vec<Transaction<M>> {
+    vec![Transaction::deposit(client_id, id, amount); copies]
+}
+
+/**
+ * Deposits `amount` and charges it back, locking the account, then tries a
+ * deposit and a withdrawal of `amount` on it. Uses ids `first_id` to
+ * `first_id + 2`; whether the probes go through depends on the bank's
+ * `LockedAccountPolicy`.
+ */
+pub fn locked_account_probe<M: Money>(
+    client_id: u16,
+    first_id: u32,
+    amount: M,
+) -> Vec<Transaction<M>> {
+    vec![
+        Transaction::deposit(client_id, first_id, amount),
+        Transaction::dispute(client_id, first_id),
+        Transaction::chargeback(client_id, first_id),
+        Transaction::deposit(client_id, first_id + 1, amount),
+        Transaction::withdrawal(client_id, first_id + 2, amount),
+    ]
+}
+
+/**
+ * The account report as `Bank::write_report` writes it.
+ */
+pub fn report<M: Money>(bank: &Bank<M>) -> String {
+    let mut report = Vec::new();
+    bank.write_report(&mut report)
+        .expect("writing to memory succeeds");
+    String::from_utf8(report).expect("the report is UTF-8")
+}
+
+/**
+ * Asserts that the report has the same header and rows as `expected`, in
+ * any order, so expectations need not list clients by id.
+ * Surrounding whitespace of `expected` and its lines is ignored.
+ */
+#[track_caller]
+pub fn assert_report<M: Money>(bank: &Bank<M>, expected: &str) {
+    let sorted = |report: &str| {
+        let mut lines = report.trim().lines().map(str::trim);
+        let header = lines.next().map(String::from);
+        let mut rows: Vec<String> = lines.map(String::from).collect();
+        rows.sort();
+        (header, rows)
+    };
+    assert_eq!(sorted(&report(bank)), sorted(expected));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{LockedAccountPolicy, TxOutcome};
+    use crate::validation::{DuplicateTransaction, Rejection};
+
+    #[test]
+    fn test_bank_with_deposits_and_dispute_storm() {
+        // GIVEN
+        let bank = bank_with_deposits(&[(1, 10.0), (2, 3.0)]);
+
+        // WHEN
+        bank.batch_process(dispute_storm(2, 10, 4, 1.0)).unwrap();
+
+        // THEN
+        assert_report(
+            &bank,
+            "client,available,held,total,locked,frozen
+             2,5.0000,0.0000,5.0000,true,false
+             1,10.0000,0.0000,10.0000,false,false",
+        );
+    }
+
+    #[test]
+    fn test_duplicate_ids_and_locked_account_probe() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .validator(DuplicateTransaction)
+            .locked_account_policy(LockedAccountPolicy::BlockAll)
+            .build();
+        let mut outcomes = Vec::new();
+        let mut transactions = duplicate_ids(1, 1, 2.0, 2);
+        transactions.extend(locked_account_probe(1, 2, 1.0));
+
+        // WHEN
+        bank.batch_process_with(transactions, |_, outcome| outcomes.push(outcome))
+            .unwrap();
+
+        // THEN
+        assert_eq!(
+            outcomes[1],
+            TxOutcome::Rejected(Rejection::DuplicateTransaction)
+        );
+        assert_eq!(
+            outcomes[5..],
+            [
+                TxOutcome::Rejected(Rejection::AccountLocked),
+                TxOutcome::Rejected(Rejection::AccountLocked)
+            ]
+        );
+        assert_report(
+            &bank,
+            "client,available,held,total,locked,frozen\n1,2.0000,0.0000,2.0000,true,false\n",
+        );
+    }
+}
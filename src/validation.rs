@@ -0,0 +1,300 @@
+use crate::bank::{AccountView, Transaction, TransactionType, TxHistory};
+use crate::error::ErrorCode;
+use crate::money::Money;
+use rust_decimal::Decimal;
+use std::fmt;
+
+/**
+ * Why a validator refused a transaction.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Rejection {
+    AmountLimit,
+    AccountLocked,
+    AccountFrozen,
+    DuplicateTransaction,
+    KycWithdrawal,
+    KycDepositCap,
+    Denylisted,
+    /// A direct debit without a mandate of the client covering it.
+    NoMandate,
+    /// An amount that is zero, negative or not a number.
+    NonPositiveAmount,
+    /// Not a deposit or withdrawal, in a batch given to `Bank::stage_batch`.
+    NotStageable,
+    /// Refused by the rule at this position (from 1) in the rules file.
+    Rule(usize),
+    Custom(&'static str),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::AmountLimit => write!(f, "amount exceeds the per-transaction limit"),
+            Rejection::AccountLocked => write!(f, "account is locked"),
+            Rejection::AccountFrozen => write!(f, "account is frozen"),
+            Rejection::DuplicateTransaction => write!(f, "transaction id was already used"),
+            Rejection::KycWithdrawal => {
+                write!(f, "client is not KYC verified, withdrawals are disabled")
+            }
+            Rejection::KycDepositCap => write!(
+                f,
+                "client is not KYC verified, deposit would exceed the unverified balance cap"
+            ),
+            Rejection::Denylisted => write!(f, "client is on the denylist"),
+            Rejection::NoMandate => write!(f, "no mandate authorizes the direct debit"),
+            Rejection::NonPositiveAmount => write!(f, "amount is not positive"),
+            Rejection::NotStageable => write!(f, "only deposits and withdrawals can be staged"),
+            Rejection::Rule(position) => write!(f, "refused by rule #{}", position),
+            Rejection::Custom(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl Rejection {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Rejection::AmountLimit => ErrorCode::AmountLimit,
+            Rejection::AccountLocked => ErrorCode::AccountLocked,
+            Rejection::AccountFrozen => ErrorCode::AccountFrozen,
+            Rejection::DuplicateTransaction => ErrorCode::DuplicateTransaction,
+            Rejection::KycWithdrawal => ErrorCode::KycWithdrawal,
+            Rejection::KycDepositCap => ErrorCode::KycDepositCap,
+            Rejection::Denylisted => ErrorCode::Denylisted,
+            Rejection::NoMandate => ErrorCode::NoMandate,
+            Rejection::NonPositiveAmount => ErrorCode::NonPositiveAmount,
+            Rejection::NotStageable => ErrorCode::NotStageable,
+            Rejection::Rule(_) => ErrorCode::RuleRejected,
+            Rejection::Custom(_) => ErrorCode::CustomRejected,
+        }
+    }
+}
+
+/**
+ * Checked before a transaction touches any balance. Validators see the
+ * account as it is before the transaction and every stored transaction, and
+ * may refuse the transaction, in which case nothing is applied.
+ */
+pub trait TxValidator<M: Money = Decimal> {
+    fn validate(
+        &self,
+        tx: &Transaction<M>,
+        account: &AccountView<M>,
+        history: &TxHistory<M>,
+    ) -> Result<(), Rejection>;
+
+    /**
+     * Whether an accepted deposit or withdrawal should be held as pending
+     * until it is approved or declined. Defaults to never.
+     */
+    fn needs_review(
+        &self,
+        _tx: &Transaction<M>,
+        _account: &AccountView<M>,
+        _history: &TxHistory<M>,
+    ) -> bool {
+        false
+    }
+}
+
+/**
+ * Refuses deposits and withdrawals larger than `max_amount`.
+ */
+pub struct AmountLimit<M = Decimal> {
+    pub max_amount: M,
+}
+
+impl<M: Money> TxValidator<M> for AmountLimit<M> {
+    fn validate(
+        &self,
+        tx: &Transaction<M>,
+        _: &AccountView<M>,
+        _: &TxHistory<M>,
+    ) -> Result<(), Rejection> {
+        match tx.amount() {
+            Some(amount) if amount > self.max_amount => Err(Rejection::AmountLimit),
+            _ => Ok(()),
+        }
+    }
+}
+
+/**
+ * Refuses deposits and withdrawals on accounts locked by a chargeback.
+ * Disputes, resolves and chargebacks still go through.
+ */
+pub struct LockedAccount;
+
+impl<M: Money> TxValidator<M> for LockedAccount {
+    fn validate(
+        &self,
+        tx: &Transaction<M>,
+        account: &AccountView<M>,
+        _: &TxHistory<M>,
+    ) -> Result<(), Rejection> {
+        if account.locked && is_funds_movement(tx) {
+            Err(Rejection::AccountLocked)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/**
+ * Refuses deposits and withdrawals whose id is already in the history.
+ */
+pub struct DuplicateTransaction;
+
+impl<M: Money> TxValidator<M> for DuplicateTransaction {
+    fn validate(
+        &self,
+        tx: &Transaction<M>,
+        _: &AccountView<M>,
+        history: &TxHistory<M>,
+    ) -> Result<(), Rejection> {
+        if is_funds_movement(tx) && history.contains(tx.id()) {
+            Err(Rejection::DuplicateTransaction)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn is_funds_movement<M: Money>(tx: &Transaction<M>) -> bool {
+    matches!(
+        tx.tx_type(),
+        TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::DirectDebit
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{Bank, BankBuilder, TxOutcome};
+
+    fn process_all(bank: &Bank<f32>, transactions: Vec<Transaction<f32>>) -> Vec<TxOutcome> {
+        let mut outcomes = Vec::new();
+        bank.batch_process_with(transactions, |_, outcome| outcomes.push(outcome))
+            .unwrap();
+        outcomes
+    }
+
+    #[test]
+    fn test_amount_limit_rejects_large_amounts() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .validator(AmountLimit { max_amount: 100.0 })
+            .build();
+
+        // WHEN
+        let outcomes = process_all(
+            &bank,
+            vec![
+                Transaction::deposit(1, 1, 100.0),
+                Transaction::deposit(1, 2, 100.5),
+            ],
+        );
+
+        // THEN
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Applied,
+                TxOutcome::Rejected(Rejection::AmountLimit)
+            ]
+        );
+        assert_eq!(bank.account(1).unwrap().total, 100.0);
+    }
+
+    #[test]
+    fn test_locked_account_rejects_deposits_after_chargeback() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .validator(LockedAccount)
+            .build();
+
+        // WHEN
+        let outcomes = process_all(
+            &bank,
+            vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::dispute(1, 1),
+                Transaction::chargeback(1, 1),
+                Transaction::deposit(1, 2, 5.0),
+            ],
+        );
+
+        // THEN
+        assert_eq!(outcomes[3], TxOutcome::Rejected(Rejection::AccountLocked));
+        assert_eq!(bank.account(1).unwrap().total, 0.0);
+    }
+
+    #[test]
+    fn test_duplicate_transaction_rejects_reused_id() {
+        // GIVEN
+        let bank = BankBuilder::<f32>::default()
+            .validator(DuplicateTransaction)
+            .build();
+
+        // WHEN
+        let outcomes = process_all(
+            &bank,
+            vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::dispute(1, 1),
+            ],
+        );
+
+        // THEN
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Applied,
+                TxOutcome::Rejected(Rejection::DuplicateTransaction),
+                TxOutcome::Applied
+            ]
+        );
+        assert_eq!(bank.account(1).unwrap().total, 10.0);
+    }
+
+    #[test]
+    fn test_custom_validator_sees_account_and_history() {
+        // GIVEN
+        struct NoWithdrawalWhileDisputed;
+        impl TxValidator<f32> for NoWithdrawalWhileDisputed {
+            fn validate(
+                &self,
+                tx: &Transaction<f32>,
+                account: &AccountView<f32>,
+                history: &TxHistory<f32>,
+            ) -> Result<(), Rejection> {
+                let has_dispute = account.held > 0.0 && history.is_disputed(1);
+                if tx.tx_type() == TransactionType::Withdrawal && has_dispute {
+                    Err(Rejection::Custom("open dispute on account"))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+        let bank = BankBuilder::<f32>::default()
+            .validator(NoWithdrawalWhileDisputed)
+            .build();
+
+        // WHEN
+        let outcomes = process_all(
+            &bank,
+            vec![
+                Transaction::deposit(1, 1, 10.0),
+                Transaction::deposit(1, 2, 10.0),
+                Transaction::dispute(1, 1),
+                Transaction::withdrawal(1, 3, 5.0),
+            ],
+        );
+
+        // THEN
+        assert_eq!(
+            outcomes[3],
+            TxOutcome::Rejected(Rejection::Custom("open dispute on account"))
+        );
+    }
+}